@@ -1,253 +1,6102 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{self};
+use std::mem;
+use std::panic;
+use std::thread;
 
-type Environment = HashMap<String, Expr>;
+#[path = "common/environment.rs"]
+mod environment;
 
-#[derive(PartialEq, Eq, Clone)]
-enum Expr {
+use environment::{UndefinedVariable, Value};
+
+pub(crate) type Environment = environment::Environment<Expr>;
+
+#[derive(PartialEq, Eq, Hash, Clone)]
+#[allow(dead_code, clippy::enum_variant_names)]
+pub(crate) enum Expr {
     Number(i64),
     Boolean(bool),
     Variable(String),
     Add(Box<Expr>, Box<Expr>),
+    Subtract(Box<Expr>, Box<Expr>),
     Multiply(Box<Expr>, Box<Expr>),
+    Divide(Box<Expr>, Box<Expr>),
+    Modulo(Box<Expr>, Box<Expr>),
     LessThan(Box<Expr>, Box<Expr>),
+    GreaterThan(Box<Expr>, Box<Expr>),
+    LessThanOrEqual(Box<Expr>, Box<Expr>),
+    GreaterThanOrEqual(Box<Expr>, Box<Expr>),
+    Equal(Box<Expr>, Box<Expr>),
+    NotEqual(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    AssignExpr(String, Box<Expr>),
+    PreIncrement(String),
+    PostIncrement(String),
 }
 
+#[allow(dead_code)]
 impl Expr {
-    fn evalute(&self, env: &Environment) -> Self {
-        match self {
-            Self::Number(_) => self.clone(),
+    /// Evaluates operands left to right; assignment and increment
+    /// expressions write their result back into `env`.
+    fn evalute(&self, env: &mut Environment) -> Self {
+        self.evalute_in(env, &Checked)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    fn evalute_in<N: NumericSemantics>(
+        &self,
+        env: &mut Environment,
+        semantics: &N,
+    ) -> Result<Self, EvalError> {
+        Ok(match self {
+            Self::Number(n) => Self::Number(semantics.literal(*n)),
             Self::Boolean(_) => self.clone(),
-            Self::Variable(name) => env[name].clone(),
-            Self::Add(l, r) => match (l.evalute(env), r.evalute(env)) {
-                (Self::Number(a), Self::Number(b)) => Self::Number(a + b),
-                _ => panic!("invalid expr"),
+            Self::Variable(name) => env.get(name)?.clone(),
+            Self::AssignExpr(name, expr) => {
+                let value = expr.evalute_in(env, semantics)?;
+                env.set(name, value.clone());
+                value
+            }
+            Self::PreIncrement(name) | Self::PostIncrement(name) => match *env.get(name)? {
+                Self::Number(n) => {
+                    let incremented = semantics.add(n, 1)?;
+                    env.set(name, Self::Number(incremented));
+                    match self {
+                        Self::PreIncrement(_) => Self::Number(incremented),
+                        _ => Self::Number(n),
+                    }
+                }
+                _ => return Err(EvalError::TypeMismatch(self.clone())),
             },
-            Self::Multiply(l, r) => match (l.evalute(env), r.evalute(env)) {
-                (Self::Number(a), Self::Number(b)) => Self::Number(a * b),
-                _ => panic!("invalid expr"),
+            Self::Add(l, r) => match (l.evalute_in(env, semantics)?, r.evalute_in(env, semantics)?)
+            {
+                (Self::Number(a), Self::Number(b)) => Self::Number(semantics.add(a, b)?),
+                _ => return Err(EvalError::TypeMismatch(self.clone())),
             },
-            Self::LessThan(l, r) => match (l.evalute(env), r.evalute(env)) {
-                (Self::Number(a), Self::Number(b)) => Self::Boolean(a < b),
-                _ => panic!("invalid expr"),
+            Self::Subtract(l, r) => {
+                match (l.evalute_in(env, semantics)?, r.evalute_in(env, semantics)?) {
+                    (Self::Number(a), Self::Number(b)) => Self::Number(semantics.subtract(a, b)?),
+                    _ => return Err(EvalError::TypeMismatch(self.clone())),
+                }
+            }
+            Self::Multiply(l, r) => {
+                match (l.evalute_in(env, semantics)?, r.evalute_in(env, semantics)?) {
+                    (Self::Number(a), Self::Number(b)) => Self::Number(semantics.multiply(a, b)?),
+                    _ => return Err(EvalError::TypeMismatch(self.clone())),
+                }
+            }
+            Self::Divide(l, r) => {
+                match (l.evalute_in(env, semantics)?, r.evalute_in(env, semantics)?) {
+                    (Self::Number(_), Self::Number(0)) => {
+                        return Err(EvalError::DivisionByZero(self.clone()))
+                    }
+                    (Self::Number(a), Self::Number(b)) => Self::Number(semantics.divide(a, b)?),
+                    _ => return Err(EvalError::TypeMismatch(self.clone())),
+                }
+            }
+            Self::Modulo(l, r) => {
+                match (l.evalute_in(env, semantics)?, r.evalute_in(env, semantics)?) {
+                    (Self::Number(_), Self::Number(0)) => {
+                        return Err(EvalError::DivisionByZero(self.clone()))
+                    }
+                    (Self::Number(a), Self::Number(b)) => Self::Number(semantics.remainder(a, b)?),
+                    _ => return Err(EvalError::TypeMismatch(self.clone())),
+                }
+            }
+            Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r) => {
+                match (l.evalute_in(env, semantics)?, r.evalute_in(env, semantics)?) {
+                    (Self::Number(a), Self::Number(b)) => Self::Boolean(self.compare(a, b)),
+                    _ => return Err(EvalError::TypeMismatch(self.clone())),
+                }
+            }
+            Self::Equal(l, r) | Self::NotEqual(l, r) => {
+                let equal = match (l.evalute_in(env, semantics)?, r.evalute_in(env, semantics)?) {
+                    (Self::Number(a), Self::Number(b)) => a == b,
+                    (Self::Boolean(a), Self::Boolean(b)) => a == b,
+                    _ => return Err(EvalError::TypeMismatch(self.clone())),
+                };
+                Self::Boolean(equal == matches!(self, Self::Equal(..)))
+            }
+            // The right operand is only evaluated when the left one does
+            // not decide the result: `false && e` and `true || e`.
+            Self::And(l, r) | Self::Or(l, r) => {
+                let decisive = matches!(self, Self::Or(..));
+                match l.evalute_in(env, semantics)? {
+                    Self::Boolean(b) if b == decisive => Self::Boolean(b),
+                    Self::Boolean(_) => match r.evalute_in(env, semantics)? {
+                        value @ Self::Boolean(_) => value,
+                        _ => return Err(EvalError::TypeMismatch(self.clone())),
+                    },
+                    _ => return Err(EvalError::TypeMismatch(self.clone())),
+                }
+            }
+            Self::Not(expr) => match expr.evalute_in(env, semantics)? {
+                Self::Boolean(b) => Self::Boolean(!b),
+                _ => return Err(EvalError::TypeMismatch(self.clone())),
             },
+        })
+    }
+
+    /// The type `self` evaluates to, looking up variables in `env`.
+    fn type_of(&self, env: &Environment) -> Result<Type, String> {
+        let numbers = |operands: &[&Expr], result| {
+            for operand in operands {
+                if operand.type_of(env)? != Type::Number {
+                    return Err(format!("`{}` is not a number in `{}`", operand, self));
+                }
+            }
+            Ok(result)
+        };
+        match self {
+            Self::Number(_) => Ok(Type::Number),
+            Self::Boolean(_) => Ok(Type::Boolean),
+            Self::Variable(name) => env
+                .get(name)
+                .map_err(|error| error.to_string())?
+                .type_of(env),
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r) => numbers(&[l, r], Type::Number),
+            Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r) => numbers(&[l, r], Type::Boolean),
+            Self::Equal(l, r) | Self::NotEqual(l, r) => {
+                if l.type_of(env)? != r.type_of(env)? {
+                    return Err(format!(
+                        "`{}` and `{}` have different types in `{}`",
+                        l, r, self
+                    ));
+                }
+                Ok(Type::Boolean)
+            }
+            Self::And(l, r) | Self::Or(l, r) => {
+                for operand in [l, r] {
+                    if operand.type_of(env)? != Type::Boolean {
+                        return Err(format!("`{}` is not a boolean in `{}`", operand, self));
+                    }
+                }
+                Ok(Type::Boolean)
+            }
+            Self::Not(expr) => {
+                if expr.type_of(env)? != Type::Boolean {
+                    return Err(format!("`{}` is not a boolean in `{}`", expr, self));
+                }
+                Ok(Type::Boolean)
+            }
+            Self::AssignExpr(_, expr) => expr.type_of(env),
+            Self::PreIncrement(name) | Self::PostIncrement(name) => {
+                numbers(&[&Self::Variable(name.clone())], Type::Number)
+            }
         }
     }
-}
 
-impl fmt::Display for Expr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+    /// Parses a formula built from numbers, `true`, `false`, variables,
+    /// `+`, `-`, `*`, `/`, `%`, `<`, `>`, `<=`, `>=`, `==`, `!=`, `&&`, `||`,
+    /// `!` and parentheses, with the usual precedence.
+    fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = Parser { source, pos: 0 };
+        let expr = parser.or()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(expr),
+            Some(c) => Err(format!("unexpected `{}` at {}", c, parser.pos)),
+        }
+    }
+
+    /// Whether a comparison holds between the numbers `a` and `b`.
+    fn compare(&self, a: i64, b: i64) -> bool {
         match self {
-            Self::Number(n) => write!(f, "{}", n),
-            Self::Add(l, r) => write!(f, "{} + {}", l, r),
-            Self::Multiply(l, r) => write!(f, "{} * {}", l, r),
-            Self::Boolean(b) => write!(f, "{}", b),
-            Self::LessThan(l, r) => write!(f, "{} < {}", l, r),
-            Self::Variable(name) => write!(f, "{}", name),
+            Self::LessThan(..) => a < b,
+            Self::GreaterThan(..) => a > b,
+            Self::LessThanOrEqual(..) => a <= b,
+            Self::GreaterThanOrEqual(..) => a >= b,
+            _ => panic!("`compare()` not supported"),
         }
     }
-}
 
-impl fmt::Debug for Expr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        write!(f, "<<{}>>", self)
+    fn is_value(&self) -> bool {
+        matches!(self, Self::Number(_) | Self::Boolean(_))
     }
-}
 
-#[derive(PartialEq, Eq, Clone)]
-enum Stmt {
-    DoNothing,
-    Assign(String, Expr),
-    If {
-        condition: Expr,
-        consequence: Box<Stmt>,
-        alternative: Box<Stmt>,
-    },
-    Sequence {
-        first: Box<Stmt>,
-        second: Box<Stmt>,
-    },
-    While {
-        condition: Expr,
-        body: Box<Stmt>,
-    },
-}
+    /// Whether `self` has two operands, which `!` puts in parentheses.
+    fn is_binary(&self) -> bool {
+        !matches!(
+            self,
+            Self::Number(_)
+                | Self::Boolean(_)
+                | Self::Variable(_)
+                | Self::AssignExpr(..)
+                | Self::PreIncrement(_)
+                | Self::PostIncrement(_)
+                | Self::Not(_)
+        )
+    }
 
-impl Stmt {
-    fn evalute(&self, mut env: Environment) -> Environment {
+    /// Whether evaluating `self` can fail other than by overflowing: it
+    /// divides, which may be by zero, or reads a variable, which may be
+    /// unbound.
+    fn can_fail(&self) -> bool {
+        self.subexprs().any(|expr| {
+            matches!(
+                expr,
+                Self::Divide(..) | Self::Modulo(..) | Self::Variable(_)
+            )
+        })
+    }
+
+    fn has_side_effects(&self) -> bool {
         match self {
-            Self::DoNothing => env,
-            Self::Assign(name, expr) => {
-                env.insert(name.into(), expr.evalute(&env));
-                env
+            Self::Number(_) | Self::Boolean(_) | Self::Variable(_) => false,
+            Self::AssignExpr(..) | Self::PreIncrement(_) | Self::PostIncrement(_) => true,
+            Self::Not(expr) => expr.has_side_effects(),
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r)
+            | Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r)
+            | Self::Equal(l, r)
+            | Self::NotEqual(l, r)
+            | Self::And(l, r)
+            | Self::Or(l, r) => l.has_side_effects() || r.has_side_effects(),
+        }
+    }
+
+    fn assigns(&self, name: &str) -> bool {
+        match self {
+            Self::Number(_) | Self::Boolean(_) | Self::Variable(_) => false,
+            Self::AssignExpr(assigned, expr) => assigned == name || expr.assigns(name),
+            Self::PreIncrement(assigned) | Self::PostIncrement(assigned) => assigned == name,
+            Self::Not(expr) => expr.assigns(name),
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r)
+            | Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r)
+            | Self::Equal(l, r)
+            | Self::NotEqual(l, r)
+            | Self::And(l, r)
+            | Self::Or(l, r) => l.assigns(name) || r.assigns(name),
+        }
+    }
+
+    /// Expressions with side effects are left untouched, since folding
+    /// them would drop their writes.
+    fn specialize(&self, known: &Environment) -> Self {
+        if self.has_side_effects() {
+            return self.clone();
+        }
+        match self {
+            Self::Number(_) | Self::Boolean(_) => self.clone(),
+            Self::Variable(name) => known.get(name).cloned().unwrap_or_else(|_| self.clone()),
+            Self::Add(l, r) => {
+                Self::Add(l.specialize(known).into(), r.specialize(known).into()).fold()
+            }
+            Self::Subtract(l, r) => {
+                Self::Subtract(l.specialize(known).into(), r.specialize(known).into()).fold()
+            }
+            Self::Multiply(l, r) => {
+                Self::Multiply(l.specialize(known).into(), r.specialize(known).into()).fold()
+            }
+            Self::Divide(l, r) => {
+                Self::Divide(l.specialize(known).into(), r.specialize(known).into()).fold()
+            }
+            Self::Modulo(l, r) => {
+                Self::Modulo(l.specialize(known).into(), r.specialize(known).into()).fold()
+            }
+            Self::LessThan(l, r) => {
+                Self::LessThan(l.specialize(known).into(), r.specialize(known).into()).fold()
+            }
+            Self::GreaterThan(l, r) => {
+                Self::GreaterThan(l.specialize(known).into(), r.specialize(known).into()).fold()
+            }
+            Self::LessThanOrEqual(l, r) => {
+                Self::LessThanOrEqual(l.specialize(known).into(), r.specialize(known).into()).fold()
+            }
+            Self::GreaterThanOrEqual(l, r) => {
+                Self::GreaterThanOrEqual(l.specialize(known).into(), r.specialize(known).into())
+                    .fold()
+            }
+            Self::Equal(l, r) => {
+                Self::Equal(l.specialize(known).into(), r.specialize(known).into()).fold()
+            }
+            Self::NotEqual(l, r) => {
+                Self::NotEqual(l.specialize(known).into(), r.specialize(known).into()).fold()
+            }
+            Self::And(l, r) => {
+                Self::And(l.specialize(known).into(), r.specialize(known).into()).fold()
+            }
+            Self::Or(l, r) => {
+                Self::Or(l.specialize(known).into(), r.specialize(known).into()).fold()
+            }
+            Self::Not(expr) => Self::Not(expr.specialize(known).into()).fold(),
+            Self::AssignExpr(..) | Self::PreIncrement(_) | Self::PostIncrement(_) => {
+                unreachable!()
             }
-            Self::If {
-                condition,
-                consequence,
-                alternative,
-            } => match condition.evalute(&env) {
-                Expr::Boolean(true) => consequence.evalute(env),
-                Expr::Boolean(false) => alternative.evalute(env),
-                _ => panic!("invalid condition"),
-            },
-            Self::Sequence { first, second } => second.evalute(first.evalute(env)),
-            Self::While { condition, body } => match condition.evalute(&env) {
-                Expr::Boolean(true) => self.evalute(body.evalute(env)),
-                Expr::Boolean(false) => env,
-                _ => panic!("invalid condition"),
-            },
         }
     }
-}
 
-impl fmt::Display for Stmt {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+    /// Replaces every variable found in `names`, keeping the rest.
+    fn rename(&self, names: &HashMap<String, String>) -> Self {
+        let name = |name: &String| names.get(name).unwrap_or(name).clone();
         match self {
-            Self::DoNothing => write!(f, "do-nothing"),
-            Self::Assign(name, expr) => write!(f, "{} = {}", name, expr),
-            Self::If {
-                condition,
-                consequence,
-                alternative,
-            } => write!(
-                f,
-                "if ({}) {{ {} }} else {{ {} }}",
-                condition, consequence, alternative
-            ),
-            Self::Sequence { first, second } => write!(f, "{}; {}", first, second),
-            Self::While { condition, body } => write!(f, "while ({}) {{ {} }}", condition, body),
+            Self::Number(_) | Self::Boolean(_) => self.clone(),
+            Self::Variable(n) => Self::Variable(name(n)),
+            Self::Add(l, r) => Self::Add(l.rename(names).into(), r.rename(names).into()),
+            Self::Subtract(l, r) => Self::Subtract(l.rename(names).into(), r.rename(names).into()),
+            Self::Multiply(l, r) => Self::Multiply(l.rename(names).into(), r.rename(names).into()),
+            Self::Divide(l, r) => Self::Divide(l.rename(names).into(), r.rename(names).into()),
+            Self::Modulo(l, r) => Self::Modulo(l.rename(names).into(), r.rename(names).into()),
+            Self::LessThan(l, r) => Self::LessThan(l.rename(names).into(), r.rename(names).into()),
+            Self::GreaterThan(l, r) => {
+                Self::GreaterThan(l.rename(names).into(), r.rename(names).into())
+            }
+            Self::LessThanOrEqual(l, r) => {
+                Self::LessThanOrEqual(l.rename(names).into(), r.rename(names).into())
+            }
+            Self::GreaterThanOrEqual(l, r) => {
+                Self::GreaterThanOrEqual(l.rename(names).into(), r.rename(names).into())
+            }
+            Self::Equal(l, r) => Self::Equal(l.rename(names).into(), r.rename(names).into()),
+            Self::NotEqual(l, r) => Self::NotEqual(l.rename(names).into(), r.rename(names).into()),
+            Self::And(l, r) => Self::And(l.rename(names).into(), r.rename(names).into()),
+            Self::Or(l, r) => Self::Or(l.rename(names).into(), r.rename(names).into()),
+            Self::Not(expr) => Self::Not(expr.rename(names).into()),
+            Self::AssignExpr(n, e) => Self::AssignExpr(name(n), e.rename(names).into()),
+            Self::PreIncrement(n) => Self::PreIncrement(name(n)),
+            Self::PostIncrement(n) => Self::PostIncrement(name(n)),
         }
     }
-}
 
-impl fmt::Debug for Stmt {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        write!(f, "<<{}>>", self)
+    fn numbers(&self, numbers: &mut Vec<i64>) {
+        match self {
+            Self::Number(n) => numbers.push(*n),
+            Self::Boolean(_)
+            | Self::Variable(_)
+            | Self::PreIncrement(_)
+            | Self::PostIncrement(_) => {}
+            Self::AssignExpr(_, expr) | Self::Not(expr) => expr.numbers(numbers),
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r)
+            | Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r)
+            | Self::Equal(l, r)
+            | Self::NotEqual(l, r)
+            | Self::And(l, r)
+            | Self::Or(l, r) => {
+                l.numbers(numbers);
+                r.numbers(numbers);
+            }
+        }
+    }
+
+    fn children(&self) -> Vec<&Expr> {
+        match self {
+            Self::Number(_)
+            | Self::Boolean(_)
+            | Self::Variable(_)
+            | Self::PreIncrement(_)
+            | Self::PostIncrement(_) => vec![],
+            Self::AssignExpr(_, expr) | Self::Not(expr) => vec![expr],
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r)
+            | Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r)
+            | Self::Equal(l, r)
+            | Self::NotEqual(l, r)
+            | Self::And(l, r)
+            | Self::Or(l, r) => vec![l, r],
+        }
+    }
+
+    /// This expression and all of its subexpressions, in preorder.
+    fn subexprs(&self) -> impl Iterator<Item = &Expr> {
+        let mut nodes = vec![];
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            nodes.push(expr);
+            stack.extend(expr.children().into_iter().rev());
+        }
+        nodes.into_iter()
+    }
+
+    fn operator(&self) -> Option<&'static str> {
+        match self {
+            Self::Number(_) | Self::Boolean(_) | Self::Variable(_) => None,
+            Self::Add(..) => Some("+"),
+            Self::Subtract(..) => Some("-"),
+            Self::Multiply(..) => Some("*"),
+            Self::Divide(..) => Some("/"),
+            Self::Modulo(..) => Some("%"),
+            Self::LessThan(..) => Some("<"),
+            Self::GreaterThan(..) => Some(">"),
+            Self::LessThanOrEqual(..) => Some("<="),
+            Self::GreaterThanOrEqual(..) => Some(">="),
+            Self::Equal(..) => Some("=="),
+            Self::NotEqual(..) => Some("!="),
+            Self::And(..) => Some("&&"),
+            Self::Or(..) => Some("||"),
+            Self::Not(_) => Some("!"),
+            Self::AssignExpr(..) => Some("="),
+            Self::PreIncrement(_) | Self::PostIncrement(_) => Some("++"),
+        }
+    }
+
+    /// Number of nodes in the expression tree.
+    fn size(&self) -> usize {
+        self.subexprs().count()
+    }
+
+    fn depth(&self) -> usize {
+        1 + self
+            .children()
+            .into_iter()
+            .map(Expr::depth)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn count_ops(&self) -> HashMap<&'static str, usize> {
+        let mut counts = HashMap::new();
+        for op in self.subexprs().filter_map(Expr::operator) {
+            *counts.entry(op).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// One-step simplifications that keep the expression's type: numeric
+    /// operators shrink to an operand, comparisons to a boolean constant.
+    fn shrink_candidates(&self) -> Vec<Self> {
+        let mut candidates = vec![];
+        match self {
+            Self::Number(0) | Self::Boolean(_) | Self::Variable(_) => {}
+            Self::Number(n) => {
+                candidates.push(Self::Number(0));
+                if n / 2 != 0 {
+                    candidates.push(Self::Number(n / 2));
+                }
+            }
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r) => {
+                candidates.push(*l.clone());
+                candidates.push(*r.clone());
+            }
+            Self::LessThan(..)
+            | Self::GreaterThan(..)
+            | Self::LessThanOrEqual(..)
+            | Self::GreaterThanOrEqual(..)
+            | Self::Equal(..)
+            | Self::NotEqual(..)
+            | Self::And(..)
+            | Self::Or(..)
+            | Self::Not(_) => {
+                candidates.push(Self::Boolean(false));
+                candidates.push(Self::Boolean(true));
+            }
+            Self::AssignExpr(_, expr) => candidates.push(*expr.clone()),
+            Self::PreIncrement(name) | Self::PostIncrement(name) => {
+                candidates.push(Self::Variable(name.clone()))
+            }
+        }
+        match self {
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r)
+            | Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r)
+            | Self::Equal(l, r)
+            | Self::NotEqual(l, r)
+            | Self::And(l, r)
+            | Self::Or(l, r) => {
+                let rebuild = |l: Self, r: Self| match self {
+                    Self::Add(..) => Self::Add(l.into(), r.into()),
+                    Self::Subtract(..) => Self::Subtract(l.into(), r.into()),
+                    Self::Multiply(..) => Self::Multiply(l.into(), r.into()),
+                    Self::Divide(..) => Self::Divide(l.into(), r.into()),
+                    Self::Modulo(..) => Self::Modulo(l.into(), r.into()),
+                    Self::GreaterThan(..) => Self::GreaterThan(l.into(), r.into()),
+                    Self::LessThanOrEqual(..) => Self::LessThanOrEqual(l.into(), r.into()),
+                    Self::GreaterThanOrEqual(..) => Self::GreaterThanOrEqual(l.into(), r.into()),
+                    Self::Equal(..) => Self::Equal(l.into(), r.into()),
+                    Self::NotEqual(..) => Self::NotEqual(l.into(), r.into()),
+                    Self::And(..) => Self::And(l.into(), r.into()),
+                    Self::Or(..) => Self::Or(l.into(), r.into()),
+                    _ => Self::LessThan(l.into(), r.into()),
+                };
+                for l in l.shrink_candidates() {
+                    candidates.push(rebuild(l, *r.clone()));
+                }
+                for r in r.shrink_candidates() {
+                    candidates.push(rebuild(*l.clone(), r));
+                }
+            }
+            Self::AssignExpr(name, expr) => {
+                for expr in expr.shrink_candidates() {
+                    candidates.push(Self::AssignExpr(name.clone(), expr.into()));
+                }
+            }
+            Self::Not(expr) => {
+                for expr in expr.shrink_candidates() {
+                    candidates.push(Self::Not(expr.into()));
+                }
+            }
+            _ => {}
+        }
+        candidates
+    }
+
+    /// Every expression that differs from `self` in one operator or
+    /// literal: `+` and `*` swap, `-` becomes `+`, `/` becomes `*`, `%`
+    /// becomes `/`, `a < b` becomes `a >= b` (written `b < a + 1`), the
+    /// other comparisons become their negations (`==` and `!=` swap), `&&`
+    /// and `||` swap, `!` is dropped, numbers move by one and booleans flip.
+    fn mutants(&self) -> Vec<Self> {
+        let mut mutants = vec![];
+        match self {
+            Self::Number(n) => {
+                mutants.extend(n.checked_add(1).map(Self::Number));
+                mutants.extend(n.checked_sub(1).map(Self::Number));
+            }
+            Self::Boolean(b) => mutants.push(Self::Boolean(!b)),
+            Self::Add(l, r) => mutants.push(Self::Multiply(l.clone(), r.clone())),
+            Self::Subtract(l, r) | Self::Multiply(l, r) => {
+                mutants.push(Self::Add(l.clone(), r.clone()))
+            }
+            Self::Divide(l, r) => mutants.push(Self::Multiply(l.clone(), r.clone())),
+            Self::Modulo(l, r) => mutants.push(Self::Divide(l.clone(), r.clone())),
+            Self::LessThan(l, r) => mutants.push(Self::LessThan(
+                r.clone(),
+                Self::Add(l.clone(), Self::Number(1).into()).into(),
+            )),
+            Self::GreaterThan(l, r) => mutants.push(Self::LessThanOrEqual(l.clone(), r.clone())),
+            Self::LessThanOrEqual(l, r) => mutants.push(Self::GreaterThan(l.clone(), r.clone())),
+            Self::GreaterThanOrEqual(l, r) => mutants.push(Self::LessThan(l.clone(), r.clone())),
+            Self::Equal(l, r) => mutants.push(Self::NotEqual(l.clone(), r.clone())),
+            Self::NotEqual(l, r) => mutants.push(Self::Equal(l.clone(), r.clone())),
+            Self::And(l, r) => mutants.push(Self::Or(l.clone(), r.clone())),
+            Self::Or(l, r) => mutants.push(Self::And(l.clone(), r.clone())),
+            Self::Not(expr) => mutants.push(*expr.clone()),
+            Self::Variable(_)
+            | Self::AssignExpr(..)
+            | Self::PreIncrement(_)
+            | Self::PostIncrement(_) => {}
+        }
+        match self {
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r)
+            | Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r)
+            | Self::Equal(l, r)
+            | Self::NotEqual(l, r)
+            | Self::And(l, r)
+            | Self::Or(l, r) => {
+                let rebuild = |l: Self, r: Self| match self {
+                    Self::Add(..) => Self::Add(l.into(), r.into()),
+                    Self::Subtract(..) => Self::Subtract(l.into(), r.into()),
+                    Self::Multiply(..) => Self::Multiply(l.into(), r.into()),
+                    Self::Divide(..) => Self::Divide(l.into(), r.into()),
+                    Self::Modulo(..) => Self::Modulo(l.into(), r.into()),
+                    Self::GreaterThan(..) => Self::GreaterThan(l.into(), r.into()),
+                    Self::LessThanOrEqual(..) => Self::LessThanOrEqual(l.into(), r.into()),
+                    Self::GreaterThanOrEqual(..) => Self::GreaterThanOrEqual(l.into(), r.into()),
+                    Self::Equal(..) => Self::Equal(l.into(), r.into()),
+                    Self::NotEqual(..) => Self::NotEqual(l.into(), r.into()),
+                    Self::And(..) => Self::And(l.into(), r.into()),
+                    Self::Or(..) => Self::Or(l.into(), r.into()),
+                    _ => Self::LessThan(l.into(), r.into()),
+                };
+                for l in l.mutants() {
+                    mutants.push(rebuild(l, *r.clone()));
+                }
+                for r in r.mutants() {
+                    mutants.push(rebuild(*l.clone(), r));
+                }
+            }
+            Self::AssignExpr(name, expr) => {
+                for expr in expr.mutants() {
+                    mutants.push(Self::AssignExpr(name.clone(), expr.into()));
+                }
+            }
+            Self::Not(expr) => {
+                for expr in expr.mutants() {
+                    mutants.push(Self::Not(expr.into()));
+                }
+            }
+            _ => {}
+        }
+        mutants
+    }
+
+    fn fold(self) -> Self {
+        match &self {
+            // A left operand that decides the result folds even when the
+            // right one is unknown, since the right one would not run.
+            Self::And(l, _) | Self::Or(l, _)
+                if **l == Self::Boolean(matches!(self, Self::Or(..))) =>
+            {
+                *l.clone()
+            }
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r)
+            | Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r)
+            | Self::Equal(l, r)
+            | Self::NotEqual(l, r)
+            | Self::And(l, r)
+            | Self::Or(l, r)
+                if l.is_value() && r.is_value() =>
+            {
+                // An operation that fails, like a division by zero, is left
+                // in place to fail when the program runs.
+                self.evalute_in(&mut Environment::new(), &Checked)
+                    .unwrap_or(self)
+            }
+            Self::Not(expr) if expr.is_value() => self
+                .evalute_in(&mut Environment::new(), &Checked)
+                .unwrap_or(self),
+            _ => self,
+        }
     }
 }
 
-fn main() {
-    let env = HashMap::new();
-    println!("{}", Expr::Number(1).evalute(&env));
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub(crate) enum Type {
+    Number,
+    Boolean,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#[allow(dead_code)]
+struct Parser<'s> {
+    source: &'s str,
+    pos: usize,
+}
 
-    #[test]
-    fn evalute_number() {
-        let n = Expr::Number(23);
-        let env = HashMap::new();
-        assert_eq!(n, n.evalute(&env));
+#[allow(dead_code)]
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
     }
 
-    #[test]
-    fn evalute_boolean() {
-        let t = Expr::Boolean(true);
-        let mut env = HashMap::new();
-        assert_eq!(t, t.evalute(&mut env));
-
-        let f = Expr::Boolean(false);
-        let env = HashMap::new();
-        assert_eq!(f, f.evalute(&env));
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
     }
 
-    #[test]
-    fn evalute_add() {
-        let expr = Expr::Add(Expr::Number(1).into(), Expr::Number(2).into());
-        let env = HashMap::new();
-        assert_eq!(Expr::Number(3), expr.evalute(&env));
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
     }
 
-    #[test]
-    fn evalute_multiply() {
-        let expr = Expr::Multiply(Expr::Number(2).into(), Expr::Number(3).into());
-        let env = HashMap::new();
-        assert_eq!(Expr::Number(6), expr.evalute(&env));
+    /// Like `eat`, but without skipping whitespace first, for the second
+    /// character of an operator.
+    fn eat_directly(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
     }
 
-    #[test]
-    fn evalute_less_than() {
-        let expr = Expr::LessThan(
-            Expr::Add(Expr::Variable("x".into()).into(), Expr::Number(2).into()).into(),
-            Expr::Variable("y".into()).into(),
-        );
-        let mut env = HashMap::new();
-        env.insert("x".into(), Expr::Number(2));
-        env.insert("y".into(), Expr::Number(5));
-        assert_eq!(Expr::Boolean(true), expr.evalute(&env));
+    /// Eats `c` twice in a row, as in `&&`, after skipping whitespace.
+    fn eat_twice(&mut self, c: char) -> bool {
+        let start = self.pos;
+        if self.eat(c) && self.eat_directly(c) {
+            return true;
+        }
+        self.pos = start;
+        false
     }
 
-    #[test]
-    fn evalute_donothing() {
-        let stmt = Stmt::DoNothing;
-        let mut env = HashMap::new();
-        env.insert("x".into(), Expr::Number(2));
-        assert_eq!(env.clone(), stmt.evalute(env));
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat_twice('|') {
+            expr = Expr::Or(expr.into(), self.and()?.into());
+        }
+        Ok(expr)
     }
 
-    #[test]
-    fn evalute_assign() {
-        let stmt = Stmt::Assign("x".into(), Expr::Number(1));
-        let mut env = HashMap::new();
-        env.insert("y".into(), Expr::Number(2));
-        let mut expected = env.clone();
-        expected.insert("x".into(), Expr::Number(1));
-        assert_eq!(expected, stmt.evalute(env));
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.equality()?;
+        while self.eat_twice('&') {
+            expr = Expr::And(expr.into(), self.equality()?.into());
+        }
+        Ok(expr)
     }
 
-    #[test]
-    fn evalute_if() {
-        let stmt = Stmt::If {
-            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(3).into()),
-            consequence: Stmt::Assign(
-                "y".into(),
-                Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(2).into()).into(),
-            )
-            .into(),
-            alternative: Stmt::DoNothing.into(),
+    fn equality(&mut self) -> Result<Expr, String> {
+        let l = self.comparison()?;
+        let start = self.pos;
+        let equality: fn(Box<Expr>, Box<Expr>) -> Expr = if self.eat('=') {
+            Expr::Equal
+        } else if self.eat('!') {
+            Expr::NotEqual
+        } else {
+            return Ok(l);
         };
-        let mut env = HashMap::new();
-        env.insert("x".into(), Expr::Number(2));
-        let mut expected = env.clone();
-        expected.insert("y".into(), Expr::Number(4));
-        assert_eq!(expected, stmt.evalute(env));
+        if !self.eat_directly('=') {
+            self.pos = start;
+            return Ok(l);
+        }
+        Ok(equality(l.into(), self.comparison()?.into()))
     }
 
-    #[test]
-    fn evalute_sequence() {
-        let stmt = Stmt::Sequence {
-            first: Stmt::Assign("x".into(), Expr::Number(2).into()).into(),
-            second: Stmt::Assign(
-                "y".into(),
-                Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(2).into()).into(),
-            )
-            .into(),
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let l = self.sum()?;
+        let comparison: fn(Box<Expr>, Box<Expr>) -> Expr = if self.eat('<') {
+            if self.eat_directly('=') {
+                Expr::LessThanOrEqual
+            } else {
+                Expr::LessThan
+            }
+        } else if self.eat('>') {
+            if self.eat_directly('=') {
+                Expr::GreaterThanOrEqual
+            } else {
+                Expr::GreaterThan
+            }
+        } else {
+            return Ok(l);
         };
-        let env = HashMap::new();
-        let mut expected = env.clone();
-        expected.insert("x".into(), Expr::Number(2));
-        expected.insert("y".into(), Expr::Number(4));
-        assert_eq!(expected, stmt.evalute(env));
+        Ok(comparison(l.into(), self.sum()?.into()))
     }
 
-    #[test]
-    fn evalute_while() {
-        let stmt = Stmt::While {
-            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(5).into())
-                .into(),
-            body: Stmt::Assign(
-                "x".into(),
-                Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(3).into()).into(),
-            )
-            .into(),
-        };
-        let mut env = HashMap::new();
-        env.insert("x".into(), Expr::Number(1));
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        loop {
+            if self.eat('+') {
+                expr = Expr::Add(expr.into(), self.product()?.into());
+            } else if self.eat('-') {
+                expr = Expr::Subtract(expr.into(), self.product()?.into());
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
 
-        let mut expected = env.clone();
-        expected.insert("x".into(), Expr::Number(9));
-        assert_eq!(expected, stmt.evalute(env));
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.atom()?;
+        loop {
+            if self.eat('*') {
+                expr = Expr::Multiply(expr.into(), self.atom()?.into());
+            } else if self.eat('/') {
+                expr = Expr::Divide(expr.into(), self.atom()?.into());
+            } else if self.eat('%') {
+                expr = Expr::Modulo(expr.into(), self.atom()?.into());
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        if self.eat('!') {
+            return Ok(Expr::Not(self.atom()?.into()));
+        }
+        if self.eat('(') {
+            let expr = self.or()?;
+            if !self.eat(')') {
+                return Err(format!("expected `)` at {}", self.pos));
+            }
+            return Ok(expr);
+        }
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            self.pos += 1;
+        }
+        let word = &self.source[start..self.pos];
+        match word {
+            "" => Err(match self.peek() {
+                Some(c) => format!("unexpected `{}` at {}", c, start),
+                None => "unexpected end of input".into(),
+            }),
+            "true" => Ok(Expr::Boolean(true)),
+            "false" => Ok(Expr::Boolean(false)),
+            _ if word.starts_with(|c: char| c.is_ascii_digit()) => word
+                .parse()
+                .map(Expr::Number)
+                .map_err(|_| format!("invalid number `{}`", word)),
+            _ => Ok(Expr::Variable(word.into())),
+        }
+    }
+}
+
+/// Parses, type checks and evaluates a formula such as `1 + 2 * x` in one
+/// call. `env` is only read, so formulas cannot change it.
+#[allow(dead_code)]
+fn eval_str(source: &str, env: &Environment) -> Result<Expr, String> {
+    let expr = Expr::parse(source)?;
+    expr.type_of(env)?;
+    expr.evalute_in(&mut env.clone(), &Checked)
+        .map_err(|error| error.to_string())
+}
+
+/// Every side-effect-free expression of type `ty` at most `depth` levels
+/// deep, built from the literals 0, 1, `true` and `false`, the variables in
+/// `env` holding a value of that type, and the operators. The number of
+/// expressions grows doubly exponentially, so keep `depth` small.
+#[allow(dead_code)]
+pub(crate) fn synthesize(ty: Type, env: &Environment, depth: usize) -> Vec<Expr> {
+    if depth == 0 {
+        return vec![];
+    }
+    let mut exprs = match ty {
+        Type::Number => vec![Expr::Number(0), Expr::Number(1)],
+        Type::Boolean => vec![Expr::Boolean(true), Expr::Boolean(false)],
+    };
+    let mut variables: Vec<&String> = env
+        .iter()
+        .filter(|(_, value)| value.type_of(env) == Ok(ty))
+        .map(|(name, _)| name)
+        .collect();
+    variables.sort();
+    exprs.extend(
+        variables
+            .into_iter()
+            .map(|name| Expr::Variable(name.clone())),
+    );
+    let operands = synthesize(Type::Number, env, depth - 1);
+    for l in &operands {
+        for r in &operands {
+            let (l, r) = (Box::new(l.clone()), Box::new(r.clone()));
+            match ty {
+                Type::Number => {
+                    exprs.push(Expr::Add(l.clone(), r.clone()));
+                    exprs.push(Expr::Multiply(l, r));
+                }
+                Type::Boolean => exprs.push(Expr::LessThan(l, r)),
+            }
+        }
+    }
+    exprs
+}
+
+/// An algebraic identity, read left to right as a rewrite applied at the
+/// root of an expression. Laws only fire on side-effect-free operands, and
+/// they hold exactly under `Wrapping` arithmetic; under `Checked` a
+/// rewrite can move where an overflow happens. A law that drops an operand
+/// only fires when that operand is a number that cannot fail, so no
+/// rewrite hides a division by zero, an unbound variable or a type
+/// mismatch.
+#[allow(dead_code)]
+struct Law {
+    name: &'static str,
+    rewrite: fn(&Expr) -> Option<Expr>,
+}
+
+#[allow(dead_code)]
+const LAWS: &[Law] = &[
+    Law {
+        name: "add-commutative",
+        rewrite: |expr| match expr {
+            Expr::Add(a, b) => Some(Expr::Add(b.clone(), a.clone())),
+            _ => None,
+        },
+    },
+    Law {
+        name: "multiply-commutative",
+        rewrite: |expr| match expr {
+            Expr::Multiply(a, b) => Some(Expr::Multiply(b.clone(), a.clone())),
+            _ => None,
+        },
+    },
+    Law {
+        name: "add-associative",
+        rewrite: |expr| match expr {
+            Expr::Add(ab, c) => match ab.as_ref() {
+                Expr::Add(a, b) => {
+                    Some(Expr::Add(a.clone(), Expr::Add(b.clone(), c.clone()).into()))
+                }
+                _ => None,
+            },
+            _ => None,
+        },
+    },
+    Law {
+        name: "multiply-associative",
+        rewrite: |expr| match expr {
+            Expr::Multiply(ab, c) => match ab.as_ref() {
+                Expr::Multiply(a, b) => Some(Expr::Multiply(
+                    a.clone(),
+                    Expr::Multiply(b.clone(), c.clone()).into(),
+                )),
+                _ => None,
+            },
+            _ => None,
+        },
+    },
+    Law {
+        name: "distributive",
+        rewrite: |expr| match expr {
+            Expr::Multiply(a, bc) => match bc.as_ref() {
+                Expr::Add(b, c) => Some(Expr::Add(
+                    Expr::Multiply(a.clone(), b.clone()).into(),
+                    Expr::Multiply(a.clone(), c.clone()).into(),
+                )),
+                _ => None,
+            },
+            _ => None,
+        },
+    },
+    Law {
+        name: "factor",
+        rewrite: |expr| match expr {
+            Expr::Add(ab, ac) => match (ab.as_ref(), ac.as_ref()) {
+                (Expr::Multiply(a, b), Expr::Multiply(a2, c)) if a == a2 => Some(Expr::Multiply(
+                    a.clone(),
+                    Expr::Add(b.clone(), c.clone()).into(),
+                )),
+                _ => None,
+            },
+            _ => None,
+        },
+    },
+    Law {
+        name: "add-identity",
+        rewrite: |expr| match expr {
+            Expr::Add(a, zero) if **zero == Expr::Number(0) => Some(*a.clone()),
+            _ => None,
+        },
+    },
+    Law {
+        name: "multiply-identity",
+        rewrite: |expr| match expr {
+            Expr::Multiply(a, one) if **one == Expr::Number(1) => Some(*a.clone()),
+            _ => None,
+        },
+    },
+    Law {
+        name: "multiply-zero",
+        rewrite: |expr| match expr {
+            Expr::Multiply(a, zero)
+                if **zero == Expr::Number(0)
+                    && !a.can_fail()
+                    && a.type_of(&Environment::new()) == Ok(Type::Number) =>
+            {
+                Some(Expr::Number(0))
+            }
+            _ => None,
+        },
+    },
+];
+
+/// Every expression one law application away from `expr`, at any
+/// position, with the name of the law used.
+#[allow(dead_code)]
+fn rewrites(expr: &Expr) -> Vec<(&'static str, Expr)> {
+    if expr.has_side_effects() {
+        return vec![];
+    }
+    let mut results: Vec<_> = LAWS
+        .iter()
+        .filter_map(|law| (law.rewrite)(expr).map(|rewritten| (law.name, rewritten)))
+        .collect();
+    if let Expr::Add(l, r)
+    | Expr::Subtract(l, r)
+    | Expr::Multiply(l, r)
+    | Expr::Divide(l, r)
+    | Expr::Modulo(l, r)
+    | Expr::LessThan(l, r)
+    | Expr::GreaterThan(l, r)
+    | Expr::LessThanOrEqual(l, r)
+    | Expr::GreaterThanOrEqual(l, r)
+    | Expr::Equal(l, r)
+    | Expr::NotEqual(l, r)
+    | Expr::And(l, r)
+    | Expr::Or(l, r) = expr
+    {
+        let rebuild = |l: Expr, r: Expr| match expr {
+            Expr::Add(..) => Expr::Add(l.into(), r.into()),
+            Expr::Subtract(..) => Expr::Subtract(l.into(), r.into()),
+            Expr::Multiply(..) => Expr::Multiply(l.into(), r.into()),
+            Expr::Divide(..) => Expr::Divide(l.into(), r.into()),
+            Expr::Modulo(..) => Expr::Modulo(l.into(), r.into()),
+            Expr::GreaterThan(..) => Expr::GreaterThan(l.into(), r.into()),
+            Expr::LessThanOrEqual(..) => Expr::LessThanOrEqual(l.into(), r.into()),
+            Expr::GreaterThanOrEqual(..) => Expr::GreaterThanOrEqual(l.into(), r.into()),
+            Expr::Equal(..) => Expr::Equal(l.into(), r.into()),
+            Expr::NotEqual(..) => Expr::NotEqual(l.into(), r.into()),
+            Expr::And(..) => Expr::And(l.into(), r.into()),
+            Expr::Or(..) => Expr::Or(l.into(), r.into()),
+            _ => Expr::LessThan(l.into(), r.into()),
+        };
+        for (name, l) in rewrites(l) {
+            results.push((name, rebuild(l, *r.clone())));
+        }
+        for (name, r) in rewrites(r) {
+            results.push((name, rebuild(*l.clone(), r)));
+        }
+    }
+    if let Expr::Not(operand) = expr {
+        for (name, operand) in rewrites(operand) {
+            results.push((name, Expr::Not(operand.into())));
+        }
+    }
+    results
+}
+
+#[allow(dead_code)]
+type ClassId = usize;
+
+/// An expression node whose operands are equivalence classes rather than
+/// expressions.
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+enum ENode {
+    /// A number or boolean.
+    Leaf(Expr),
+    Variable(String),
+    Add(ClassId, ClassId),
+    Subtract(ClassId, ClassId),
+    Multiply(ClassId, ClassId),
+    Divide(ClassId, ClassId),
+    Modulo(ClassId, ClassId),
+    LessThan(ClassId, ClassId),
+    GreaterThan(ClassId, ClassId),
+    LessThanOrEqual(ClassId, ClassId),
+    GreaterThanOrEqual(ClassId, ClassId),
+    Equal(ClassId, ClassId),
+    NotEqual(ClassId, ClassId),
+    And(ClassId, ClassId),
+    Or(ClassId, ClassId),
+    Not(ClassId),
+}
+
+/// An e-graph: a set of expressions sharing their subterms, partitioned
+/// into classes of expressions known to be equal. Equality saturation
+/// keeps applying `LAWS` to every class until nothing new is learnt or a
+/// node limit is hit, then `extract` picks the smallest member of a class.
+#[allow(dead_code)]
+struct EGraph {
+    /// Union-find over class ids.
+    parents: Vec<ClassId>,
+    nodes: Vec<(ENode, ClassId)>,
+    memo: HashMap<ENode, ClassId>,
+}
+
+#[allow(dead_code)]
+impl EGraph {
+    fn new() -> Self {
+        Self {
+            parents: vec![],
+            nodes: vec![],
+            memo: HashMap::new(),
+        }
+    }
+
+    fn find(&self, mut id: ClassId) -> ClassId {
+        while self.parents[id] != id {
+            id = self.parents[id];
+        }
+        id
+    }
+
+    fn canonical(&self, node: &ENode) -> ENode {
+        match node {
+            ENode::Leaf(_) | ENode::Variable(_) => node.clone(),
+            ENode::Add(l, r) => ENode::Add(self.find(*l), self.find(*r)),
+            ENode::Subtract(l, r) => ENode::Subtract(self.find(*l), self.find(*r)),
+            ENode::Multiply(l, r) => ENode::Multiply(self.find(*l), self.find(*r)),
+            ENode::Divide(l, r) => ENode::Divide(self.find(*l), self.find(*r)),
+            ENode::Modulo(l, r) => ENode::Modulo(self.find(*l), self.find(*r)),
+            ENode::LessThan(l, r) => ENode::LessThan(self.find(*l), self.find(*r)),
+            ENode::GreaterThan(l, r) => ENode::GreaterThan(self.find(*l), self.find(*r)),
+            ENode::LessThanOrEqual(l, r) => ENode::LessThanOrEqual(self.find(*l), self.find(*r)),
+            ENode::GreaterThanOrEqual(l, r) => {
+                ENode::GreaterThanOrEqual(self.find(*l), self.find(*r))
+            }
+            ENode::Equal(l, r) => ENode::Equal(self.find(*l), self.find(*r)),
+            ENode::NotEqual(l, r) => ENode::NotEqual(self.find(*l), self.find(*r)),
+            ENode::And(l, r) => ENode::And(self.find(*l), self.find(*r)),
+            ENode::Or(l, r) => ENode::Or(self.find(*l), self.find(*r)),
+            ENode::Not(operand) => ENode::Not(self.find(*operand)),
+        }
+    }
+
+    fn add(&mut self, node: ENode) -> ClassId {
+        let node = self.canonical(&node);
+        if let Some(&id) = self.memo.get(&node) {
+            return self.find(id);
+        }
+        let id = self.parents.len();
+        self.parents.push(id);
+        self.nodes.push((node.clone(), id));
+        self.memo.insert(node, id);
+        id
+    }
+
+    /// Adds a pure expression.
+    fn add_expr(&mut self, expr: &Expr) -> ClassId {
+        self.add_term(expr, false)
+    }
+
+    /// Adds an expression a law built from one of `views`, in which every
+    /// variable is a `#n` placeholder for class `n`.
+    fn add_view(&mut self, view: &Expr) -> ClassId {
+        self.add_term(view, true)
+    }
+
+    fn add_term(&mut self, expr: &Expr, is_view: bool) -> ClassId {
+        if let (Expr::Variable(name), true) = (expr, is_view) {
+            return self.find(name[1..].parse().expect("invalid class placeholder"));
+        }
+        let mut add = |expr: &Expr| self.add_term(expr, is_view);
+        let node = match expr {
+            Expr::Variable(name) => ENode::Variable(name.clone()),
+            Expr::Add(l, r) => ENode::Add(add(l), add(r)),
+            Expr::Subtract(l, r) => ENode::Subtract(add(l), add(r)),
+            Expr::Multiply(l, r) => ENode::Multiply(add(l), add(r)),
+            Expr::Divide(l, r) => ENode::Divide(add(l), add(r)),
+            Expr::Modulo(l, r) => ENode::Modulo(add(l), add(r)),
+            Expr::LessThan(l, r) => ENode::LessThan(add(l), add(r)),
+            Expr::GreaterThan(l, r) => ENode::GreaterThan(add(l), add(r)),
+            Expr::LessThanOrEqual(l, r) => ENode::LessThanOrEqual(add(l), add(r)),
+            Expr::GreaterThanOrEqual(l, r) => ENode::GreaterThanOrEqual(add(l), add(r)),
+            Expr::Equal(l, r) => ENode::Equal(add(l), add(r)),
+            Expr::NotEqual(l, r) => ENode::NotEqual(add(l), add(r)),
+            Expr::And(l, r) => ENode::And(add(l), add(r)),
+            Expr::Or(l, r) => ENode::Or(add(l), add(r)),
+            Expr::Not(operand) => ENode::Not(add(operand)),
+            _ => ENode::Leaf(expr.clone()),
+        };
+        self.add(node)
+    }
+
+    fn union(&mut self, a: ClassId, b: ClassId) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        self.parents[a.max(b)] = a.min(b);
+        true
+    }
+
+    /// Restores the invariant that equal nodes live in one class: merging
+    /// two classes can make nodes above them equal too.
+    fn rebuild(&mut self) {
+        loop {
+            let mut merged = false;
+            let mut memo: HashMap<ENode, ClassId> = HashMap::new();
+            let mut nodes = vec![];
+            for (node, class) in std::mem::take(&mut self.nodes) {
+                let node = self.canonical(&node);
+                let class = self.find(class);
+                match memo.get(&node) {
+                    Some(&other) => merged |= self.union(other, class),
+                    None => {
+                        memo.insert(node.clone(), class);
+                        nodes.push((node, class));
+                    }
+                }
+            }
+            self.nodes = nodes;
+            self.memo = memo;
+            if !merged {
+                return;
+            }
+        }
+    }
+
+    fn classes(&self) -> HashMap<ClassId, Vec<ENode>> {
+        let mut classes: HashMap<ClassId, Vec<ENode>> = HashMap::new();
+        for (node, class) in &self.nodes {
+            classes
+                .entry(self.find(*class))
+                .or_default()
+                .push(self.canonical(node));
+        }
+        classes
+    }
+
+    /// `node` written as expressions two levels deep: each operand is
+    /// either its class, as a `#n` placeholder, or one of the class's
+    /// nodes with placeholders below it. That is as deep as any law looks.
+    /// Variables of the program only ever show up as their class, so every
+    /// variable in a view is a placeholder; see `add_view`.
+    fn views(node: &ENode, classes: &HashMap<ClassId, Vec<ENode>>) -> Vec<Expr> {
+        let placeholder = |id: ClassId| Expr::Variable(format!("#{}", id));
+        let shallow = |node: &ENode| match node {
+            ENode::Leaf(expr) => expr.clone(),
+            ENode::Variable(_) => unreachable!("variables are shown as their class"),
+            ENode::Add(l, r) => Expr::Add(placeholder(*l).into(), placeholder(*r).into()),
+            ENode::Subtract(l, r) => Expr::Subtract(placeholder(*l).into(), placeholder(*r).into()),
+            ENode::Multiply(l, r) => Expr::Multiply(placeholder(*l).into(), placeholder(*r).into()),
+            ENode::Divide(l, r) => Expr::Divide(placeholder(*l).into(), placeholder(*r).into()),
+            ENode::Modulo(l, r) => Expr::Modulo(placeholder(*l).into(), placeholder(*r).into()),
+            ENode::LessThan(l, r) => Expr::LessThan(placeholder(*l).into(), placeholder(*r).into()),
+            ENode::GreaterThan(l, r) => {
+                Expr::GreaterThan(placeholder(*l).into(), placeholder(*r).into())
+            }
+            ENode::LessThanOrEqual(l, r) => {
+                Expr::LessThanOrEqual(placeholder(*l).into(), placeholder(*r).into())
+            }
+            ENode::GreaterThanOrEqual(l, r) => {
+                Expr::GreaterThanOrEqual(placeholder(*l).into(), placeholder(*r).into())
+            }
+            ENode::Equal(l, r) => Expr::Equal(placeholder(*l).into(), placeholder(*r).into()),
+            ENode::NotEqual(l, r) => Expr::NotEqual(placeholder(*l).into(), placeholder(*r).into()),
+            ENode::And(l, r) => Expr::And(placeholder(*l).into(), placeholder(*r).into()),
+            ENode::Or(l, r) => Expr::Or(placeholder(*l).into(), placeholder(*r).into()),
+            ENode::Not(operand) => Expr::Not(placeholder(*operand).into()),
+        };
+        let operand = |id: ClassId| {
+            let mut exprs = vec![placeholder(id)];
+            exprs.extend(
+                classes[&id]
+                    .iter()
+                    .filter(|node| !matches!(node, ENode::Variable(_)))
+                    .map(shallow),
+            );
+            exprs
+        };
+        let (l, r) = match node {
+            ENode::Leaf(expr) => return vec![expr.clone()],
+            ENode::Variable(_) => return vec![],
+            ENode::Not(id) => {
+                return operand(*id)
+                    .into_iter()
+                    .map(|operand| Expr::Not(operand.into()))
+                    .collect()
+            }
+            ENode::Add(l, r)
+            | ENode::Subtract(l, r)
+            | ENode::Multiply(l, r)
+            | ENode::Divide(l, r)
+            | ENode::Modulo(l, r)
+            | ENode::LessThan(l, r)
+            | ENode::GreaterThan(l, r)
+            | ENode::LessThanOrEqual(l, r)
+            | ENode::GreaterThanOrEqual(l, r)
+            | ENode::Equal(l, r)
+            | ENode::NotEqual(l, r)
+            | ENode::And(l, r)
+            | ENode::Or(l, r) => (l, r),
+        };
+        let mut views = vec![];
+        for l in operand(*l) {
+            for r in operand(*r) {
+                let (l, r) = (Box::new(l.clone()), Box::new(r));
+                views.push(match node {
+                    ENode::Add(..) => Expr::Add(l, r),
+                    ENode::Subtract(..) => Expr::Subtract(l, r),
+                    ENode::Multiply(..) => Expr::Multiply(l, r),
+                    ENode::Divide(..) => Expr::Divide(l, r),
+                    ENode::Modulo(..) => Expr::Modulo(l, r),
+                    ENode::GreaterThan(..) => Expr::GreaterThan(l, r),
+                    ENode::LessThanOrEqual(..) => Expr::LessThanOrEqual(l, r),
+                    ENode::GreaterThanOrEqual(..) => Expr::GreaterThanOrEqual(l, r),
+                    ENode::Equal(..) => Expr::Equal(l, r),
+                    ENode::NotEqual(..) => Expr::NotEqual(l, r),
+                    ENode::And(..) => Expr::And(l, r),
+                    ENode::Or(..) => Expr::Or(l, r),
+                    _ => Expr::LessThan(l, r),
+                });
+            }
+        }
+        views
+    }
+
+    /// Applies every law, and folds constants under `Wrapping`, in every
+    /// class until a round learns nothing new or the graph grows past
+    /// `node_limit` nodes. Multiply-zero is left out: a view shows its
+    /// operands as placeholders, which may fail, so it could never fire,
+    /// and an operand that cannot fail is built from literals alone and
+    /// folds to a constant anyway.
+    fn saturate(&mut self, node_limit: usize) {
+        loop {
+            let classes = self.classes();
+            let mut changed = false;
+            for (node, class) in self.nodes.clone() {
+                for view in Self::views(&node, &classes) {
+                    let mut equal: Vec<Expr> = LAWS
+                        .iter()
+                        .filter(|law| law.name != "multiply-zero")
+                        .filter_map(|law| (law.rewrite)(&view))
+                        .collect();
+                    if let Expr::Add(l, r)
+                    | Expr::Subtract(l, r)
+                    | Expr::Multiply(l, r)
+                    | Expr::Divide(l, r)
+                    | Expr::Modulo(l, r)
+                    | Expr::LessThan(l, r)
+                    | Expr::GreaterThan(l, r)
+                    | Expr::LessThanOrEqual(l, r)
+                    | Expr::GreaterThanOrEqual(l, r)
+                    | Expr::Equal(l, r)
+                    | Expr::NotEqual(l, r)
+                    | Expr::And(l, r)
+                    | Expr::Or(l, r) = &view
+                    {
+                        if l.is_value() && r.is_value() {
+                            equal.extend(view.evalute_in(&mut Environment::new(), &Wrapping).ok());
+                        }
+                    }
+                    if let Expr::Not(operand) = &view {
+                        if operand.is_value() {
+                            equal.extend(view.evalute_in(&mut Environment::new(), &Wrapping).ok());
+                        }
+                    }
+                    for expr in equal {
+                        let id = self.add_view(&expr);
+                        changed |= self.union(class, id);
+                    }
+                }
+                if self.nodes.len() > node_limit {
+                    self.rebuild();
+                    return;
+                }
+            }
+            self.rebuild();
+            if !changed {
+                return;
+            }
+        }
+    }
+
+    /// The smallest expression in class `id`.
+    fn extract(&self, id: ClassId) -> Expr {
+        let mut best: HashMap<ClassId, (usize, Expr)> = HashMap::new();
+        loop {
+            let mut improved = false;
+            for (node, class) in &self.nodes {
+                let class = self.find(*class);
+                let candidate = match self.canonical(node) {
+                    ENode::Leaf(expr) => Some((1, expr)),
+                    ENode::Variable(name) => Some((1, Expr::Variable(name))),
+                    ENode::Not(operand) => best
+                        .get(&operand)
+                        .map(|(size, operand)| (1 + size, Expr::Not(operand.clone().into()))),
+                    ENode::Add(l, r)
+                    | ENode::Subtract(l, r)
+                    | ENode::Multiply(l, r)
+                    | ENode::Divide(l, r)
+                    | ENode::Modulo(l, r)
+                    | ENode::LessThan(l, r)
+                    | ENode::GreaterThan(l, r)
+                    | ENode::LessThanOrEqual(l, r)
+                    | ENode::GreaterThanOrEqual(l, r)
+                    | ENode::Equal(l, r)
+                    | ENode::NotEqual(l, r)
+                    | ENode::And(l, r)
+                    | ENode::Or(l, r) => match (best.get(&l), best.get(&r)) {
+                        (Some((l_size, l)), Some((r_size, r))) => {
+                            let (l, r) = (Box::new(l.clone()), Box::new(r.clone()));
+                            Some((
+                                1 + l_size + r_size,
+                                match node {
+                                    ENode::Add(..) => Expr::Add(l, r),
+                                    ENode::Subtract(..) => Expr::Subtract(l, r),
+                                    ENode::Multiply(..) => Expr::Multiply(l, r),
+                                    ENode::Divide(..) => Expr::Divide(l, r),
+                                    ENode::Modulo(..) => Expr::Modulo(l, r),
+                                    ENode::GreaterThan(..) => Expr::GreaterThan(l, r),
+                                    ENode::LessThanOrEqual(..) => Expr::LessThanOrEqual(l, r),
+                                    ENode::GreaterThanOrEqual(..) => Expr::GreaterThanOrEqual(l, r),
+                                    ENode::Equal(..) => Expr::Equal(l, r),
+                                    ENode::NotEqual(..) => Expr::NotEqual(l, r),
+                                    ENode::And(..) => Expr::And(l, r),
+                                    ENode::Or(..) => Expr::Or(l, r),
+                                    _ => Expr::LessThan(l, r),
+                                },
+                            ))
+                        }
+                        _ => None,
+                    },
+                };
+                if let Some((size, expr)) = candidate {
+                    if best
+                        .get(&class)
+                        .is_none_or(|(best_size, _)| size < *best_size)
+                    {
+                        best.insert(class, (size, expr));
+                        improved = true;
+                    }
+                }
+            }
+            if !improved {
+                return best
+                    .remove(&self.find(id))
+                    .expect("class has no finite expression")
+                    .1;
+            }
+        }
+    }
+}
+
+/// Simplifies `expr` by equality saturation over `LAWS` and constant
+/// folding, returning the smallest equivalent expression found. Like the
+/// laws it is exact under `Wrapping` arithmetic. Expressions with side
+/// effects are returned unchanged.
+#[allow(dead_code)]
+fn saturate_simplify(expr: &Expr, node_limit: usize) -> Expr {
+    if expr.has_side_effects() {
+        return expr.clone();
+    }
+    let mut graph = EGraph::new();
+    let root = graph.add_expr(expr);
+    graph.saturate(node_limit);
+    graph.extract(root)
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            Self::Number(n) => write!(f, "{}", n),
+            Self::Add(l, r) => write!(f, "{} + {}", l, r),
+            Self::Subtract(l, r) => write!(f, "{} - {}", l, r),
+            Self::Multiply(l, r) => write!(f, "{} * {}", l, r),
+            Self::Divide(l, r) => write!(f, "{} / {}", l, r),
+            Self::Modulo(l, r) => write!(f, "{} % {}", l, r),
+            Self::Boolean(b) => write!(f, "{}", b),
+            Self::LessThan(l, r) => write!(f, "{} < {}", l, r),
+            Self::GreaterThan(l, r) => write!(f, "{} > {}", l, r),
+            Self::LessThanOrEqual(l, r) => write!(f, "{} <= {}", l, r),
+            Self::GreaterThanOrEqual(l, r) => write!(f, "{} >= {}", l, r),
+            Self::Equal(l, r) => write!(f, "{} == {}", l, r),
+            Self::NotEqual(l, r) => write!(f, "{} != {}", l, r),
+            Self::And(l, r) => write!(f, "{} && {}", l, r),
+            Self::Or(l, r) => write!(f, "{} || {}", l, r),
+            Self::Not(expr) if expr.is_binary() => write!(f, "!({})", expr),
+            Self::Not(expr) => write!(f, "!{}", expr),
+            Self::Variable(name) => write!(f, "{}", name),
+            Self::AssignExpr(name, expr) => write!(f, "({} = {})", name, expr),
+            Self::PreIncrement(name) => write!(f, "++{}", name),
+            Self::PostIncrement(name) => write!(f, "{}++", name),
+        }
+    }
+}
+
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "<<{}>>", self)
+    }
+}
+
+impl Value for Expr {
+    fn parse_literal(text: &str) -> Option<Self> {
+        match text {
+            "true" => Some(Self::Boolean(true)),
+            "false" => Some(Self::Boolean(false)),
+            _ => text.parse().ok().map(Self::Number),
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone)]
+pub(crate) enum Stmt {
+    DoNothing,
+    Assign(String, Expr),
+    If {
+        condition: Expr,
+        consequence: Box<Stmt>,
+        alternative: Box<Stmt>,
+    },
+    Sequence {
+        first: Box<Stmt>,
+        second: Box<Stmt>,
+    },
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+    },
+}
+
+#[allow(dead_code)]
+impl Stmt {
+    fn evalute(&self, env: Environment) -> Environment {
+        self.evalute_in(env, &Checked)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `evalute`, but gives up once more than `step_limit` statements
+    /// (counting every loop iteration) have been evaluated.
+    fn evalute_with_limit(
+        &self,
+        env: Environment,
+        step_limit: usize,
+    ) -> Result<Environment, EvalError> {
+        let mut fuel = step_limit;
+        let mut env = env;
+        self.run(&mut env, &mut fuel, &Checked)?;
+        Ok(env)
+    }
+
+    /// Evaluates the program with arithmetic done by `semantics`.
+    fn evalute_in<N: NumericSemantics>(
+        &self,
+        env: Environment,
+        semantics: &N,
+    ) -> Result<Environment, EvalError> {
+        let mut env = env;
+        self.evalute_in_mut(&mut env, semantics)?;
+        Ok(env)
+    }
+
+    /// Like `evalute`, but updates `env` in place, so a caller that keeps
+    /// its environment does not have to clone it first.
+    fn evalute_mut(&self, env: &mut Environment) {
+        self.evalute_in_mut(env, &Checked)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `evalute_in`, but in place. On an error, `env` keeps the
+    /// writes made before it.
+    fn evalute_in_mut<N: NumericSemantics>(
+        &self,
+        env: &mut Environment,
+        semantics: &N,
+    ) -> Result<(), EvalError> {
+        let mut fuel = usize::MAX;
+        self.run(env, &mut fuel, semantics)
+    }
+
+    pub(crate) fn run<N: NumericSemantics>(
+        &self,
+        env: &mut Environment,
+        fuel: &mut usize,
+        semantics: &N,
+    ) -> Result<(), EvalError> {
+        if *fuel == 0 {
+            return Err(EvalError::StepLimitExceeded);
+        }
+        *fuel -= 1;
+        match self {
+            Self::DoNothing => Ok(()),
+            Self::Assign(name, expr) => {
+                let value = expr.evalute_in(env, semantics)?;
+                env.set(name, value);
+                Ok(())
+            }
+            Self::If {
+                condition,
+                consequence,
+                alternative,
+            } => match condition.evalute_in(env, semantics)? {
+                Expr::Boolean(true) => consequence.run(env, fuel, semantics),
+                Expr::Boolean(false) => alternative.run(env, fuel, semantics),
+                _ => Err(EvalError::TypeMismatch(condition.clone())),
+            },
+            Self::Sequence { first, second } => {
+                first.run(env, fuel, semantics)?;
+                second.run(env, fuel, semantics)
+            }
+            // Iterates rather than recursing, charging each further
+            // iteration like the recursive `run` it replaces.
+            Self::While { condition, body } => loop {
+                match condition.evalute_in(env, semantics)? {
+                    Expr::Boolean(true) => body.run(env, fuel, semantics)?,
+                    Expr::Boolean(false) => return Ok(()),
+                    _ => return Err(EvalError::TypeMismatch(condition.clone())),
+                }
+                if *fuel == 0 {
+                    return Err(EvalError::StepLimitExceeded);
+                }
+                *fuel -= 1;
+            },
+        }
+    }
+
+    /// Like `run` under `Checked`, telling `observer` about every
+    /// assignment and condition right after evaluating it, along with the
+    /// path of its statement.
+    fn run_observed(
+        &self,
+        path: &mut Vec<usize>,
+        mut env: Environment,
+        fuel: &mut usize,
+        observer: &mut dyn RunObserver,
+    ) -> Result<Environment, EvalError> {
+        if *fuel == 0 {
+            return Err(EvalError::StepLimitExceeded);
+        }
+        *fuel -= 1;
+        fn child(
+            i: usize,
+            stmt: &Stmt,
+            path: &mut Vec<usize>,
+            env: Environment,
+            fuel: &mut usize,
+            observer: &mut dyn RunObserver,
+        ) -> Result<Environment, EvalError> {
+            path.push(i);
+            let result = stmt.run_observed(path, env, fuel, observer);
+            path.pop();
+            result
+        }
+        match self {
+            Self::DoNothing => Ok(env),
+            Self::Assign(name, expr) => {
+                let value = expr.evalute_in(&mut env, &Checked)?;
+                observer.assign(path, name, expr);
+                env.set(name, value);
+                Ok(env)
+            }
+            Self::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let taken = match condition.evalute_in(&mut env, &Checked)? {
+                    Expr::Boolean(taken) => taken,
+                    _ => return Err(EvalError::TypeMismatch(condition.clone())),
+                };
+                observer.condition(path, condition, taken);
+                if taken {
+                    child(0, consequence, path, env, fuel, observer)
+                } else {
+                    child(1, alternative, path, env, fuel, observer)
+                }
+            }
+            Self::Sequence { first, second } => {
+                let env = child(0, first, path, env, fuel, observer)?;
+                child(1, second, path, env, fuel, observer)
+            }
+            // Loops in place like `run`, so a long loop cannot overflow
+            // the stack.
+            Self::While { condition, body } => loop {
+                let taken = match condition.evalute_in(&mut env, &Checked)? {
+                    Expr::Boolean(taken) => taken,
+                    _ => return Err(EvalError::TypeMismatch(condition.clone())),
+                };
+                observer.condition(path, condition, taken);
+                if !taken {
+                    return Ok(env);
+                }
+                env = child(0, body, path, env, fuel, observer)?;
+                if *fuel == 0 {
+                    return Err(EvalError::StepLimitExceeded);
+                }
+                *fuel -= 1;
+            },
+        }
+    }
+
+    /// Evaluates everything that depends only on `known` and returns the
+    /// residual program along with the variables still known afterwards.
+    /// Loops whose condition is known are unrolled, so a known infinite
+    /// loop makes specialization diverge just like evaluation would.
+    fn specialize(&self, known: Environment) -> (Stmt, Environment) {
+        let mut unrolls = usize::MAX;
+        self.specialize_within(known, &mut unrolls)
+    }
+
+    /// Like `specialize`, but unrolls at most `unrolls` loop iterations in
+    /// all. A loop reached after that is kept, as if its condition were
+    /// unknown.
+    fn specialize_within(
+        &self,
+        mut known: Environment,
+        unrolls: &mut usize,
+    ) -> (Stmt, Environment) {
+        match self {
+            Self::DoNothing => (Self::DoNothing, known),
+            Self::Assign(name, expr) => {
+                let expr = expr.specialize(&known);
+                known.retain(|name, _| !expr.assigns(name));
+                if expr.is_value() {
+                    known.set(name, expr.clone());
+                } else {
+                    known.remove(name);
+                }
+                (Self::Assign(name.into(), expr), known)
+            }
+            Self::If {
+                condition,
+                consequence,
+                alternative,
+            } => match condition.specialize(&known) {
+                Expr::Boolean(true) => consequence.specialize_within(known, unrolls),
+                Expr::Boolean(false) => alternative.specialize_within(known, unrolls),
+                condition => {
+                    known.retain(|name, _| !condition.assigns(name));
+                    let (consequence, consequence_known) =
+                        consequence.specialize_within(known.clone(), unrolls);
+                    let (alternative, alternative_known) =
+                        alternative.specialize_within(known, unrolls);
+                    let known = consequence_known
+                        .into_iter()
+                        .filter(|(name, value)| alternative_known.get(name) == Ok(value))
+                        .collect();
+                    (
+                        Self::If {
+                            condition,
+                            consequence: consequence.into(),
+                            alternative: alternative.into(),
+                        },
+                        known,
+                    )
+                }
+            },
+            Self::Sequence { first, second } => {
+                let (first, known) = first.specialize_within(known, unrolls);
+                let (second, known) = second.specialize_within(known, unrolls);
+                let stmt = match (first, second) {
+                    (Self::DoNothing, stmt) | (stmt, Self::DoNothing) => stmt,
+                    (first, second) => Self::Sequence {
+                        first: first.into(),
+                        second: second.into(),
+                    },
+                };
+                (stmt, known)
+            }
+            Self::While { condition, body } => match condition.specialize(&known) {
+                Expr::Boolean(true) if *unrolls > 0 => {
+                    *unrolls -= 1;
+                    Self::Sequence {
+                        first: body.clone(),
+                        second: self.clone().into(),
+                    }
+                    .specialize_within(known, unrolls)
+                }
+                Expr::Boolean(false) => (Self::DoNothing, known),
+                _ => {
+                    known.retain(|name, _| !self.assigns(name));
+                    let condition = condition.specialize(&known);
+                    let (body, _) = body.specialize_within(known.clone(), unrolls);
+                    (
+                        Self::While {
+                            condition,
+                            body: body.into(),
+                        },
+                        known,
+                    )
+                }
+            },
+        }
+    }
+
+    /// Decides whether two loop-free programs are observationally
+    /// equivalent under `semantics`: from every initial environment that
+    /// binds `variables` to a number in `0..modulus` or a boolean, both
+    /// either leave the same values in `variables` or fail with the same
+    /// kind of error; `x + x` and `x * 2` fail alike on a boolean `x`,
+    /// though the failing expressions differ. The domain is finite, so
+    /// every environment is tried.
+    /// `Ok(Some(env))` is one they differ on; `Ok(None)` means there is
+    /// none.
+    fn equivalent(
+        &self,
+        other: &Stmt,
+        variables: &[String],
+        semantics: &modular::Modular,
+    ) -> Result<Option<Environment>, Undecided> {
+        if self.has_loop() || other.has_loop() {
+            return Err(Undecided::HasLoop);
+        }
+        let domain: Vec<Expr> = (0..semantics.modulus())
+            .map(Expr::Number)
+            .chain([Expr::Boolean(false), Expr::Boolean(true)])
+            .collect();
+        let inputs = domain
+            .len()
+            .checked_pow(variables.len() as u32)
+            .filter(|&inputs| inputs <= EQUIVALENCE_INPUTS)
+            .ok_or(Undecided::TooManyInputs)?;
+        let outcome = |stmt: &Stmt, env: &Environment| {
+            stmt.evalute_in(env.clone(), semantics)
+                .map(|env| {
+                    variables
+                        .iter()
+                        .map(|name| env.get(name).ok().cloned())
+                        .collect::<Vec<_>>()
+                })
+                .map_err(|error| mem::discriminant(&error))
+        };
+        let mut indices = vec![0; variables.len()];
+        for _ in 0..inputs {
+            let env: Environment = variables
+                .iter()
+                .zip(&indices)
+                .map(|(name, &i)| (name.clone(), domain[i].clone()))
+                .collect();
+            if outcome(self, &env) != outcome(other, &env) {
+                return Ok(Some(env));
+            }
+            for index in indices.iter_mut() {
+                *index += 1;
+                if *index < domain.len() {
+                    break;
+                }
+                *index = 0;
+            }
+        }
+        Ok(None)
+    }
+
+    fn children(&self) -> Vec<&Stmt> {
+        match self {
+            Self::DoNothing | Self::Assign(..) => vec![],
+            Self::If {
+                consequence,
+                alternative,
+                ..
+            } => vec![consequence, alternative],
+            Self::Sequence { first, second } => vec![first, second],
+            Self::While { body, .. } => vec![body],
+        }
+    }
+
+    /// The expressions that appear directly in this statement, not in its
+    /// child statements.
+    fn exprs(&self) -> Vec<&Expr> {
+        match self {
+            Self::DoNothing | Self::Sequence { .. } => vec![],
+            Self::Assign(_, expr) => vec![expr],
+            Self::If { condition, .. } | Self::While { condition, .. } => vec![condition],
+        }
+    }
+
+    /// This statement and all of its substatements, in preorder.
+    fn substmts(&self) -> impl Iterator<Item = &Stmt> {
+        let mut nodes = vec![];
+        let mut stack = vec![self];
+        while let Some(stmt) = stack.pop() {
+            nodes.push(stmt);
+            stack.extend(stmt.children().into_iter().rev());
+        }
+        nodes.into_iter()
+    }
+
+    /// Follows `path`, a list of child indices as given by `children`,
+    /// from this statement down.
+    fn at(&self, path: &[usize]) -> Option<&Stmt> {
+        match path.split_first() {
+            None => Some(self),
+            Some((&i, rest)) => self.children().get(i)?.at(rest),
+        }
+    }
+
+    fn at_mut(&mut self, path: &[usize]) -> Option<&mut Stmt> {
+        let (&i, rest) = match path.split_first() {
+            None => return Some(self),
+            Some(split) => split,
+        };
+        let child = match (self, i) {
+            (Self::If { consequence, .. }, 0) => consequence,
+            (Self::If { alternative, .. }, 1) => alternative,
+            (Self::Sequence { first, .. }, 0) => first,
+            (Self::Sequence { second, .. }, 1) => second,
+            (Self::While { body, .. }, 0) => body,
+            _ => return None,
+        };
+        child.at_mut(rest)
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::DoNothing => "do-nothing",
+            Self::Assign(..) => "assign",
+            Self::If { .. } => "if",
+            Self::Sequence { .. } => "sequence",
+            Self::While { .. } => "while",
+        }
+    }
+
+    /// Every statement with its path, in preorder.
+    fn paths(&self) -> Vec<(Vec<usize>, &Stmt)> {
+        let mut paths = vec![(vec![], self)];
+        for (i, child) in self.children().into_iter().enumerate() {
+            for (mut path, stmt) in child.paths() {
+                path.insert(0, i);
+                paths.push((path, stmt));
+            }
+        }
+        paths
+    }
+
+    /// The statements matched by a selector such as `while > assign[var=x]`,
+    /// with their paths. See `Selector` for the syntax.
+    fn select(&self, selector: &str) -> Result<Vec<(Vec<usize>, &Stmt)>, String> {
+        let selector = Selector::parse(selector)?;
+        Ok(self
+            .paths()
+            .into_iter()
+            .filter(|(path, stmt)| {
+                let ancestors: Vec<&Stmt> = (0..path.len())
+                    .filter_map(|len| self.at(&path[..len]))
+                    .filter(|stmt| !matches!(stmt, Self::Sequence { .. }))
+                    .collect();
+                selector.matches(stmt, &ancestors)
+            })
+            .collect())
+    }
+
+    /// Every expression node anywhere in the program, statement by
+    /// statement in preorder.
+    fn subexprs(&self) -> impl Iterator<Item = &Expr> {
+        self.substmts()
+            .flat_map(Stmt::exprs)
+            .flat_map(Expr::subexprs)
+    }
+
+    /// Every variable the program assigns or reads.
+    fn variables(&self) -> HashSet<String> {
+        let assigned = self.substmts().filter_map(|stmt| match stmt {
+            Stmt::Assign(name, _) => Some(name),
+            _ => None,
+        });
+        let used = self.subexprs().filter_map(|expr| match expr {
+            Expr::Variable(name)
+            | Expr::AssignExpr(name, _)
+            | Expr::PreIncrement(name)
+            | Expr::PostIncrement(name) => Some(name),
+            _ => None,
+        });
+        assigned.chain(used).cloned().collect()
+    }
+
+    /// Number of statement and expression nodes in the program.
+    fn size(&self) -> usize {
+        self.substmts().count() + self.subexprs().count()
+    }
+
+    fn depth(&self) -> usize {
+        let stmt_depth = self.children().into_iter().map(Stmt::depth);
+        let expr_depth = self.exprs().into_iter().map(Expr::depth);
+        1 + stmt_depth.chain(expr_depth).max().unwrap_or(0)
+    }
+
+    fn count_ops(&self) -> HashMap<&'static str, usize> {
+        let mut counts = HashMap::new();
+        for op in self.subexprs().filter_map(Expr::operator) {
+            *counts.entry(op).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// One-step simplifications of the program, most drastic first.
+    fn shrink_candidates(&self) -> Vec<Stmt> {
+        let mut candidates = vec![];
+        match self {
+            Self::DoNothing => {}
+            Self::Assign(name, expr) => {
+                candidates.push(Self::DoNothing);
+                for expr in expr.shrink_candidates() {
+                    candidates.push(Self::Assign(name.clone(), expr));
+                }
+            }
+            Self::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                candidates.push(Self::DoNothing);
+                candidates.push(*consequence.clone());
+                candidates.push(*alternative.clone());
+                for condition in condition.shrink_candidates() {
+                    candidates.push(Self::If {
+                        condition,
+                        consequence: consequence.clone(),
+                        alternative: alternative.clone(),
+                    });
+                }
+                for consequence in consequence.shrink_candidates() {
+                    candidates.push(Self::If {
+                        condition: condition.clone(),
+                        consequence: consequence.into(),
+                        alternative: alternative.clone(),
+                    });
+                }
+                for alternative in alternative.shrink_candidates() {
+                    candidates.push(Self::If {
+                        condition: condition.clone(),
+                        consequence: consequence.clone(),
+                        alternative: alternative.into(),
+                    });
+                }
+            }
+            Self::Sequence { first, second } => {
+                candidates.push(*first.clone());
+                candidates.push(*second.clone());
+                for first in first.shrink_candidates() {
+                    candidates.push(Self::Sequence {
+                        first: first.into(),
+                        second: second.clone(),
+                    });
+                }
+                for second in second.shrink_candidates() {
+                    candidates.push(Self::Sequence {
+                        first: first.clone(),
+                        second: second.into(),
+                    });
+                }
+            }
+            Self::While { condition, body } => {
+                candidates.push(Self::DoNothing);
+                candidates.push(*body.clone());
+                for condition in condition.shrink_candidates() {
+                    candidates.push(Self::While {
+                        condition,
+                        body: body.clone(),
+                    });
+                }
+                for body in body.shrink_candidates() {
+                    candidates.push(Self::While {
+                        condition: condition.clone(),
+                        body: body.into(),
+                    });
+                }
+            }
+        }
+        candidates
+    }
+
+    /// Every program with exactly one expression mutated as in
+    /// `Expr::mutants`.
+    fn mutants(&self) -> Vec<Stmt> {
+        let mut mutants = vec![];
+        match self {
+            Self::DoNothing => {}
+            Self::Assign(name, expr) => {
+                for expr in expr.mutants() {
+                    mutants.push(Self::Assign(name.clone(), expr));
+                }
+            }
+            Self::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                for condition in condition.mutants() {
+                    mutants.push(Self::If {
+                        condition,
+                        consequence: consequence.clone(),
+                        alternative: alternative.clone(),
+                    });
+                }
+                for consequence in consequence.mutants() {
+                    mutants.push(Self::If {
+                        condition: condition.clone(),
+                        consequence: consequence.into(),
+                        alternative: alternative.clone(),
+                    });
+                }
+                for alternative in alternative.mutants() {
+                    mutants.push(Self::If {
+                        condition: condition.clone(),
+                        consequence: consequence.clone(),
+                        alternative: alternative.into(),
+                    });
+                }
+            }
+            Self::Sequence { first, second } => {
+                for first in first.mutants() {
+                    mutants.push(Self::Sequence {
+                        first: first.into(),
+                        second: second.clone(),
+                    });
+                }
+                for second in second.mutants() {
+                    mutants.push(Self::Sequence {
+                        first: first.clone(),
+                        second: second.into(),
+                    });
+                }
+            }
+            Self::While { condition, body } => {
+                for condition in condition.mutants() {
+                    mutants.push(Self::While {
+                        condition,
+                        body: body.clone(),
+                    });
+                }
+                for body in body.mutants() {
+                    mutants.push(Self::While {
+                        condition: condition.clone(),
+                        body: body.into(),
+                    });
+                }
+            }
+        }
+        mutants
+    }
+
+    fn rename(&self, names: &HashMap<String, String>) -> Stmt {
+        match self {
+            Self::DoNothing => Self::DoNothing,
+            Self::Assign(name, expr) => {
+                Self::Assign(names.get(name).unwrap_or(name).clone(), expr.rename(names))
+            }
+            Self::If {
+                condition,
+                consequence,
+                alternative,
+            } => Self::If {
+                condition: condition.rename(names),
+                consequence: consequence.rename(names).into(),
+                alternative: alternative.rename(names).into(),
+            },
+            Self::Sequence { first, second } => Self::Sequence {
+                first: first.rename(names).into(),
+                second: second.rename(names).into(),
+            },
+            Self::While { condition, body } => Self::While {
+                condition: condition.rename(names),
+                body: body.rename(names).into(),
+            },
+        }
+    }
+
+    fn has_loop(&self) -> bool {
+        match self {
+            Self::DoNothing | Self::Assign(..) => false,
+            Self::If {
+                consequence,
+                alternative,
+                ..
+            } => consequence.has_loop() || alternative.has_loop(),
+            Self::Sequence { first, second } => first.has_loop() || second.has_loop(),
+            Self::While { .. } => true,
+        }
+    }
+
+    fn numbers(&self, numbers: &mut Vec<i64>) {
+        match self {
+            Self::DoNothing => {}
+            Self::Assign(_, expr) => expr.numbers(numbers),
+            Self::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                condition.numbers(numbers);
+                consequence.numbers(numbers);
+                alternative.numbers(numbers);
+            }
+            Self::Sequence { first, second } => {
+                first.numbers(numbers);
+                second.numbers(numbers);
+            }
+            Self::While { condition, body } => {
+                condition.numbers(numbers);
+                body.numbers(numbers);
+            }
+        }
+    }
+
+    fn assigns(&self, name: &str) -> bool {
+        match self {
+            Self::DoNothing => false,
+            Self::Assign(assigned, expr) => assigned == name || expr.assigns(name),
+            Self::If {
+                condition,
+                consequence,
+                alternative,
+            } => condition.assigns(name) || consequence.assigns(name) || alternative.assigns(name),
+            Self::Sequence { first, second } => first.assigns(name) || second.assigns(name),
+            Self::While { condition, body } => condition.assigns(name) || body.assigns(name),
+        }
+    }
+
+    /// Every variable the statement may read, on any path.
+    fn read_set(&self) -> HashSet<String> {
+        self.subexprs()
+            .filter_map(|expr| match expr {
+                Expr::Variable(name) | Expr::PreIncrement(name) | Expr::PostIncrement(name) => {
+                    Some(name.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Every variable the statement may write, on any path.
+    fn write_set(&self) -> HashSet<String> {
+        let mut writes: HashSet<String> = self
+            .subexprs()
+            .filter_map(|expr| match expr {
+                Expr::AssignExpr(name, _)
+                | Expr::PreIncrement(name)
+                | Expr::PostIncrement(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+        for stmt in self.substmts() {
+            if let Self::Assign(name, _) = stmt {
+                writes.insert(name.clone());
+            }
+        }
+        writes
+    }
+
+    /// Whether running `self` and `other` in either order, or at the same
+    /// time, gives the same result: neither writes anything the other
+    /// reads or writes.
+    fn independent_of(&self, other: &Stmt) -> bool {
+        let (writes, other_writes) = (self.write_set(), other.write_set());
+        writes.is_disjoint(&other.read_set())
+            && writes.is_disjoint(&other_writes)
+            && other_writes.is_disjoint(&self.read_set())
+    }
+}
+
+/// Identifies a statement of a program; see `NodeIds`.
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy, Debug)]
+struct NodeId(usize);
+
+/// The ids of a program's statements, handed out in preorder when the
+/// program is built. An id keeps naming the same statement for as long as
+/// it lives: `replace` gives the statements it brings in fresh ids and
+/// retires those it drops, and never renumbers the rest.
+#[allow(dead_code)]
+struct NodeIds {
+    paths: BTreeMap<NodeId, Vec<usize>>,
+    next: usize,
+}
+
+#[allow(dead_code)]
+impl NodeIds {
+    fn new(stmt: &Stmt) -> Self {
+        let mut ids = Self {
+            paths: BTreeMap::new(),
+            next: 0,
+        };
+        ids.number(stmt, &[]);
+        ids
+    }
+
+    fn number(&mut self, stmt: &Stmt, at: &[usize]) {
+        for (path, _) in stmt.paths() {
+            self.paths.insert(NodeId(self.next), [at, &path].concat());
+            self.next += 1;
+        }
+    }
+
+    /// The child-index path, as taken by `Stmt::at`, to the statement `id`
+    /// names.
+    fn path(&self, id: NodeId) -> Option<&[usize]> {
+        self.paths.get(&id).map(Vec::as_slice)
+    }
+
+    fn find<'a>(&self, stmt: &'a Stmt, id: NodeId) -> Option<&'a Stmt> {
+        stmt.at(self.path(id)?)
+    }
+
+    /// Replaces the statement at `path` in `stmt`, which these ids number,
+    /// with `new`.
+    fn replace(&mut self, stmt: &mut Stmt, path: &[usize], new: Stmt) -> Option<Stmt> {
+        let old = mem::replace(stmt.at_mut(path)?, new);
+        self.paths.retain(|_, at| !at.starts_with(path));
+        self.number(stmt.at(path)?, path);
+        Some(old)
+    }
+}
+
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct Compound {
+    kind: Option<&'static str>,
+    attributes: Vec<(String, String)>,
+}
+
+#[allow(dead_code)]
+impl Compound {
+    fn parse(text: &str) -> Result<Self, String> {
+        let text = text.strip_prefix("stmt.").unwrap_or(text);
+        let (kind, mut rest) = text.split_at(text.find('[').unwrap_or(text.len()));
+        let kind = match kind {
+            "*" | "stmt" => None,
+            _ => Some(
+                ["do-nothing", "assign", "if", "sequence", "while"]
+                    .iter()
+                    .find(|&&known| known == kind)
+                    .copied()
+                    .ok_or_else(|| format!("unknown statement kind `{}`", kind))?,
+            ),
+        };
+        let mut attributes = vec![];
+        while !rest.is_empty() {
+            let end = rest
+                .find(']')
+                .filter(|_| rest.starts_with('['))
+                .ok_or_else(|| format!("malformed attribute `{}`", rest))?;
+            let (name, value) = rest[1..end]
+                .split_once('=')
+                .ok_or_else(|| format!("attribute `{}` needs a value", &rest[1..end]))?;
+            if name != "var" {
+                return Err(format!("unknown attribute `{}`", name));
+            }
+            attributes.push((name.to_string(), value.to_string()));
+            rest = &rest[end + 1..];
+        }
+        Ok(Self { kind, attributes })
+    }
+
+    fn matches(&self, stmt: &Stmt) -> bool {
+        self.kind.is_none_or(|kind| kind == stmt.kind())
+            && self.attributes.iter().all(|(_, value)| match stmt {
+                Stmt::Assign(name, _) => name == value,
+                _ => false,
+            })
+    }
+}
+
+/// A CSS-like statement selector. Each step names a statement kind
+/// (`do-nothing`, `assign`, `if`, `sequence`, `while`, or `*`, optionally
+/// written `stmt.while`) followed by `[var=x]` filters on the assigned
+/// variable. Steps separated by spaces match descendants, and by `>`
+/// direct children. Sequences only glue statements together, so they are
+/// skipped when looking for a statement's parent.
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct Selector {
+    steps: Vec<Compound>,
+    /// `child[i]` tells whether `steps[i + 1]` must be a direct child of
+    /// `steps[i]` rather than any descendant.
+    child: Vec<bool>,
+}
+
+#[allow(dead_code)]
+impl Selector {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut steps = vec![];
+        let mut child = vec![];
+        let mut pending_child = false;
+        for token in text.replace('>', " > ").split_whitespace() {
+            if token == ">" {
+                if steps.is_empty() || pending_child {
+                    return Err("`>` must stand between two steps".into());
+                }
+                pending_child = true;
+                continue;
+            }
+            if !steps.is_empty() {
+                child.push(pending_child);
+            }
+            steps.push(Compound::parse(token)?);
+            pending_child = false;
+        }
+        if steps.is_empty() || pending_child {
+            return Err(format!("incomplete selector `{}`", text));
+        }
+        Ok(Self { steps, child })
+    }
+
+    /// `ancestors` are the statement's non-sequence ancestors, outermost
+    /// first.
+    fn matches(&self, stmt: &Stmt, ancestors: &[&Stmt]) -> bool {
+        let last = self.steps.len() - 1;
+        self.steps[last].matches(stmt) && self.matches_ancestors(last, ancestors)
+    }
+
+    fn matches_ancestors(&self, step: usize, ancestors: &[&Stmt]) -> bool {
+        if step == 0 {
+            return true;
+        }
+        let candidates = if self.child[step - 1] {
+            ancestors.len().saturating_sub(1)..ancestors.len()
+        } else {
+            0..ancestors.len()
+        };
+        candidates.rev().any(|i| {
+            self.steps[step - 1].matches(ancestors[i])
+                && self.matches_ancestors(step - 1, &ancestors[..i])
+        })
+    }
+}
+
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub(crate) enum EvalError {
+    StepLimitExceeded,
+    Overflow,
+    UndefinedVariable(String),
+    /// The divisor of this expression evaluated to zero.
+    DivisionByZero(Expr),
+    /// The operands of this equality evaluated to values of different
+    /// types, an operand of this arithmetic or comparison to a boolean,
+    /// an operand of this `&&`, `||` or `!` to a number, the variable of
+    /// this increment holds a boolean, or this condition evaluated to a
+    /// number.
+    TypeMismatch(Expr),
+}
+
+/// The most initial environments `Stmt::equivalent` tries before giving
+/// up.
+const EQUIVALENCE_INPUTS: usize = 1_000_000;
+
+/// Why `Stmt::equivalent` could not decide.
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone, Debug)]
+enum Undecided {
+    /// A program has a loop, so it may not terminate.
+    HasLoop,
+    /// There are more initial environments than `EQUIVALENCE_INPUTS`.
+    TooManyInputs,
+}
+
+impl From<UndefinedVariable> for EvalError {
+    fn from(error: UndefinedVariable) -> Self {
+        Self::UndefinedVariable(error.0)
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            Self::StepLimitExceeded => write!(f, "step limit exceeded"),
+            Self::Overflow => write!(f, "arithmetic overflow"),
+            Self::UndefinedVariable(name) => write!(f, "undefined variable `{}`", name),
+            Self::DivisionByZero(expr) => write!(f, "division by zero in `{}`", expr),
+            Self::TypeMismatch(expr) => write!(f, "type mismatch in `{}`", expr),
+        }
+    }
+}
+
+/// The arithmetic model the evaluator runs `+` and `*` (and increments)
+/// under.
+pub(crate) trait NumericSemantics {
+    /// Maps a literal from the program into the model's value range.
+    fn literal(&self, n: i64) -> i64 {
+        n
+    }
+    fn add(&self, a: i64, b: i64) -> Result<i64, EvalError>;
+    fn subtract(&self, a: i64, b: i64) -> Result<i64, EvalError>;
+    fn multiply(&self, a: i64, b: i64) -> Result<i64, EvalError>;
+    /// Only called with a nonzero `b`. Division truncates toward zero.
+    fn divide(&self, a: i64, b: i64) -> Result<i64, EvalError>;
+    /// Only called with a nonzero `b`. The remainder of `divide`, so it
+    /// has the sign of `a`.
+    fn remainder(&self, a: i64, b: i64) -> Result<i64, EvalError>;
+}
+
+/// Two's complement arithmetic that wraps around on overflow.
+#[allow(dead_code)]
+struct Wrapping;
+
+impl NumericSemantics for Wrapping {
+    fn add(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        Ok(a.wrapping_add(b))
+    }
+
+    fn subtract(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        Ok(a.wrapping_sub(b))
+    }
+
+    fn multiply(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        Ok(a.wrapping_mul(b))
+    }
+
+    fn divide(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        Ok(a.wrapping_div(b))
+    }
+
+    fn remainder(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        Ok(a.wrapping_rem(b))
+    }
+}
+
+/// Kept in its own module so the modulus can only be set through
+/// `Modular::new`, which rejects the ones `rem_euclid` cannot work with.
+pub(crate) mod modular {
+    use super::{EvalError, NumericSemantics};
+
+    /// Arithmetic modulo `n`, with every value kept in `0..n`. Any program
+    /// over a fixed set of variables then only has finitely many states.
+    #[allow(dead_code)]
+    pub struct Modular {
+        modulus: i64,
+    }
+
+    #[allow(dead_code)]
+    impl Modular {
+        /// Arithmetic modulo `modulus`, or `None` unless it is positive.
+        pub fn new(modulus: i64) -> Option<Self> {
+            if modulus > 0 {
+                Some(Self { modulus })
+            } else {
+                None
+            }
+        }
+
+        pub fn modulus(&self) -> i64 {
+            self.modulus
+        }
+
+        fn reduce(&self, n: i128) -> i64 {
+            n.rem_euclid(self.modulus as i128) as i64
+        }
+    }
+
+    impl NumericSemantics for Modular {
+        fn literal(&self, n: i64) -> i64 {
+            self.reduce(n as i128)
+        }
+
+        fn add(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+            Ok(self.reduce(a as i128 + b as i128))
+        }
+
+        fn subtract(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+            Ok(self.reduce(a as i128 - b as i128))
+        }
+
+        fn multiply(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+            Ok(self.reduce(a as i128 * b as i128))
+        }
+
+        fn divide(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+            Ok(self.reduce(a as i128 / b as i128))
+        }
+
+        fn remainder(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+            Ok(self.reduce(a as i128 % b as i128))
+        }
+    }
+}
+
+/// Arithmetic that reports overflow as an error; the default for
+/// `evalute`.
+pub(crate) struct Checked;
+
+impl NumericSemantics for Checked {
+    fn add(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        a.checked_add(b).ok_or(EvalError::Overflow)
+    }
+
+    fn subtract(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        a.checked_sub(b).ok_or(EvalError::Overflow)
+    }
+
+    fn multiply(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        a.checked_mul(b).ok_or(EvalError::Overflow)
+    }
+
+    fn divide(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        a.checked_div(b).ok_or(EvalError::Overflow)
+    }
+
+    fn remainder(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        a.checked_rem(b).ok_or(EvalError::Overflow)
+    }
+}
+
+/// Bindings that differ between two environments, each list sorted by
+/// variable name.
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+struct EnvironmentDiff {
+    added: Vec<(String, Expr)>,
+    removed: Vec<(String, Expr)>,
+    changed: Vec<(String, Expr, Expr)>,
+}
+
+#[allow(dead_code)]
+impl EnvironmentDiff {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for EnvironmentDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        for (name, value) in &self.added {
+            writeln!(f, "+ {} = {}", name, value)?;
+        }
+        for (name, value) in &self.removed {
+            writeln!(f, "- {} = {}", name, value)?;
+        }
+        for (name, old, new) in &self.changed {
+            writeln!(f, "~ {}: {} -> {}", name, old, new)?;
+        }
+        Ok(())
+    }
+}
+
+/// What changes when going from `old` to `new`.
+#[allow(dead_code)]
+fn diff_environments(old: &Environment, new: &Environment) -> EnvironmentDiff {
+    let mut diff = EnvironmentDiff::default();
+    for (name, value) in new {
+        match old.get(name).ok() {
+            None => diff.added.push((name.clone(), value.clone())),
+            Some(old_value) if old_value != value => {
+                diff.changed
+                    .push((name.clone(), old_value.clone(), value.clone()))
+            }
+            Some(_) => {}
+        }
+    }
+    for (name, value) in old {
+        if !new.contains(name) {
+            diff.removed.push((name.clone(), value.clone()));
+        }
+    }
+    diff.added.sort_by(|a, b| a.0.cmp(&b.0));
+    diff.removed.sort_by(|a, b| a.0.cmp(&b.0));
+    diff.changed.sort_by(|a, b| a.0.cmp(&b.0));
+    diff
+}
+
+#[allow(dead_code)]
+fn specialize(stmt: &Stmt, known_env: Environment) -> Stmt {
+    stmt.specialize(known_env).0
+}
+
+/// Delta-debugging style minimizer: repeatedly replaces the program with
+/// the first simplification that still satisfies `is_failing`, until no
+/// single simplification does.
+#[allow(dead_code)]
+pub(crate) fn shrink(stmt: &Stmt, is_failing: impl Fn(&Stmt) -> bool) -> Stmt {
+    let mut current = stmt.clone();
+    while let Some(smaller) = current
+        .shrink_candidates()
+        .into_iter()
+        .find(|candidate| is_failing(candidate))
+    {
+        current = smaller;
+    }
+    current
+}
+
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Debug)]
+struct MutationReport {
+    mutants: usize,
+    /// The mutants the test suite did not catch.
+    survivors: Vec<Stmt>,
+}
+
+#[allow(dead_code)]
+impl MutationReport {
+    fn killed(&self) -> usize {
+        self.mutants - self.survivors.len()
+    }
+}
+
+/// Runs the test suite `passes` against every mutant of `stmt`. A good
+/// suite fails on (kills) most mutants. Mutants can loop where the
+/// original did not, so the suite should evaluate with a step limit.
+#[allow(dead_code)]
+fn mutation_test(stmt: &Stmt, passes: impl Fn(&Stmt) -> bool) -> MutationReport {
+    let mutants = stmt.mutants();
+    MutationReport {
+        mutants: mutants.len(),
+        survivors: mutants
+            .into_iter()
+            .filter(|mutant| passes(mutant))
+            .collect(),
+    }
+}
+
+/// What `Stmt::run_observed` reports as it goes. Paths identify
+/// statements as in `Stmt::at`.
+#[allow(dead_code)]
+trait RunObserver {
+    fn assign(&mut self, _path: &[usize], _name: &str, _expr: &Expr) {}
+    fn condition(&mut self, _path: &[usize], _condition: &Expr, _taken: bool) {}
+}
+
+/// The evaluation rules of the big-step semantics.
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Rule {
+    Value,
+    Variable,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    DivideByZero,
+    LessThan,
+    GreaterThan,
+    LessThanOrEqual,
+    GreaterThanOrEqual,
+    Equal,
+    NotEqual,
+    TypeMismatch,
+    AndFalse,
+    AndTrue,
+    OrTrue,
+    OrFalse,
+    Not,
+    AssignExpr,
+    Increment,
+    DoNothing,
+    Assign,
+    IfTrue,
+    IfFalse,
+    Sequence,
+    WhileTrue,
+    WhileFalse,
+}
+
+#[allow(dead_code)]
+impl Rule {
+    const ALL: [Rule; 29] = [
+        Rule::Value,
+        Rule::Variable,
+        Rule::Add,
+        Rule::Subtract,
+        Rule::Multiply,
+        Rule::Divide,
+        Rule::Modulo,
+        Rule::DivideByZero,
+        Rule::LessThan,
+        Rule::GreaterThan,
+        Rule::LessThanOrEqual,
+        Rule::GreaterThanOrEqual,
+        Rule::Equal,
+        Rule::NotEqual,
+        Rule::TypeMismatch,
+        Rule::AndFalse,
+        Rule::AndTrue,
+        Rule::OrTrue,
+        Rule::OrFalse,
+        Rule::Not,
+        Rule::AssignExpr,
+        Rule::Increment,
+        Rule::DoNothing,
+        Rule::Assign,
+        Rule::IfTrue,
+        Rule::IfFalse,
+        Rule::Sequence,
+        Rule::WhileTrue,
+        Rule::WhileFalse,
+    ];
+
+    fn definition(self) -> RuleDefinition {
+        let (name, premises, conclusion): (_, &'static [&'static str], _) = match self {
+            Self::Value => ("Value", &[], "<v, env> => <v, env>"),
+            Self::Variable => ("Variable", &["env(x) = v"], "<x, env> => <v, env>"),
+            Self::Add => (
+                "Add",
+                &["<e1, env> => <n1, env1>", "<e2, env1> => <n2, env2>"],
+                "<e1 + e2, env> => <n1 + n2, env2>",
+            ),
+            Self::Subtract => (
+                "Subtract",
+                &["<e1, env> => <n1, env1>", "<e2, env1> => <n2, env2>"],
+                "<e1 - e2, env> => <n1 - n2, env2>",
+            ),
+            Self::Multiply => (
+                "Multiply",
+                &["<e1, env> => <n1, env1>", "<e2, env1> => <n2, env2>"],
+                "<e1 * e2, env> => <n1 * n2, env2>",
+            ),
+            Self::Divide => (
+                "Divide",
+                &[
+                    "<e1, env> => <n1, env1>",
+                    "<e2, env1> => <n2, env2>",
+                    "n2 != 0",
+                ],
+                "<e1 / e2, env> => <n1 / n2, env2>",
+            ),
+            Self::Modulo => (
+                "Modulo",
+                &[
+                    "<e1, env> => <n1, env1>",
+                    "<e2, env1> => <n2, env2>",
+                    "n2 != 0",
+                ],
+                "<e1 % e2, env> => <n1 % n2, env2>",
+            ),
+            Self::DivideByZero => (
+                "DivideByZero",
+                &["<e1, env> => <n1, env1>", "<e2, env1> => <0, env2>"],
+                "<e1 / e2, env> => division by zero    <e1 % e2, env> => division by zero",
+            ),
+            Self::LessThan => (
+                "LessThan",
+                &["<e1, env> => <n1, env1>", "<e2, env1> => <n2, env2>"],
+                "<e1 < e2, env> => <n1 < n2, env2>",
+            ),
+            Self::GreaterThan => (
+                "GreaterThan",
+                &["<e1, env> => <n1, env1>", "<e2, env1> => <n2, env2>"],
+                "<e1 > e2, env> => <n1 > n2, env2>",
+            ),
+            Self::LessThanOrEqual => (
+                "LessThanOrEqual",
+                &["<e1, env> => <n1, env1>", "<e2, env1> => <n2, env2>"],
+                "<e1 <= e2, env> => <n1 <= n2, env2>",
+            ),
+            Self::GreaterThanOrEqual => (
+                "GreaterThanOrEqual",
+                &["<e1, env> => <n1, env1>", "<e2, env1> => <n2, env2>"],
+                "<e1 >= e2, env> => <n1 >= n2, env2>",
+            ),
+            Self::Equal => (
+                "Equal",
+                &[
+                    "<e1, env> => <v1, env1>",
+                    "<e2, env1> => <v2, env2>",
+                    "v1, v2 : t",
+                ],
+                "<e1 == e2, env> => <v1 == v2, env2>",
+            ),
+            Self::NotEqual => (
+                "NotEqual",
+                &[
+                    "<e1, env> => <v1, env1>",
+                    "<e2, env1> => <v2, env2>",
+                    "v1, v2 : t",
+                ],
+                "<e1 != e2, env> => <v1 != v2, env2>",
+            ),
+            Self::TypeMismatch => (
+                "TypeMismatch",
+                &[
+                    "<e1, env> => <v1, env1>",
+                    "<e2, env1> => <v2, env2>",
+                    "v1 : t1",
+                    "v2 : t2",
+                    "t1 != t2",
+                ],
+                "<e1 == e2, env> => type mismatch    <e1 != e2, env> => type mismatch",
+            ),
+            Self::AndFalse => (
+                "AndFalse",
+                &["<e1, env> => <false, env1>"],
+                "<e1 && e2, env> => <false, env1>",
+            ),
+            Self::AndTrue => (
+                "AndTrue",
+                &["<e1, env> => <true, env1>", "<e2, env1> => <b, env2>"],
+                "<e1 && e2, env> => <b, env2>",
+            ),
+            Self::OrTrue => (
+                "OrTrue",
+                &["<e1, env> => <true, env1>"],
+                "<e1 || e2, env> => <true, env1>",
+            ),
+            Self::OrFalse => (
+                "OrFalse",
+                &["<e1, env> => <false, env1>", "<e2, env1> => <b, env2>"],
+                "<e1 || e2, env> => <b, env2>",
+            ),
+            Self::Not => ("Not", &["<e, env> => <b, env1>"], "<!e, env> => <!b, env1>"),
+            Self::AssignExpr => (
+                "AssignExpr",
+                &["<e, env> => <v, env1>"],
+                "<(x = e), env> => <v, env1[x := v]>",
+            ),
+            Self::Increment => (
+                "Increment",
+                &["env(x) = n"],
+                "<++x, env> => <n + 1, env[x := n + 1]>    <x++, env> => <n, env[x := n + 1]>",
+            ),
+            Self::DoNothing => ("DoNothing", &[], "<do-nothing, env> => env"),
+            Self::Assign => (
+                "Assign",
+                &["<e, env> => <v, env1>"],
+                "<x = e, env> => env1[x := v]",
+            ),
+            Self::IfTrue => (
+                "IfTrue",
+                &["<e, env> => <true, env1>", "<s1, env1> => env2"],
+                "<if (e) { s1 } else { s2 }, env> => env2",
+            ),
+            Self::IfFalse => (
+                "IfFalse",
+                &["<e, env> => <false, env1>", "<s2, env1> => env2"],
+                "<if (e) { s1 } else { s2 }, env> => env2",
+            ),
+            Self::Sequence => (
+                "Sequence",
+                &["<s1, env> => env1", "<s2, env1> => env2"],
+                "<s1; s2, env> => env2",
+            ),
+            Self::WhileTrue => (
+                "WhileTrue",
+                &[
+                    "<e, env> => <true, env1>",
+                    "<s, env1> => env2",
+                    "<while (e) { s }, env2> => env3",
+                ],
+                "<while (e) { s }, env> => env3",
+            ),
+            Self::WhileFalse => (
+                "WhileFalse",
+                &["<e, env> => <false, env1>"],
+                "<while (e) { s }, env> => env1",
+            ),
+        };
+        RuleDefinition {
+            name,
+            premises,
+            conclusion,
+        }
+    }
+}
+
+/// A rule written out as an inference rule: the premises above the line
+/// and the conclusion below it, in the notation of the book.
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+struct RuleDefinition {
+    name: &'static str,
+    premises: &'static [&'static str],
+    conclusion: &'static str,
+}
+
+impl fmt::Display for RuleDefinition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        let premises = self.premises.join("    ");
+        let width = premises.len().max(self.conclusion.len());
+        writeln!(f, "{}", premises)?;
+        writeln!(f, "{} ({})", "-".repeat(width), self.name)?;
+        write!(f, "{}", self.conclusion)
+    }
+}
+
+/// A condition, by the path of its `If` or `While`, and whether it held.
+#[allow(dead_code)]
+type Branch = (Vec<usize>, bool);
+
+impl RunObserver for Vec<Branch> {
+    fn condition(&mut self, path: &[usize], _condition: &Expr, taken: bool) {
+        self.push((path.to_vec(), taken));
+    }
+}
+
+/// The branches a run from `env` takes, up to where it stops.
+#[allow(dead_code)]
+fn branches_taken(stmt: &Stmt, env: &Environment) -> Vec<Branch> {
+    let mut branches = vec![];
+    let mut fuel = 10_000;
+    let _ = stmt.run_observed(&mut vec![], env.clone(), &mut fuel, &mut branches);
+    branches
+}
+
+/// Which executed statements were influenced by untrusted input.
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Debug, Default)]
+struct TaintReport {
+    /// Assignments that stored a tainted value, in order of first run.
+    assignments: Vec<Vec<usize>>,
+    /// `If` and `While` statements whose condition read a tainted value.
+    conditions: Vec<Vec<usize>>,
+    /// The variables holding tainted values at the end.
+    tainted: Vec<String>,
+}
+
+#[allow(dead_code)]
+struct TaintTracker {
+    tainted: HashSet<String>,
+    report: TaintReport,
+}
+
+#[allow(dead_code)]
+impl TaintTracker {
+    /// Whether `expr` reads a tainted variable, updating the taint of the
+    /// variables it writes in evaluation order.
+    fn expr(&mut self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Number(_) | Expr::Boolean(_) => false,
+            Expr::Variable(name) | Expr::PreIncrement(name) | Expr::PostIncrement(name) => {
+                self.tainted.contains(name)
+            }
+            Expr::AssignExpr(name, expr) => {
+                let tainted = self.expr(expr);
+                self.set(name, tainted);
+                tainted
+            }
+            Expr::Not(expr) => self.expr(expr),
+            Expr::Add(l, r)
+            | Expr::Subtract(l, r)
+            | Expr::Multiply(l, r)
+            | Expr::Divide(l, r)
+            | Expr::Modulo(l, r)
+            | Expr::LessThan(l, r)
+            | Expr::GreaterThan(l, r)
+            | Expr::LessThanOrEqual(l, r)
+            | Expr::GreaterThanOrEqual(l, r)
+            | Expr::Equal(l, r)
+            | Expr::NotEqual(l, r)
+            | Expr::And(l, r)
+            | Expr::Or(l, r) => {
+                let l = self.expr(l);
+                let r = self.expr(r);
+                l || r
+            }
+        }
+    }
+
+    fn set(&mut self, name: &str, tainted: bool) {
+        if tainted {
+            self.tainted.insert(name.into());
+        } else {
+            self.tainted.remove(name);
+        }
+    }
+}
+
+impl RunObserver for TaintTracker {
+    fn assign(&mut self, path: &[usize], name: &str, expr: &Expr) {
+        let tainted = self.expr(expr);
+        self.set(name, tainted);
+        if tainted && !self.report.assignments.iter().any(|seen| seen == path) {
+            self.report.assignments.push(path.to_vec());
+        }
+    }
+
+    fn condition(&mut self, path: &[usize], condition: &Expr, _taken: bool) {
+        if self.expr(condition) && !self.report.conditions.iter().any(|seen| seen == path) {
+            self.report.conditions.push(path.to_vec());
+        }
+    }
+}
+
+/// Runs `stmt` from `env`, treating the values of `untrusted` as tainted
+/// and following them through explicit data flow: a variable is tainted
+/// while it holds a value computed from a tainted one. Flows through
+/// control, such as an assignment made only because a tainted condition
+/// held, are not tracked.
+#[allow(dead_code)]
+fn track_taint(
+    stmt: &Stmt,
+    env: Environment,
+    untrusted: &[&str],
+    step_limit: usize,
+) -> Result<TaintReport, EvalError> {
+    let mut tracker = TaintTracker {
+        tainted: untrusted.iter().map(|name| name.to_string()).collect(),
+        report: TaintReport::default(),
+    };
+    let mut fuel = step_limit;
+    stmt.run_observed(&mut vec![], env, &mut fuel, &mut tracker)?;
+    let mut tainted: Vec<String> = tracker.tainted.into_iter().collect();
+    tainted.sort();
+    Ok(TaintReport {
+        tainted,
+        ..tracker.report
+    })
+}
+
+/// Finds inputs over `vars` that together take every branch reachable
+/// within `budget` runs, concolic style: run an input, then for each
+/// decision along its path (last first) whose other side is still
+/// uncovered, search for an input that follows the same path up to that
+/// decision and then goes the other way. The search is bounded rather
+/// than symbolic: it tries small values and the program's own constants
+/// give or take one.
+#[allow(dead_code)]
+fn generate_inputs(stmt: &Stmt, vars: &[&str], budget: usize) -> Vec<Environment> {
+    let mut numbers = vec![];
+    stmt.numbers(&mut numbers);
+    let mut values: Vec<i64> = (-8..=8)
+        .chain(numbers.iter().copied().flat_map(|n| {
+            vec![n.checked_sub(1), Some(n), n.checked_add(1)]
+                .into_iter()
+                .flatten()
+        }))
+        .collect();
+    values.sort_by_key(|n| (n.unsigned_abs(), *n));
+    values.dedup();
+    let input = |mut index: usize| {
+        let mut env = Environment::new();
+        for name in vars {
+            env.set(name, Expr::Number(values[index % values.len()]));
+            index /= values.len();
+        }
+        env
+    };
+    let combinations = values
+        .len()
+        .checked_pow(vars.len() as u32)
+        .unwrap_or(usize::MAX);
+
+    let mut runs = 0;
+    let mut inputs = vec![];
+    let mut covered = HashSet::new();
+    let mut attempted = HashSet::new();
+    let mut paths = vec![];
+    if budget > 0 {
+        runs += 1;
+        let path = branches_taken(stmt, &input(0));
+        covered.extend(path.iter().cloned());
+        inputs.push(input(0));
+        paths.push(path);
+    }
+    while let Some(path) = paths.pop() {
+        for i in (0..path.len()).rev() {
+            let target = (path[i].0.clone(), !path[i].1);
+            if covered.contains(&target) || !attempted.insert(target.clone()) {
+                continue;
+            }
+            for index in 0..combinations {
+                if runs == budget {
+                    return inputs;
+                }
+                runs += 1;
+                let env = input(index);
+                let taken = branches_taken(stmt, &env);
+                if taken.len() > i && taken[..i] == path[..i] && taken[i] == target {
+                    covered.extend(taken.iter().cloned());
+                    inputs.push(env);
+                    paths.push(taken);
+                    break;
+                }
+            }
+        }
+    }
+    inputs
+}
+
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+enum Label {
+    Low,
+    High,
+}
+
+/// A write that would let High data reach a Low variable.
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct FlowViolation {
+    /// The statement doing the write, as in `Stmt::at`.
+    path: Vec<usize>,
+    stmt: Stmt,
+    variable: String,
+    /// Whether the data only flows through an enclosing condition, not
+    /// the written value itself.
+    implicit: bool,
+}
+
+impl fmt::Display for FlowViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "`{}` at {:?} leaks High data into Low variable `{}`",
+            self.stmt, self.path, self.variable
+        )?;
+        if self.implicit {
+            write!(f, " through an enclosing condition")?;
+        }
+        Ok(())
+    }
+}
+
+/// Tracks the label of the control flow (`pc`) as the checker walks down
+/// into conditionals and loops.
+#[allow(dead_code)]
+struct FlowChecker<'a> {
+    labels: &'a HashMap<String, Label>,
+    path: Vec<usize>,
+    violations: Vec<FlowViolation>,
+}
+
+#[allow(dead_code)]
+impl FlowChecker<'_> {
+    fn label(&self, name: &str) -> Label {
+        self.labels.get(name).copied().unwrap_or(Label::Low)
+    }
+
+    fn write(&mut self, stmt: &Stmt, name: &str, value: Label, pc: Label) {
+        if value.max(pc) > self.label(name) {
+            self.violations.push(FlowViolation {
+                path: self.path.clone(),
+                stmt: stmt.clone(),
+                variable: name.into(),
+                implicit: value < pc,
+            });
+        }
+    }
+
+    /// The label of the data `expr` evaluates to, checking the writes it
+    /// makes along the way.
+    fn expr(&mut self, stmt: &Stmt, expr: &Expr, pc: Label) -> Label {
+        match expr {
+            Expr::Number(_) | Expr::Boolean(_) => Label::Low,
+            Expr::Variable(name) => self.label(name),
+            Expr::PreIncrement(name) | Expr::PostIncrement(name) => {
+                let label = self.label(name);
+                self.write(stmt, name, label, pc);
+                label
+            }
+            Expr::AssignExpr(name, value) => {
+                let label = self.expr(stmt, value, pc);
+                self.write(stmt, name, label, pc);
+                label
+            }
+            Expr::Not(operand) => self.expr(stmt, operand, pc),
+            Expr::Add(l, r)
+            | Expr::Subtract(l, r)
+            | Expr::Multiply(l, r)
+            | Expr::Divide(l, r)
+            | Expr::Modulo(l, r)
+            | Expr::LessThan(l, r)
+            | Expr::GreaterThan(l, r)
+            | Expr::LessThanOrEqual(l, r)
+            | Expr::GreaterThanOrEqual(l, r)
+            | Expr::Equal(l, r)
+            | Expr::NotEqual(l, r)
+            | Expr::And(l, r)
+            | Expr::Or(l, r) => self.expr(stmt, l, pc).max(self.expr(stmt, r, pc)),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, pc: Label) {
+        match stmt {
+            Stmt::DoNothing => {}
+            Stmt::Assign(name, expr) => {
+                let label = self.expr(stmt, expr, pc);
+                self.write(stmt, name, label, pc);
+            }
+            Stmt::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let pc = pc.max(self.expr(stmt, condition, pc));
+                self.child(0, consequence, pc);
+                self.child(1, alternative, pc);
+            }
+            Stmt::Sequence { first, second } => {
+                self.child(0, first, pc);
+                self.child(1, second, pc);
+            }
+            Stmt::While { condition, body } => {
+                // Every run of the condition after the first happens under
+                // its own label, so its writes are checked under that.
+                let checked = self.violations.len();
+                let pc = pc.max(self.expr(stmt, condition, pc));
+                self.violations.truncate(checked);
+                self.expr(stmt, condition, pc);
+                self.child(0, body, pc);
+            }
+        }
+    }
+
+    fn child(&mut self, i: usize, stmt: &Stmt, pc: Label) {
+        self.path.push(i);
+        self.stmt(stmt, pc);
+        self.path.pop();
+    }
+}
+
+/// Checks that no High data can reach a Low variable, either directly
+/// through an assigned value or implicitly through a write made under a
+/// High `If` or `While` condition. Variables missing from `labels` are
+/// Low. Termination and timing channels are not considered.
+#[allow(dead_code)]
+fn check_information_flow(
+    stmt: &Stmt,
+    labels: &HashMap<String, Label>,
+) -> Result<(), Vec<FlowViolation>> {
+    let mut checker = FlowChecker {
+        labels,
+        path: vec![],
+        violations: vec![],
+    };
+    checker.stmt(stmt, Label::Low);
+    if checker.violations.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.violations)
+    }
+}
+
+/// Hands out temporary names that do not clash with any variable of the
+/// program being transformed.
+#[allow(dead_code)]
+struct Fresh {
+    taken: HashSet<String>,
+    next: usize,
+}
+
+#[allow(dead_code)]
+impl Fresh {
+    fn new(stmt: &Stmt) -> Self {
+        Self {
+            taken: stmt.variables(),
+            next: 0,
+        }
+    }
+
+    fn name(&mut self) -> String {
+        loop {
+            let name = format!("t{}", self.next);
+            self.next += 1;
+            if !self.taken.contains(&name) {
+                return name;
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub(crate) fn sequence(stmts: Vec<Stmt>) -> Stmt {
+    stmts
+        .into_iter()
+        .rev()
+        .reduce(|second, first| Stmt::Sequence {
+            first: first.into(),
+            second: second.into(),
+        })
+        .unwrap_or(Stmt::DoNothing)
+}
+
+/// Nests every sequence to the left, so `a; (b; c)` becomes `(a; b); c`.
+/// Sequencing is associative, so the program behaves the same.
+#[allow(dead_code)]
+fn left_nest(stmt: &Stmt) -> Stmt {
+    match stmt {
+        Stmt::DoNothing | Stmt::Assign(..) => stmt.clone(),
+        Stmt::If {
+            condition,
+            consequence,
+            alternative,
+        } => Stmt::If {
+            condition: condition.clone(),
+            consequence: left_nest(consequence).into(),
+            alternative: left_nest(alternative).into(),
+        },
+        Stmt::Sequence { .. } => flatten(stmt)
+            .iter()
+            .map(left_nest)
+            .reduce(|first, second| Stmt::Sequence {
+                first: first.into(),
+                second: second.into(),
+            })
+            .unwrap(),
+        Stmt::While { condition, body } => Stmt::While {
+            condition: condition.clone(),
+            body: left_nest(body).into(),
+        },
+    }
+}
+
+/// Renames every variable to `v0`, `v1`, ... in order of first appearance,
+/// skipping names the program already uses, and with `restructure` set
+/// also re-nests its sequences. Returns the new program with the mapping
+/// from old names to new ones.
+#[allow(dead_code)]
+fn obfuscate(stmt: &Stmt, restructure: bool) -> (Stmt, HashMap<String, String>) {
+    let mut seen: Vec<String> = vec![];
+    for name in Fresh::new(stmt).taken {
+        if !seen.contains(&name) {
+            seen.push(name);
+        }
+    }
+    let mut names = HashMap::new();
+    let mut next = 0;
+    for name in &seen {
+        let renamed = loop {
+            let candidate = format!("v{}", next);
+            next += 1;
+            if !seen.contains(&candidate) {
+                break candidate;
+            }
+        };
+        names.insert(name.clone(), renamed);
+    }
+    let renamed = stmt.rename(&names);
+    let renamed = if restructure {
+        left_nest(&renamed)
+    } else {
+        renamed
+    };
+    (renamed, names)
+}
+
+/// Runs `original` and `obfuscated` from every input, renaming the input
+/// and the final environment through `names`, and returns the first input
+/// whose observable behavior differs. Running out of steps counts as
+/// behavior, so diverging programs must diverge on the same inputs.
+#[allow(dead_code)]
+fn preserves_behavior(
+    original: &Stmt,
+    obfuscated: &Stmt,
+    names: &HashMap<String, String>,
+    inputs: &[Environment],
+    step_limit: usize,
+) -> Result<(), Environment> {
+    let rename = |env: &Environment| -> Environment {
+        env.iter()
+            .map(|(name, value)| (names.get(name).unwrap_or(name).clone(), value.clone()))
+            .collect()
+    };
+    for input in inputs {
+        let expected = original
+            .evalute_with_limit(input.clone(), step_limit)
+            .map(|env| rename(&env));
+        let actual = obfuscated.evalute_with_limit(rename(input), step_limit);
+        if expected != actual {
+            return Err(input.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Reduces `expr` to an atom (a literal or a variable), emitting the
+/// assignments that compute it into `out`. With `capture_reads` set,
+/// variables are copied into temporaries as soon as they are read, so a
+/// later side effect in the same expression cannot change the value.
+#[allow(dead_code)]
+fn atomize(expr: &Expr, fresh: &mut Fresh, out: &mut Vec<Stmt>, capture_reads: bool) -> Expr {
+    match expr {
+        Expr::Number(_) | Expr::Boolean(_) => expr.clone(),
+        Expr::Variable(_) if !capture_reads => expr.clone(),
+        Expr::Variable(_) => {
+            let temp = fresh.name();
+            out.push(Stmt::Assign(temp.clone(), expr.clone()));
+            Expr::Variable(temp)
+        }
+        Expr::AssignExpr(name, value) => {
+            let value = atomize(value, fresh, out, capture_reads);
+            out.push(Stmt::Assign(name.clone(), value.clone()));
+            value
+        }
+        _ => {
+            let value = anf_expr(expr, fresh, out, capture_reads);
+            let temp = fresh.name();
+            out.push(Stmt::Assign(temp.clone(), value));
+            Expr::Variable(temp)
+        }
+    }
+}
+
+/// Like `atomize`, but leaves one operator application with atomic
+/// operands in place.
+#[allow(dead_code)]
+fn anf_expr(expr: &Expr, fresh: &mut Fresh, out: &mut Vec<Stmt>, capture_reads: bool) -> Expr {
+    match expr {
+        Expr::Add(l, r)
+        | Expr::Subtract(l, r)
+        | Expr::Multiply(l, r)
+        | Expr::Divide(l, r)
+        | Expr::Modulo(l, r)
+        | Expr::LessThan(l, r)
+        | Expr::GreaterThan(l, r)
+        | Expr::LessThanOrEqual(l, r)
+        | Expr::GreaterThanOrEqual(l, r)
+        | Expr::Equal(l, r)
+        | Expr::NotEqual(l, r) => {
+            let l = atomize(l, fresh, out, capture_reads).into();
+            let r = atomize(r, fresh, out, capture_reads).into();
+            match expr {
+                Expr::Add(..) => Expr::Add(l, r),
+                Expr::Subtract(..) => Expr::Subtract(l, r),
+                Expr::Multiply(..) => Expr::Multiply(l, r),
+                Expr::Divide(..) => Expr::Divide(l, r),
+                Expr::Modulo(..) => Expr::Modulo(l, r),
+                Expr::GreaterThan(..) => Expr::GreaterThan(l, r),
+                Expr::LessThanOrEqual(..) => Expr::LessThanOrEqual(l, r),
+                Expr::GreaterThanOrEqual(..) => Expr::GreaterThanOrEqual(l, r),
+                Expr::Equal(..) => Expr::Equal(l, r),
+                Expr::NotEqual(..) => Expr::NotEqual(l, r),
+                _ => Expr::LessThan(l, r),
+            }
+        }
+        Expr::Not(operand) => Expr::Not(atomize(operand, fresh, out, capture_reads).into()),
+        // The right operand's assignments must only run when it is
+        // needed, so they go inside an `if` on the left operand.
+        Expr::And(l, r) | Expr::Or(l, r) => {
+            let temp = fresh.name();
+            let l = anf_expr(l, fresh, out, capture_reads);
+            out.push(Stmt::Assign(temp.clone(), l));
+            let mut right = vec![];
+            let r = anf_expr(r, fresh, &mut right, capture_reads);
+            right.push(Stmt::Assign(temp.clone(), r));
+            let (consequence, alternative) = match expr {
+                Expr::And(..) => (sequence(right), Stmt::DoNothing),
+                _ => (Stmt::DoNothing, sequence(right)),
+            };
+            out.push(Stmt::If {
+                condition: Expr::Variable(temp.clone()),
+                consequence: consequence.into(),
+                alternative: alternative.into(),
+            });
+            Expr::Variable(temp)
+        }
+        Expr::PreIncrement(name) => {
+            let variable = Box::new(Expr::Variable(name.clone()));
+            out.push(Stmt::Assign(
+                name.clone(),
+                Expr::Add(variable.clone(), Expr::Number(1).into()),
+            ));
+            *variable
+        }
+        Expr::PostIncrement(name) => {
+            let temp = fresh.name();
+            out.push(Stmt::Assign(temp.clone(), Expr::Variable(name.clone())));
+            out.push(Stmt::Assign(
+                name.clone(),
+                Expr::Add(Expr::Variable(temp.clone()).into(), Expr::Number(1).into()),
+            ));
+            Expr::Variable(temp)
+        }
+        _ => atomize(expr, fresh, out, capture_reads),
+    }
+}
+
+#[allow(dead_code)]
+fn anf_stmt(stmt: &Stmt, fresh: &mut Fresh) -> Stmt {
+    let mut out = vec![];
+    match stmt {
+        Stmt::DoNothing => return Stmt::DoNothing,
+        Stmt::Assign(name, expr) => {
+            let expr = anf_expr(expr, fresh, &mut out, expr.has_side_effects());
+            out.push(Stmt::Assign(name.clone(), expr));
+        }
+        Stmt::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            let condition = atomize(condition, fresh, &mut out, condition.has_side_effects());
+            out.push(Stmt::If {
+                condition,
+                consequence: anf_stmt(consequence, fresh).into(),
+                alternative: anf_stmt(alternative, fresh).into(),
+            });
+        }
+        Stmt::Sequence { first, second } => {
+            out.push(anf_stmt(first, fresh));
+            out.push(anf_stmt(second, fresh));
+        }
+        Stmt::While { condition, body } => {
+            // The condition is recomputed before every test: once before
+            // the loop and again at the end of each iteration.
+            let mut test = vec![];
+            let condition = atomize(condition, fresh, &mut test, condition.has_side_effects());
+            out.extend(test.clone());
+            let mut body = vec![anf_stmt(body, fresh)];
+            body.extend(test);
+            out.push(Stmt::While {
+                condition,
+                body: sequence(body).into(),
+            });
+        }
+    }
+    sequence(out)
+}
+
+/// A-normal form: every operator is applied to literals or variables
+/// only, with intermediate results named by fresh temporaries `t0`, `t1`,
+/// ... and conditions reduced to a single variable or literal.
+#[allow(dead_code)]
+fn to_anf(stmt: &Stmt) -> Stmt {
+    anf_stmt(stmt, &mut Fresh::new(stmt))
+}
+
+/// A transformation, given the analyses of the program it transforms.
+#[allow(dead_code)]
+type Pass = fn(&Stmt, &mut Analyses) -> Stmt;
+
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct PassStats {
+    name: &'static str,
+    size_before: usize,
+    size_after: usize,
+    changed: bool,
+    /// How many analyses the pass computed rather than reused.
+    analyses_computed: usize,
+}
+
+/// What passes know about the program a pipeline is transforming. Each
+/// analysis is computed when a pass first asks for it and kept until a
+/// pass changes the program.
+#[allow(dead_code)]
+struct Analyses {
+    /// The variables of the program the pipeline started from, whose
+    /// final values passes must keep. Any other variable is a temporary
+    /// a pass introduced.
+    observable: HashSet<String>,
+    read: Option<HashSet<String>>,
+    computed: usize,
+}
+
+#[allow(dead_code)]
+impl Analyses {
+    fn new(stmt: &Stmt) -> Self {
+        Self {
+            observable: stmt.variables(),
+            read: None,
+            computed: 0,
+        }
+    }
+
+    /// The variables some expression of `stmt` reads.
+    fn read(&mut self, stmt: &Stmt) -> &HashSet<String> {
+        if self.read.is_none() {
+            self.computed += 1;
+        }
+        self.read.get_or_insert_with(|| {
+            stmt.subexprs()
+                .filter_map(|expr| match expr {
+                    Expr::Variable(name) | Expr::PreIncrement(name) | Expr::PostIncrement(name) => {
+                        Some(name.clone())
+                    }
+                    _ => None,
+                })
+                .collect()
+        })
+    }
+
+    /// Forgets every analysis, after a pass changed the program.
+    fn invalidate(&mut self) {
+        self.read = None;
+    }
+}
+
+/// Runs a fixed sequence of named transformations, feeding each one the
+/// output of the previous and recording how each changed the program.
+#[allow(dead_code)]
+struct PassManager {
+    passes: Vec<(&'static str, Pass)>,
+}
+
+#[allow(dead_code)]
+impl PassManager {
+    fn new() -> Self {
+        Self { passes: vec![] }
+    }
+
+    /// The standard pipeline: fold constants, lower to ANF, fold the
+    /// temporaries that became constant, then drop the ones left unread.
+    fn standard() -> Self {
+        Self::new()
+            .add("simplify", |stmt, _| simplify(stmt))
+            .add("anf", |stmt, _| to_anf(stmt))
+            .add("cse", |stmt, _| eliminate_common_subexpressions(stmt))
+            .add("constant-propagation", |stmt, _| simplify(stmt))
+            .add("dce", eliminate_dead_code)
+    }
+
+    fn add(mut self, name: &'static str, pass: Pass) -> Self {
+        self.passes.push((name, pass));
+        self
+    }
+
+    fn run(&self, stmt: &Stmt) -> (Stmt, Vec<PassStats>) {
+        let mut stmt = stmt.clone();
+        let mut analyses = Analyses::new(&stmt);
+        // The size of the current program is only recomputed when a pass
+        // actually changed it.
+        let mut size = stmt.size();
+        let mut stats = vec![];
+        for (name, pass) in &self.passes {
+            let computed = analyses.computed;
+            let transformed = pass(&stmt, &mut analyses);
+            let changed = transformed != stmt;
+            let size_after = if changed { transformed.size() } else { size };
+            if changed {
+                analyses.invalidate();
+            }
+            stats.push(PassStats {
+                name,
+                size_before: size,
+                size_after,
+                changed,
+                analyses_computed: analyses.computed - computed,
+            });
+            stmt = transformed;
+            size = size_after;
+        }
+        (stmt, stats)
+    }
+}
+
+/// Drops assignments to temporaries that nothing reads. Assignments whose
+/// expression could fail are kept, so the program still fails where it
+/// did.
+#[allow(dead_code)]
+fn eliminate_dead_code(stmt: &Stmt, analyses: &mut Analyses) -> Stmt {
+    fn dce(stmt: &Stmt, dead: &dyn Fn(&str) -> bool) -> Stmt {
+        match stmt {
+            Stmt::Assign(name, expr)
+                if dead(name) && !expr.has_side_effects() && !expr.can_fail() =>
+            {
+                Stmt::DoNothing
+            }
+            Stmt::DoNothing | Stmt::Assign(..) => stmt.clone(),
+            Stmt::If {
+                condition,
+                consequence,
+                alternative,
+            } => Stmt::If {
+                condition: condition.clone(),
+                consequence: dce(consequence, dead).into(),
+                alternative: dce(alternative, dead).into(),
+            },
+            Stmt::Sequence { .. } => sequence(
+                flatten(stmt)
+                    .iter()
+                    .map(|stmt| dce(stmt, dead))
+                    .filter(|stmt| *stmt != Stmt::DoNothing)
+                    .collect(),
+            ),
+            Stmt::While { condition, body } => Stmt::While {
+                condition: condition.clone(),
+                body: dce(body, dead).into(),
+            },
+        }
+    }
+    let read = analyses.read(stmt).clone();
+    let observable = &analyses.observable;
+    dce(stmt, &|name| {
+        !observable.contains(name) && !read.contains(name)
+    })
+}
+
+#[allow(dead_code)]
+fn reads(expr: &Expr, name: &str) -> bool {
+    expr.subexprs()
+        .any(|expr| matches!(expr, Expr::Variable(read) if read == name))
+}
+
+/// `available` maps pure operator expressions to a variable that
+/// currently holds their value.
+#[allow(dead_code)]
+fn cse(stmt: &Stmt, available: &mut Vec<(Expr, String)>) -> Stmt {
+    match stmt {
+        Stmt::DoNothing => Stmt::DoNothing,
+        Stmt::Assign(name, expr) => {
+            let reusable = expr.operator().is_some() && !expr.has_side_effects();
+            let rewritten = match available
+                .iter()
+                .find(|(known, _)| reusable && known == expr)
+            {
+                Some((_, holder)) => Expr::Variable(holder.clone()),
+                None => expr.clone(),
+            };
+            available.retain(|(known, holder)| holder != name && !reads(known, name));
+            if expr.has_side_effects() {
+                available.clear();
+            }
+            if reusable && !reads(expr, name) {
+                available.push((expr.clone(), name.clone()));
+            }
+            Stmt::Assign(name.clone(), rewritten)
+        }
+        Stmt::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            // The condition runs before either branch, so its writes can
+            // change what the available expressions evaluate to.
+            if condition.has_side_effects() {
+                available.clear();
+            }
+            let stmt = Stmt::If {
+                condition: condition.clone(),
+                consequence: cse(consequence, &mut available.clone()).into(),
+                alternative: cse(alternative, &mut available.clone()).into(),
+            };
+            available.clear();
+            stmt
+        }
+        Stmt::Sequence { first, second } => Stmt::Sequence {
+            first: cse(first, available).into(),
+            second: cse(second, available).into(),
+        },
+        Stmt::While { condition, body } => {
+            // The body may run after earlier iterations changed anything
+            // it assigns, so nothing is assumed to be available inside it.
+            available.clear();
+            Stmt::While {
+                condition: condition.clone(),
+                body: cse(body, &mut vec![]).into(),
+            }
+        }
+    }
+}
+
+/// Common subexpression elimination over straight-line code: an
+/// assignment that recomputes an operator expression whose value is
+/// still held by an earlier variable copies that variable instead. Most
+/// effective after `to_anf`, which gives every subexpression a name.
+#[allow(dead_code)]
+fn eliminate_common_subexpressions(stmt: &Stmt) -> Stmt {
+    cse(stmt, &mut vec![])
+}
+
+#[allow(dead_code)]
+fn flatten(stmt: &Stmt) -> Vec<Stmt> {
+    match stmt {
+        Stmt::Sequence { first, second } => {
+            let mut stmts = flatten(first);
+            stmts.extend(flatten(second));
+            stmts
+        }
+        _ => vec![stmt.clone()],
+    }
+}
+
+/// Unrolls every loop whose condition has no side effects by `factor`:
+/// each iteration runs the body, then up to `factor - 1` more copies
+/// guarded by the condition. Loops with statically known bounds can be
+/// unrolled completely with `specialize`.
+#[allow(dead_code)]
+fn unroll_loops(stmt: &Stmt, factor: usize) -> Stmt {
+    match stmt {
+        Stmt::DoNothing | Stmt::Assign(..) => stmt.clone(),
+        Stmt::If {
+            condition,
+            consequence,
+            alternative,
+        } => Stmt::If {
+            condition: condition.clone(),
+            consequence: unroll_loops(consequence, factor).into(),
+            alternative: unroll_loops(alternative, factor).into(),
+        },
+        Stmt::Sequence { first, second } => Stmt::Sequence {
+            first: unroll_loops(first, factor).into(),
+            second: unroll_loops(second, factor).into(),
+        },
+        Stmt::While { condition, body } => {
+            let body = unroll_loops(body, factor);
+            if condition.has_side_effects() || factor < 2 {
+                return Stmt::While {
+                    condition: condition.clone(),
+                    body: body.into(),
+                };
+            }
+            let mut unrolled = body.clone();
+            for _ in 1..factor {
+                unrolled = Stmt::Sequence {
+                    first: body.clone().into(),
+                    second: Stmt::If {
+                        condition: condition.clone(),
+                        consequence: unrolled.into(),
+                        alternative: Stmt::DoNothing.into(),
+                    }
+                    .into(),
+                };
+            }
+            Stmt::While {
+                condition: condition.clone(),
+                body: unrolled.into(),
+            }
+        }
+    }
+}
+
+/// Loop-invariant code motion. A top-level assignment `t = e` in a loop
+/// body moves in front of the loop when `e` is pure and none of its
+/// variables change in the loop, nothing else in the loop assigns `t`,
+/// and neither the condition nor the statements before it read `t`. The
+/// loop is guarded by its condition so `t` is still only assigned when
+/// the loop runs at least once.
+#[allow(dead_code)]
+fn hoist_invariants(stmt: &Stmt) -> Stmt {
+    match stmt {
+        Stmt::DoNothing | Stmt::Assign(..) => stmt.clone(),
+        Stmt::If {
+            condition,
+            consequence,
+            alternative,
+        } => Stmt::If {
+            condition: condition.clone(),
+            consequence: hoist_invariants(consequence).into(),
+            alternative: hoist_invariants(alternative).into(),
+        },
+        Stmt::Sequence { first, second } => Stmt::Sequence {
+            first: hoist_invariants(first).into(),
+            second: hoist_invariants(second).into(),
+        },
+        Stmt::While { condition, body } => {
+            let body = hoist_invariants(body);
+            let stmts = flatten(&body);
+            let reads_stmt = |stmt: &Stmt, name: &str| stmt.subexprs().any(|e| reads(e, name));
+            let mut hoisted = vec![];
+            let mut kept: Vec<Stmt> = vec![];
+            for (i, stmt) in stmts.iter().enumerate() {
+                let invariant = match stmt {
+                    Stmt::Assign(name, expr) => {
+                        !condition.has_side_effects()
+                            && !expr.has_side_effects()
+                            && !reads(condition, name)
+                            && !expr.subexprs().any(|e| match e {
+                                Expr::Variable(read) => {
+                                    read == name || stmts.iter().any(|s| s.assigns(read))
+                                }
+                                _ => false,
+                            })
+                            && stmts
+                                .iter()
+                                .enumerate()
+                                .all(|(j, other)| j == i || !other.assigns(name))
+                            && !kept.iter().any(|before| reads_stmt(before, name))
+                    }
+                    _ => false,
+                };
+                if invariant {
+                    hoisted.push(stmt.clone());
+                } else {
+                    kept.push(stmt.clone());
+                }
+            }
+            let loop_stmt = Stmt::While {
+                condition: condition.clone(),
+                body: sequence(kept).into(),
+            };
+            if hoisted.is_empty() {
+                return loop_stmt;
+            }
+            hoisted.push(loop_stmt);
+            Stmt::If {
+                condition: condition.clone(),
+                consequence: sequence(hoisted).into(),
+                alternative: Stmt::DoNothing.into(),
+            }
+        }
+    }
+}
+
+/// How many loop iterations `simplify` unrolls before it keeps the rest
+/// of a loop as it is.
+const SIMPLIFY_UNROLLS: usize = 64;
+
+/// Folds everything that does not depend on the initial environment.
+/// Loops whose condition is known are unrolled, up to `SIMPLIFY_UNROLLS`
+/// iterations in all, so a long or infinite loop stays a loop.
+#[allow(dead_code)]
+fn simplify(stmt: &Stmt) -> Stmt {
+    let mut unrolls = SIMPLIFY_UNROLLS;
+    stmt.specialize_within(Environment::new(), &mut unrolls).0
+}
+
+/// Splits a sequence into consecutive groups of pairwise independent
+/// statements, which may run in parallel within a group.
+#[allow(dead_code)]
+fn independent_groups(stmt: &Stmt) -> Vec<Vec<Stmt>> {
+    let mut groups: Vec<Vec<Stmt>> = vec![];
+    for stmt in flatten(stmt) {
+        match groups.last_mut() {
+            Some(group) if group.iter().all(|other| other.independent_of(&stmt)) => {
+                group.push(stmt)
+            }
+            _ => groups.push(vec![stmt]),
+        }
+    }
+    groups
+}
+
+/// Maps `f` over `items` on scoped threads, no more of them than
+/// `available_parallelism` allows, and keeps the results in the order of
+/// `items`. A worker that panics passes its panic on unchanged.
+#[allow(dead_code)]
+fn parallel_map<T: Send, U: Send>(items: Vec<T>, f: impl Fn(T) -> U + Sync) -> Vec<U> {
+    let workers = thread::available_parallelism().map_or(1, usize::from);
+    let chunk_size = items.len().div_ceil(workers).max(1);
+    let mut items = items.into_iter().peekable();
+    let mut chunks = vec![];
+    while items.peek().is_some() {
+        chunks.push(items.by_ref().take(chunk_size).collect::<Vec<_>>());
+    }
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| scope.spawn(move || chunk.into_iter().map(f).collect::<Vec<_>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| panic::resume_unwind(panic))
+            })
+            .collect()
+    })
+}
+
+/// Runs `stmt` with each group from `independent_groups` spread over
+/// `parallel_map`, merging each statement's writes back into one
+/// environment before the next group starts.
+#[allow(dead_code)]
+fn evaluate_independent_parallel(
+    stmt: &Stmt,
+    mut env: Environment,
+) -> Result<Environment, EvalError> {
+    for group in independent_groups(stmt) {
+        if group.len() == 1 {
+            env = group[0].evalute_in(env, &Checked)?;
+            continue;
+        }
+        let results = parallel_map(group.iter().collect(), |stmt| {
+            stmt.evalute_in(env.clone(), &Checked)
+        });
+        for (stmt, result) in group.iter().zip(results) {
+            let result = result?;
+            for name in stmt.write_set() {
+                if let Ok(value) = result.get(&name) {
+                    env.set(&name, value.clone());
+                }
+            }
+        }
+    }
+    Ok(env)
+}
+
+/// Runs `stmt` from every environment in `envs` with `parallel_map` and
+/// returns the final environments in the same order, or the first error.
+/// The AST only owns its children through `Box`, so one program can be
+/// borrowed by all threads at once.
+#[allow(dead_code)]
+fn evaluate_parallel(stmt: &Stmt, envs: Vec<Environment>) -> Result<Vec<Environment>, EvalError> {
+    parallel_map(envs, |env| stmt.evalute_in(env, &Checked))
+        .into_iter()
+        .collect()
+}
+
+/// Grades `stmt` against every input in `envs`, giving each case its own
+/// `step_limit` so one diverging input cannot hold up the rest. With
+/// `parallel` set, cases run through `parallel_map`.
+#[allow(dead_code)]
+fn evaluate_batch(
+    stmt: &Stmt,
+    envs: Vec<Environment>,
+    step_limit: usize,
+    parallel: bool,
+) -> Vec<Result<Environment, EvalError>> {
+    let grade = |env| stmt.evalute_with_limit(env, step_limit);
+    if parallel {
+        parallel_map(envs, grade)
+    } else {
+        envs.into_iter().map(grade).collect()
+    }
+}
+
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            Self::DoNothing => write!(f, "do-nothing"),
+            Self::Assign(name, expr) => write!(f, "{} = {}", name, expr),
+            Self::If {
+                condition,
+                consequence,
+                alternative,
+            } => write!(
+                f,
+                "if ({}) {{ {} }} else {{ {} }}",
+                condition, consequence, alternative
+            ),
+            Self::Sequence { first, second } => write!(f, "{}; {}", first, second),
+            Self::While { condition, body } => write!(f, "while ({}) {{ {} }}", condition, body),
+        }
+    }
+}
+
+impl fmt::Debug for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "<<{}>>", self)
+    }
+}
+
+fn main() {
+    let mut env = Environment::new();
+    println!("{}", Expr::Number(1).evalute(&mut env));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asserts that two environments hold the same bindings, listing only the
+    /// bindings that differ on failure.
+    macro_rules! assert_env_eq {
+        ($expected:expr, $actual:expr $(,)?) => {
+            let diff = diff_environments(&$expected, &$actual);
+            if !diff.is_empty() {
+                panic!("environments differ (expected -> actual):\n{}", diff);
+            }
+        };
+    }
+
+    #[test]
+    fn evalute_number() {
+        let n = Expr::Number(23);
+        let mut env = Environment::new();
+        assert_eq!(n, n.evalute(&mut env));
+    }
+
+    #[test]
+    fn evalute_boolean() {
+        let t = Expr::Boolean(true);
+        let mut env = Environment::new();
+        assert_eq!(t, t.evalute(&mut env));
+
+        let f = Expr::Boolean(false);
+        let mut env = Environment::new();
+        assert_eq!(f, f.evalute(&mut env));
+    }
+
+    #[test]
+    fn evalute_add() {
+        let expr = Expr::Add(Expr::Number(1).into(), Expr::Number(2).into());
+        let mut env = Environment::new();
+        assert_eq!(Expr::Number(3), expr.evalute(&mut env));
+    }
+
+    #[test]
+    fn evalute_multiply() {
+        let expr = Expr::Multiply(Expr::Number(2).into(), Expr::Number(3).into());
+        let mut env = Environment::new();
+        assert_eq!(Expr::Number(6), expr.evalute(&mut env));
+    }
+
+    #[test]
+    fn evalute_less_than() {
+        let expr = Expr::LessThan(
+            Expr::Add(Expr::Variable("x".into()).into(), Expr::Number(2).into()).into(),
+            Expr::Variable("y".into()).into(),
+        );
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(2));
+        env.set("y", Expr::Number(5));
+        assert_eq!(Expr::Boolean(true), expr.evalute(&mut env));
+    }
+
+    #[test]
+    fn evalute_side_effects_left_to_right() {
+        // y = x + x++  vs  y = x++ + x, starting from x = 1; the big-step
+        // rules pick the same left-to-right order as the small-step machine.
+        let x = || Box::new(Expr::Variable("x".into()));
+        let x_inc = || Box::new(Expr::PostIncrement("x".into()));
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(1));
+
+        let env_left = Stmt::Assign("y".into(), Expr::Add(x(), x_inc())).evalute(env.clone());
+        assert_eq!(Ok(&Expr::Number(2)), env_left.get("y"));
+        assert_eq!(Ok(&Expr::Number(2)), env_left.get("x"));
+
+        let env_right = Stmt::Assign("y".into(), Expr::Add(x_inc(), x())).evalute(env);
+        assert_eq!(Ok(&Expr::Number(3)), env_right.get("y"));
+        assert_eq!(Ok(&Expr::Number(2)), env_right.get("x"));
+    }
+
+    #[test]
+    fn evalute_assign_expr_and_pre_increment() {
+        // y = (x = 3) * ++x
+        let expr = Expr::Multiply(
+            Expr::AssignExpr("x".into(), Expr::Number(3).into()).into(),
+            Expr::PreIncrement("x".into()).into(),
+        );
+        let mut env = Environment::new();
+        assert_eq!(Expr::Number(12), expr.evalute(&mut env));
+        assert_eq!(Ok(&Expr::Number(4)), env.get("x"));
+
+        // ++b and b++, with b a boolean
+        env.set("b", Expr::Boolean(true));
+        for increment in [
+            Expr::PreIncrement("b".into()),
+            Expr::PostIncrement("b".into()),
+        ] {
+            assert_eq!(
+                Err(EvalError::TypeMismatch(increment.clone())),
+                increment.evalute_in(&mut env, &Checked)
+            );
+            assert_eq!(Ok(&Expr::Boolean(true)), env.get("b"));
+        }
+    }
+
+    #[test]
+    fn specialize_keeps_side_effects() {
+        // x = 1; y = x++; z = x
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign("y".into(), Expr::PostIncrement("x".into())).into(),
+            second: Stmt::Assign("z".into(), Expr::Variable("x".into())).into(),
+        };
+        let mut known = Environment::new();
+        known.set("x", Expr::Number(1));
+        let residual = specialize(&stmt, known.clone());
+        assert_eq!("y = x++; z = x", residual.to_string());
+        assert_env_eq!(stmt.evalute(known.clone()), residual.evalute(known));
+    }
+
+    #[test]
+    fn evalute_donothing() {
+        let stmt = Stmt::DoNothing;
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(2));
+        assert_env_eq!(env.clone(), stmt.evalute(env));
+    }
+
+    #[test]
+    fn evalute_assign() {
+        let stmt = Stmt::Assign("x".into(), Expr::Number(1));
+        let mut env = Environment::new();
+        env.set("y", Expr::Number(2));
+        let mut expected = env.clone();
+        expected.set("x", Expr::Number(1));
+        assert_env_eq!(expected, stmt.evalute(env));
+    }
+
+    #[test]
+    fn evalute_if() {
+        let stmt = Stmt::If {
+            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(3).into()),
+            consequence: Stmt::Assign(
+                "y".into(),
+                Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(2).into()),
+            )
+            .into(),
+            alternative: Stmt::DoNothing.into(),
+        };
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(2));
+        let mut expected = env.clone();
+        expected.set("y", Expr::Number(4));
+        assert_env_eq!(expected, stmt.evalute(env));
+    }
+
+    #[test]
+    fn evalute_sequence() {
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign("x".into(), Expr::Number(2)).into(),
+            second: Stmt::Assign(
+                "y".into(),
+                Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(2).into()),
+            )
+            .into(),
+        };
+        let env = Environment::new();
+        let mut expected = env.clone();
+        expected.set("x", Expr::Number(2));
+        expected.set("y", Expr::Number(4));
+        assert_env_eq!(expected, stmt.evalute(env));
+    }
+
+    #[test]
+    fn evalute_while() {
+        let stmt = Stmt::While {
+            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(5).into()),
+            body: Stmt::Assign(
+                "x".into(),
+                Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(3).into()),
+            )
+            .into(),
+        };
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(1));
+
+        let mut expected = env.clone();
+        expected.set("x", Expr::Number(9));
+        assert_env_eq!(expected, stmt.evalute(env));
+    }
+
+    #[test]
+    fn non_boolean_condition_is_a_type_mismatch() {
+        // if (1) { x = 1 } else { do-nothing }
+        let branch = Stmt::If {
+            condition: Expr::Number(1),
+            consequence: Stmt::Assign("x".into(), Expr::Number(1)).into(),
+            alternative: Stmt::DoNothing.into(),
+        };
+        // while (5) { do-nothing }
+        let forever = Stmt::While {
+            condition: Expr::Number(5),
+            body: Stmt::DoNothing.into(),
+        };
+        for (stmt, condition) in [(branch, 1), (forever, 5)] {
+            let expected = Err(EvalError::TypeMismatch(Expr::Number(condition)));
+            assert_eq!(expected, stmt.evalute_with_limit(Environment::new(), 100));
+            assert_eq!(
+                expected.map(|_: Environment| TaintReport::default()),
+                track_taint(&stmt, Environment::new(), &[], 100)
+            );
+        }
+    }
+
+    #[test]
+    fn specialize_expr() {
+        let expr = Expr::Add(
+            Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(2).into()).into(),
+            Expr::Variable("y".into()).into(),
+        );
+        let mut known = Environment::new();
+        known.set("x", Expr::Number(3));
+        assert_eq!(
+            Expr::Add(Expr::Number(6).into(), Expr::Variable("y".into()).into()),
+            expr.specialize(&known)
+        );
+
+        // An ill-typed constant is left in place for the run to report.
+        let ill_typed = Expr::Add(Expr::Boolean(true).into(), Expr::Number(1).into());
+        assert_eq!(ill_typed, ill_typed.specialize(&known));
+        let stmt = Stmt::Assign("x".into(), ill_typed);
+        assert_eq!(stmt, simplify(&stmt));
+    }
+
+    #[test]
+    fn specialize_unrolls_known_while() {
+        let stmt = Stmt::While {
+            condition: Expr::LessThan(Expr::Variable("i".into()).into(), Expr::Number(2).into()),
+            body: Stmt::Sequence {
+                first: Stmt::Assign(
+                    "s".into(),
+                    Expr::Add(
+                        Expr::Variable("s".into()).into(),
+                        Expr::Variable("n".into()).into(),
+                    ),
+                )
+                .into(),
+                second: Stmt::Assign(
+                    "i".into(),
+                    Expr::Add(Expr::Variable("i".into()).into(), Expr::Number(1).into()),
+                )
+                .into(),
+            }
+            .into(),
+        };
+        let mut known = Environment::new();
+        known.set("i", Expr::Number(0));
+        let residual = specialize(&stmt, known.clone());
+        assert_eq!("s = s + n; i = 1; s = s + n; i = 2", residual.to_string());
+
+        let mut env = known;
+        env.set("s", Expr::Number(1));
+        env.set("n", Expr::Number(5));
+        assert_env_eq!(stmt.evalute(env.clone()), residual.evalute(env));
+    }
+
+    #[test]
+    fn specialize_unknown_while() {
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign("y".into(), Expr::Number(2)).into(),
+            second: Stmt::While {
+                condition: Expr::LessThan(
+                    Expr::Variable("x".into()).into(),
+                    Expr::Variable("n".into()).into(),
+                ),
+                body: Stmt::Assign(
+                    "x".into(),
+                    Expr::Multiply(
+                        Expr::Variable("x".into()).into(),
+                        Expr::Variable("y".into()).into(),
+                    ),
+                )
+                .into(),
+            }
+            .into(),
+        };
+        let mut known = Environment::new();
+        known.set("x", Expr::Number(1));
+        let residual = specialize(&stmt, known.clone());
+        assert_eq!("y = 2; while (x < n) { x = x * 2 }", residual.to_string());
+
+        let mut env = known;
+        env.set("n", Expr::Number(10));
+        assert_env_eq!(stmt.evalute(env.clone()), residual.evalute(env));
+    }
+
+    #[test]
+    fn programs_are_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Expr>();
+        assert_send_sync::<Stmt>();
+        assert_send_sync::<Environment>();
+    }
+
+    #[test]
+    fn evaluate_parallel_matches_sequential() {
+        // while (x < 50) { x = x * 3 }
+        let stmt = Stmt::While {
+            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(50).into()),
+            body: Stmt::Assign(
+                "x".into(),
+                Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(3).into()),
+            )
+            .into(),
+        };
+        let envs: Vec<Environment> = (1..=8)
+            .map(|x| {
+                let mut env = Environment::new();
+                env.set("x", Expr::Number(x));
+                env
+            })
+            .collect();
+        let expected: Vec<_> = envs.iter().map(|env| stmt.evalute(env.clone())).collect();
+        assert_eq!(Ok(expected), evaluate_parallel(&stmt, envs.clone()));
+
+        let mut unbound = envs;
+        unbound[5].remove("x");
+        assert_eq!(
+            Err(EvalError::UndefinedVariable("x".into())),
+            evaluate_parallel(&stmt, unbound)
+        );
+    }
+
+    #[test]
+    fn parallel_map_keeps_order() {
+        let workers = thread::available_parallelism().map_or(1, usize::from);
+        let items: Vec<usize> = (0..workers * 3 + 1).collect();
+        let expected: Vec<usize> = items.iter().map(|n| n * n).collect();
+        assert_eq!(expected, parallel_map(items, |n| n * n));
+        assert_eq!(Vec::<usize>::new(), parallel_map(vec![], |n: usize| n));
+    }
+
+    #[test]
+    fn evaluate_batch_with_step_limit() {
+        // while (x < 10) { x = x + y }
+        let stmt = Stmt::While {
+            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(10).into()),
+            body: Stmt::Assign(
+                "x".into(),
+                Expr::Add(
+                    Expr::Variable("x".into()).into(),
+                    Expr::Variable("y".into()).into(),
+                ),
+            )
+            .into(),
+        };
+        let input = |y| {
+            let mut env = Environment::new();
+            env.set("x", Expr::Number(0));
+            env.set("y", Expr::Number(y));
+            env
+        };
+        let mut expected = input(5);
+        expected.set("x", Expr::Number(10));
+        for parallel in [false, true] {
+            assert_eq!(
+                vec![Ok(expected.clone()), Err(EvalError::StepLimitExceeded)],
+                evaluate_batch(&stmt, vec![input(5), input(0)], 100, parallel)
+            );
+        }
+    }
+
+    #[test]
+    fn shrink_keeps_failure() {
+        // x = 3; if (x < 5) { y = x * 2 } else { y = 1 }; z = 7
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign("x".into(), Expr::Number(3)).into(),
+            second: Stmt::Sequence {
+                first: Stmt::If {
+                    condition: Expr::LessThan(
+                        Expr::Variable("x".into()).into(),
+                        Expr::Number(5).into(),
+                    ),
+                    consequence: Stmt::Assign(
+                        "y".into(),
+                        Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(2).into()),
+                    )
+                    .into(),
+                    alternative: Stmt::Assign("y".into(), Expr::Number(1)).into(),
+                }
+                .into(),
+                second: Stmt::Assign("z".into(), Expr::Number(7)).into(),
+            }
+            .into(),
+        };
+        let mut env = Environment::new();
+        for name in ["x", "y", "z"] {
+            env.set(name, Expr::Number(0));
+        }
+        // The "bug": y ends up as 6.
+        let is_failing = |stmt: &Stmt| stmt.evalute(env.clone())["y"] == Expr::Number(6);
+        assert!(is_failing(&stmt));
+
+        let shrunk = shrink(&stmt, is_failing);
+        assert!(is_failing(&shrunk));
+        assert_eq!("x = 3; y = x * 2", shrunk.to_string());
+    }
+
+    #[test]
+    fn diff_environments_lists_changes() {
+        let mut old = Environment::new();
+        old.set("x", Expr::Number(1));
+        old.set("y", Expr::Number(2));
+        old.set("z", Expr::Boolean(true));
+        let mut new = old.clone();
+        new.set("x", Expr::Number(3));
+        new.remove("y");
+        new.set("w", Expr::Number(4));
+
+        let diff = diff_environments(&old, &new);
+        assert_eq!(
+            EnvironmentDiff {
+                added: vec![("w".into(), Expr::Number(4))],
+                removed: vec![("y".into(), Expr::Number(2))],
+                changed: vec![("x".into(), Expr::Number(1), Expr::Number(3))],
+            },
+            diff
+        );
+        assert_eq!("+ w = 4\n- y = 2\n~ x: 1 -> 3\n", diff.to_string());
+        assert!(diff_environments(&old, &old).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "~ x: 1 -> 3")]
+    fn assert_env_eq_reports_diff() {
+        let mut expected = Environment::new();
+        expected.set("x", Expr::Number(1));
+        let mut actual = Environment::new();
+        actual.set("x", Expr::Number(3));
+        assert_env_eq!(expected, actual);
+    }
+
+    #[test]
+    fn expr_metrics() {
+        // x * 2 + x * 3 < 10
+        let expr = Expr::LessThan(
+            Expr::Add(
+                Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(2).into()).into(),
+                Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(3).into()).into(),
+            )
+            .into(),
+            Expr::Number(10).into(),
+        );
+        assert_eq!(9, expr.size());
+        assert_eq!(4, expr.depth());
+        let counts = expr.count_ops();
+        assert_eq!(Some(&2), counts.get("*"));
+        assert_eq!(Some(&1), counts.get("+"));
+        assert_eq!(Some(&1), counts.get("<"));
+        assert_eq!(
+            "x * 2 + x * 3 < 10, x * 2 + x * 3, x * 2, x, 2, x * 3, x, 3, 10",
+            expr.subexprs()
+                .map(|expr| expr.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    #[test]
+    fn stmt_metrics() {
+        // while (x < 5) { x = x + 1; y = y * x }
+        let stmt = Stmt::While {
+            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(5).into()),
+            body: Stmt::Sequence {
+                first: Stmt::Assign(
+                    "x".into(),
+                    Expr::Add(Expr::Variable("x".into()).into(), Expr::Number(1).into()),
+                )
+                .into(),
+                second: Stmt::Assign(
+                    "y".into(),
+                    Expr::Multiply(
+                        Expr::Variable("y".into()).into(),
+                        Expr::Variable("x".into()).into(),
+                    ),
+                )
+                .into(),
+            }
+            .into(),
+        };
+        assert_eq!(4, stmt.substmts().count());
+        assert_eq!(13, stmt.size());
+        assert_eq!(5, stmt.depth());
+        let counts = stmt.count_ops();
+        assert_eq!(Some(&1), counts.get("+"));
+        assert_eq!(Some(&1), counts.get("*"));
+        assert_eq!(Some(&1), counts.get("<"));
+    }
+
+    #[test]
+    fn node_addressing() {
+        // x = 1; if (x < 2) { y = 1 } else { y = 2 }
+        let mut stmt = Stmt::Sequence {
+            first: Stmt::Assign("x".into(), Expr::Number(1)).into(),
+            second: Stmt::If {
+                condition: Expr::LessThan(
+                    Expr::Variable("x".into()).into(),
+                    Expr::Number(2).into(),
+                ),
+                consequence: Stmt::Assign("y".into(), Expr::Number(1)).into(),
+                alternative: Stmt::Assign("y".into(), Expr::Number(2)).into(),
+            }
+            .into(),
+        };
+        let alternative = Stmt::Assign("y".into(), Expr::Number(2));
+        let mut ids = NodeIds::new(&stmt);
+        assert_eq!(Some(&alternative), ids.find(&stmt, NodeId(4)));
+        assert_eq!(Some(&alternative), stmt.at(&[1, 1]));
+        assert_eq!(Some(&[1, 1][..]), ids.path(NodeId(4)));
+        for (i, node) in stmt.substmts().enumerate() {
+            assert_eq!(Some(node), ids.find(&stmt, NodeId(i)));
+        }
+        assert_eq!(None, ids.find(&stmt, NodeId(5)));
+        assert_eq!(None, stmt.at(&[0, 0]));
+
+        // Growing the first statement into a sequence leaves the other
+        // statements' ids alone.
+        let first = Stmt::Sequence {
+            first: Stmt::DoNothing.into(),
+            second: Stmt::Assign("x".into(), Expr::Number(1)).into(),
+        };
+        let old = ids.replace(&mut stmt, &[0], first.clone());
+        assert_eq!(Some(Stmt::Assign("x".into(), Expr::Number(1))), old);
+        assert_eq!(None, ids.find(&stmt, NodeId(1)));
+        assert_eq!(Some(&alternative), ids.find(&stmt, NodeId(4)));
+        assert_eq!(Some(&first), ids.find(&stmt, NodeId(5)));
+        assert_eq!(Some(&[0, 1][..]), ids.path(NodeId(7)));
+        assert_eq!(None, ids.replace(&mut stmt, &[0, 0, 0], Stmt::DoNothing));
+    }
+
+    #[test]
+    fn numeric_semantics() {
+        // y = x * x + 1
+        let stmt = Stmt::Assign(
+            "y".into(),
+            Expr::Add(
+                Expr::Multiply(
+                    Expr::Variable("x".into()).into(),
+                    Expr::Variable("x".into()).into(),
+                )
+                .into(),
+                Expr::Number(1).into(),
+            ),
+        );
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(1 << 32));
+        assert_eq!(
+            Err(EvalError::Overflow),
+            stmt.evalute_in(env.clone(), &Checked)
+        );
+        let wrapped = stmt.evalute_in(env, &Wrapping).unwrap();
+        assert_eq!(Ok(&Expr::Number(1)), wrapped.get("y"));
+    }
+
+    #[test]
+    fn modular_semantics() {
+        // x = 10; y = x * 4 + 3; while (0 < y) { y = y + 1; n = n + 1 }
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign("x".into(), Expr::Number(10)).into(),
+            second: Stmt::Sequence {
+                first: Stmt::Assign(
+                    "y".into(),
+                    Expr::Add(
+                        Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(4).into())
+                            .into(),
+                        Expr::Number(3).into(),
+                    ),
+                )
+                .into(),
+                second: Stmt::While {
+                    condition: Expr::LessThan(
+                        Expr::Number(0).into(),
+                        Expr::Variable("y".into()).into(),
+                    ),
+                    body: Stmt::Sequence {
+                        first: Stmt::Assign(
+                            "y".into(),
+                            Expr::Add(Expr::Variable("y".into()).into(), Expr::Number(1).into()),
+                        )
+                        .into(),
+                        second: Stmt::Assign(
+                            "n".into(),
+                            Expr::Add(Expr::Variable("n".into()).into(), Expr::Number(1).into()),
+                        )
+                        .into(),
+                    }
+                    .into(),
+                }
+                .into(),
+            }
+            .into(),
+        };
+        let mut env = Environment::new();
+        env.set("n", Expr::Number(0));
+        let result = stmt
+            .evalute_in(env, &modular::Modular::new(7).unwrap())
+            .unwrap();
+        // 10 = 3 (mod 7), 3 * 4 + 3 = 1 (mod 7), then y counts 1..6 up to 0.
+        assert_eq!(Ok(&Expr::Number(3)), result.get("x"));
+        assert_eq!(Ok(&Expr::Number(0)), result.get("y"));
+        assert_eq!(Ok(&Expr::Number(6)), result.get("n"));
+
+        assert!(modular::Modular::new(0).is_none());
+        assert!(modular::Modular::new(-7).is_none());
+    }
+
+    fn assert_same_variables(left: &Environment, right: &Environment, variables: &[&str]) {
+        for name in variables {
+            assert_eq!(left.get(name), right.get(name), "variable {}", name);
+        }
+    }
+
+    #[test]
+    fn anf_names_intermediate_results() {
+        // y = (x + 1) * (x + t0); while (y < x * 10) { y = y + 1 }
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign(
+                "y".into(),
+                Expr::Multiply(
+                    Expr::Add(Expr::Variable("x".into()).into(), Expr::Number(1).into()).into(),
+                    Expr::Add(
+                        Expr::Variable("x".into()).into(),
+                        Expr::Variable("t0".into()).into(),
+                    )
+                    .into(),
+                ),
+            )
+            .into(),
+            second: Stmt::While {
+                condition: Expr::LessThan(
+                    Expr::Variable("y".into()).into(),
+                    Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(10).into())
+                        .into(),
+                ),
+                body: Stmt::Assign(
+                    "y".into(),
+                    Expr::Add(Expr::Variable("y".into()).into(), Expr::Number(1).into()),
+                )
+                .into(),
+            }
+            .into(),
+        };
+        let anf = to_anf(&stmt);
+        assert_eq!(
+            "t1 = x + 1; t2 = x + t0; y = t1 * t2; t3 = x * 10; t4 = y < t3; \
+             while (t4) { y = y + 1; t3 = x * 10; t4 = y < t3 }",
+            anf.to_string()
+        );
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(2));
+        env.set("t0", Expr::Number(1));
+        assert_same_variables(
+            &stmt.evalute(env.clone()),
+            &anf.evalute(env),
+            &["x", "y", "t0"],
+        );
+    }
+
+    #[test]
+    fn anf_keeps_side_effect_order() {
+        // y = x + x++; z = ++x * (w = x)
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign(
+                "y".into(),
+                Expr::Add(
+                    Expr::Variable("x".into()).into(),
+                    Expr::PostIncrement("x".into()).into(),
+                ),
+            )
+            .into(),
+            second: Stmt::Assign(
+                "z".into(),
+                Expr::Multiply(
+                    Expr::PreIncrement("x".into()).into(),
+                    Expr::AssignExpr("w".into(), Expr::Variable("x".into()).into()).into(),
+                ),
+            )
+            .into(),
+        };
+        let anf = to_anf(&stmt);
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(1));
+        assert_same_variables(
+            &stmt.evalute(env.clone()),
+            &anf.evalute(env),
+            &["x", "y", "z", "w"],
+        );
+        assert!(anf.subexprs().all(|expr| !expr.has_side_effects()));
+    }
+
+    #[test]
+    fn pass_manager_chains_transforms() {
+        // x = 2 * 3 + y
+        let stmt = Stmt::Assign(
+            "x".into(),
+            Expr::Add(
+                Expr::Multiply(Expr::Number(2).into(), Expr::Number(3).into()).into(),
+                Expr::Variable("y".into()).into(),
+            ),
+        );
+        let (simplified, stats) = PassManager::standard().run(&stmt);
+        assert_eq!("x = 6 + y", simplified.to_string());
+        assert_eq!(
+            vec![
+                PassStats {
+                    name: "simplify",
+                    size_before: 6,
+                    size_after: 4,
+                    changed: true,
+                    analyses_computed: 0,
+                },
+                PassStats {
+                    name: "anf",
+                    size_before: 4,
+                    size_after: 4,
+                    changed: false,
+                    analyses_computed: 0,
+                },
+                PassStats {
+                    name: "cse",
+                    size_before: 4,
+                    size_after: 4,
+                    changed: false,
+                    analyses_computed: 0,
+                },
+                PassStats {
+                    name: "constant-propagation",
+                    size_before: 4,
+                    size_after: 4,
+                    changed: false,
+                    analyses_computed: 0,
+                },
+                PassStats {
+                    name: "dce",
+                    size_before: 4,
+                    size_after: 4,
+                    changed: false,
+                    analyses_computed: 1,
+                },
+            ],
+            stats
+        );
+
+        let manager = PassManager::new()
+            .add("anf", |stmt, _| to_anf(stmt))
+            .add("simplify", |stmt, _| simplify(stmt));
+        assert_eq!("t0 = 6; x = 6 + y", manager.run(&stmt).0.to_string());
+        // The first `dce` drops the temporary. The program changed, so the
+        // second one analyses it again; the third reuses that analysis.
+        let (optimized, stats) = manager
+            .add("dce", eliminate_dead_code)
+            .add("dce", eliminate_dead_code)
+            .add("dce", eliminate_dead_code)
+            .run(&stmt);
+        assert_eq!("x = 6 + y", optimized.to_string());
+        assert_eq!(
+            vec![0, 0, 1, 1, 0],
+            stats
+                .iter()
+                .map(|stats| stats.analyses_computed)
+                .collect::<Vec<_>>()
+        );
+        // A user variable stays even when nothing reads it.
+        let stmt = Stmt::Assign("x".into(), Expr::Number(1));
+        assert_eq!(stmt, PassManager::standard().run(&stmt).0);
+    }
+
+    #[test]
+    fn simplify_bounds_unrolling() {
+        // while (true) { x = 1 }
+        let forever = Stmt::While {
+            condition: Expr::Boolean(true),
+            body: Stmt::Assign("x".into(), Expr::Number(1)).into(),
+        };
+        let simplified = simplify(&forever);
+        assert_eq!(SIMPLIFY_UNROLLS + 1, flatten(&simplified).len());
+        assert_eq!(Some(&forever), flatten(&simplified).last());
+
+        // i = 0; while (i < n) { i = i + 1 }
+        let i = || Box::new(Expr::Variable("i".into()));
+        let count_to = |n| Stmt::Sequence {
+            first: Stmt::Assign("i".into(), Expr::Number(0)).into(),
+            second: Stmt::While {
+                condition: Expr::LessThan(i(), Expr::Number(n).into()),
+                body: Stmt::Assign("i".into(), Expr::Add(i(), Expr::Number(1).into())).into(),
+            }
+            .into(),
+        };
+        let (simplified, _) = PassManager::standard().run(&count_to(1_000_000_000));
+        assert!(simplified.size() < 1000);
+        let long = count_to(100);
+        let (simplified, _) = PassManager::standard().run(&long);
+        assert!(matches!(simplified, Stmt::Sequence { .. }));
+        assert_env_eq!(
+            long.evalute(Environment::new()),
+            simplified.evalute(Environment::new())
+        );
+    }
+
+    #[test]
+    fn cse_reuses_temporaries() {
+        // a = x * y + 1; b = x * y + 2
+        let plus = |n| {
+            Expr::Add(
+                Expr::Multiply(
+                    Expr::Variable("x".into()).into(),
+                    Expr::Variable("y".into()).into(),
+                )
+                .into(),
+                Expr::Number(n).into(),
+            )
+        };
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign("a".into(), plus(1)).into(),
+            second: Stmt::Assign("b".into(), plus(2)).into(),
+        };
+        let optimized = eliminate_common_subexpressions(&to_anf(&stmt));
+        assert_eq!(
+            "t0 = x * y; a = t0 + 1; t1 = t0; b = t1 + 2",
+            optimized.to_string()
+        );
+    }
+
+    #[test]
+    fn cse_preserves_semantics() {
+        let x = || Box::new(Expr::Variable("x".into()));
+        let y = || Box::new(Expr::Variable("y".into()));
+        let programs = vec![
+            // a = x * y; x = x + 1; b = x * y
+            Stmt::Sequence {
+                first: Stmt::Assign("a".into(), Expr::Multiply(x(), y())).into(),
+                second: Stmt::Sequence {
+                    first: Stmt::Assign("x".into(), Expr::Add(x(), Expr::Number(1).into())).into(),
+                    second: Stmt::Assign("b".into(), Expr::Multiply(x(), y())).into(),
+                }
+                .into(),
+            },
+            // a = x + y; a = a + y; b = x + y
+            Stmt::Sequence {
+                first: Stmt::Assign("a".into(), Expr::Add(x(), y())).into(),
+                second: Stmt::Sequence {
+                    first: Stmt::Assign(
+                        "a".into(),
+                        Expr::Add(Expr::Variable("a".into()).into(), y()),
+                    )
+                    .into(),
+                    second: Stmt::Assign("b".into(), Expr::Add(x(), y())).into(),
+                }
+                .into(),
+            },
+            // a = x * y; while (x < 3) { x = x + 1; b = x * y }; c = x * y
+            Stmt::Sequence {
+                first: Stmt::Assign("a".into(), Expr::Multiply(x(), y())).into(),
+                second: Stmt::Sequence {
+                    first: Stmt::While {
+                        condition: Expr::LessThan(x(), Expr::Number(3).into()),
+                        body: Stmt::Sequence {
+                            first: Stmt::Assign("x".into(), Expr::Add(x(), Expr::Number(1).into()))
+                                .into(),
+                            second: Stmt::Assign("b".into(), Expr::Multiply(x(), y())).into(),
+                        }
+                        .into(),
+                    }
+                    .into(),
+                    second: Stmt::Assign("c".into(), Expr::Multiply(x(), y())).into(),
+                }
+                .into(),
+            },
+            // a = x * y; if (x++ < 3) { b = x * y } else { b = 0 }
+            Stmt::Sequence {
+                first: Stmt::Assign("a".into(), Expr::Multiply(x(), y())).into(),
+                second: Stmt::If {
+                    condition: Expr::LessThan(
+                        Expr::PostIncrement("x".into()).into(),
+                        Expr::Number(3).into(),
+                    ),
+                    consequence: Stmt::Assign("b".into(), Expr::Multiply(x(), y())).into(),
+                    alternative: Stmt::Assign("b".into(), Expr::Number(0)).into(),
+                }
+                .into(),
+            },
+        ];
+        for stmt in programs {
+            for optimized in [
+                eliminate_common_subexpressions(&stmt),
+                eliminate_common_subexpressions(&to_anf(&stmt)),
+            ] {
+                for (x, y) in [(0, 0), (1, 2), (-3, 5)] {
+                    let mut env = Environment::new();
+                    for name in ["a", "b", "c"] {
+                        env.set(name, Expr::Number(0));
+                    }
+                    env.set("x", Expr::Number(x));
+                    env.set("y", Expr::Number(y));
+                    assert_same_variables(
+                        &stmt.evalute(env.clone()),
+                        &optimized.evalute(env),
+                        &["a", "b", "c", "x", "y"],
+                    );
+                }
+            }
+        }
+    }
+
+    fn sum_loop() -> Stmt {
+        // while (i < n) { t = a * b; s = s + t; i = i + 1 }
+        let var = |name: &str| Box::new(Expr::Variable(name.into()));
+        Stmt::While {
+            condition: Expr::LessThan(var("i"), var("n")),
+            body: Stmt::Sequence {
+                first: Stmt::Assign("t".into(), Expr::Multiply(var("a"), var("b"))).into(),
+                second: Stmt::Sequence {
+                    first: Stmt::Assign("s".into(), Expr::Add(var("s"), var("t"))).into(),
+                    second: Stmt::Assign("i".into(), Expr::Add(var("i"), Expr::Number(1).into()))
+                        .into(),
+                }
+                .into(),
+            }
+            .into(),
+        }
+    }
+
+    fn sum_loop_inputs() -> Vec<Environment> {
+        (0..4)
+            .map(|n| {
+                let mut env = Environment::new();
+                for (name, value) in [("i", 0), ("n", n), ("a", 2), ("b", 3), ("s", 0), ("t", 0)] {
+                    env.set(name, Expr::Number(value));
+                }
+                env
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unroll_loops_by_factor() {
+        let stmt = sum_loop();
+        let unrolled = unroll_loops(&stmt, 2);
+        assert_eq!(
+            "while (i < n) { t = a * b; s = s + t; i = i + 1; \
+             if (i < n) { t = a * b; s = s + t; i = i + 1 } else { do-nothing } }",
+            unrolled.to_string()
+        );
+        for env in sum_loop_inputs() {
+            assert_env_eq!(stmt.evalute(env.clone()), unrolled.evalute(env));
+        }
+    }
+
+    #[test]
+    fn hoist_loop_invariants() {
+        let stmt = sum_loop();
+        let hoisted = hoist_invariants(&stmt);
+        assert_eq!(
+            "if (i < n) { t = a * b; while (i < n) { s = s + t; i = i + 1 } } \
+             else { do-nothing }",
+            hoisted.to_string()
+        );
+        for env in sum_loop_inputs() {
+            assert_env_eq!(stmt.evalute(env.clone()), hoisted.evalute(env));
+        }
+
+        // while (i < n) { s = s + t; t = a * b; i = i + 1 } reads `t` before
+        // assigning it, so nothing moves.
+        let var = |name: &str| Box::new(Expr::Variable(name.into()));
+        let reads_first = Stmt::While {
+            condition: Expr::LessThan(var("i"), var("n")),
+            body: sequence(vec![
+                Stmt::Assign("s".into(), Expr::Add(var("s"), var("t"))),
+                Stmt::Assign("t".into(), Expr::Multiply(var("a"), var("b"))),
+                Stmt::Assign("i".into(), Expr::Add(var("i"), Expr::Number(1).into())),
+            ])
+            .into(),
+        };
+        assert_eq!(reads_first, hoist_invariants(&reads_first));
+    }
+
+    /// How many statements evaluating `stmt` from `env` takes, counting
+    /// every loop iteration as `evalute_with_limit` does.
+    fn statements_evaluated(stmt: &Stmt, env: &Environment) -> usize {
+        (1..)
+            .find(|&limit| stmt.evalute_with_limit(env.clone(), limit).is_ok())
+            .unwrap()
+    }
+
+    #[test]
+    fn loop_transforms_statement_counts() {
+        let stmt = sum_loop();
+        let hoisted = hoist_invariants(&stmt);
+        let unrolled = unroll_loops(&stmt, 2);
+        // Each iteration of the original loop evaluates six statements.
+        // Hoisting `t = a * b` saves two of them per iteration, after a
+        // guard that costs three once. Unrolling by two trades every second
+        // loop iteration for an `if` and a sequence, so it saves no
+        // statements; what it saves is jumps back to the loop head.
+        let expected = [(1, 2, 1), (7, 8, 10), (13, 12, 14), (19, 16, 23)];
+        for (env, expected) in sum_loop_inputs().iter().zip(expected) {
+            let counts = (
+                statements_evaluated(&stmt, env),
+                statements_evaluated(&hoisted, env),
+                statements_evaluated(&unrolled, env),
+            );
+            assert_eq!(expected, counts, "{}", env);
+        }
+        let mut env = sum_loop_inputs().remove(0);
+        env.set("n", Expr::Number(100));
+        assert_eq!(601, statements_evaluated(&stmt, &env));
+        assert_eq!(404, statements_evaluated(&hoisted, &env));
+    }
+
+    #[test]
+    fn environment_scopes() {
+        let mut env = Environment::new();
+        env.set("y", Expr::Number(2));
+        env.set("x", Expr::Boolean(true));
+        assert_eq!("{x = true, y = 2}", env.to_string());
+
+        let mut inner = env.child_scope();
+        inner.set("y", Expr::Number(3));
+        inner.set("z", Expr::Number(4));
+        assert_eq!(Ok(&Expr::Number(4)), inner.get("z"));
+        assert_eq!(
+            vec!["x", "y", "z"],
+            inner
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        // Assigning to `y` inside updated the outer binding; `z` was
+        // local to the inner scope.
+        let outer = inner.parent_scope();
+        assert_eq!("{x = true, y = 3}", outer.to_string());
+        assert_eq!(Err(UndefinedVariable("z".into())), outer.get("z"));
+        let flat: Environment = outer.clone().into_iter().collect();
+        assert_eq!(outer, flat);
+
+        assert_eq!(
+            Err(EvalError::UndefinedVariable("z".into())),
+            Expr::parse("z + 1")
+                .unwrap()
+                .evalute_in(&mut outer.clone(), &Checked)
+        );
+    }
+
+    #[test]
+    fn environment_parse() {
+        let env = Environment::parse("x=1, flag=true").unwrap();
+        assert_eq!(Ok(&Expr::Number(1)), env.get("x"));
+        assert_eq!(Ok(&Expr::Boolean(true)), env.get("flag"));
+        assert_eq!(Ok(env.clone()), Environment::parse(&env.to_string()));
+
+        let env = Environment::parse("# inputs\n\nn = -3\n  done = false, n = 4\n").unwrap();
+        assert_eq!("{done = false, n = 4}", env.to_string());
+        assert_eq!(Ok(Environment::new()), Environment::parse("{}"));
+
+        assert_eq!(
+            Err("line 2: expected `name = value`, found `y`".into()),
+            Environment::parse("x = 1\ny")
+        );
+        assert_eq!(
+            Err("line 1: invalid variable name `2x`".into()),
+            Environment::parse("2x = 1")
+        );
+        assert_eq!(
+            Err("line 1: invalid value `yes` for `ok`".into()),
+            Environment::parse("ok = yes")
+        );
+    }
+
+    #[test]
+    fn evalute_mut_updates_in_place() {
+        let var = |name: &str| Box::new(Expr::Variable(name.into()));
+        // while (i < n) { i = i + 1 }; y = 10 / (i - 5)
+        let stmt = sequence(vec![
+            Stmt::While {
+                condition: Expr::LessThan(var("i"), var("n")),
+                body: Stmt::Assign("i".into(), Expr::Add(var("i"), Expr::Number(1).into())).into(),
+            },
+            Stmt::Assign(
+                "y".into(),
+                Expr::Divide(
+                    Expr::Number(10).into(),
+                    Expr::Subtract(var("i"), Expr::Number(5).into()).into(),
+                ),
+            ),
+        ]);
+        let mut env = Environment::new();
+        env.set("i", Expr::Number(0));
+        env.set("n", Expr::Number(10));
+        let mut in_place = env.clone();
+        stmt.evalute_mut(&mut in_place);
+        assert_env_eq!(stmt.evalute(env.clone()), in_place);
+
+        // A long loop runs without a stack frame per iteration.
+        env.set("n", Expr::Number(100_000));
+        stmt.evalute_mut(&mut env);
+        assert_eq!(Ok(&Expr::Number(0)), env.get("y"));
+
+        env.set("i", Expr::Number(0));
+        env.set("n", Expr::Number(5));
+        assert!(matches!(
+            stmt.evalute_in_mut(&mut env, &Checked),
+            Err(EvalError::DivisionByZero(_))
+        ));
+        assert_eq!(Ok(&Expr::Number(5)), env.get("i"));
+    }
+
+    #[test]
+    fn gcd_with_modulo() {
+        let var = |name: &str| Box::new(Expr::Variable(name.into()));
+        // while (0 < b) { t = b; b = a % b; a = t }
+        let gcd = Stmt::While {
+            condition: Expr::LessThan(Expr::Number(0).into(), var("b")),
+            body: sequence(vec![
+                Stmt::Assign("t".into(), Expr::Variable("b".into())),
+                Stmt::Assign("b".into(), Expr::Modulo(var("a"), var("b"))),
+                Stmt::Assign("a".into(), Expr::Variable("t".into())),
+            ])
+            .into(),
+        };
+        for (a, b, expected) in [(48, 18, 6), (17, 5, 1), (0, 9, 9), (9, 0, 9)] {
+            let mut env = Environment::new();
+            env.set("a", Expr::Number(a));
+            env.set("b", Expr::Number(b));
+            assert_eq!(Ok(&Expr::Number(expected)), gcd.evalute(env).get("a"));
+        }
+    }
+
+    #[test]
+    fn boolean_operands_are_type_mismatches() {
+        for source in [
+            "true + 1",
+            "1 - false",
+            "true * 2",
+            "6 / true",
+            "false % 2",
+            "true < 1",
+            "1 > false",
+            "true <= 1",
+            "1 >= true",
+        ] {
+            let expr = Expr::parse(source).unwrap();
+            assert_eq!(
+                Err(EvalError::TypeMismatch(expr.clone())),
+                expr.evalute_in(&mut Environment::new(), &Checked),
+                "{}",
+                source
+            );
+        }
+
+        // x = true + 1
+        let stmt = Stmt::Assign(
+            "x".into(),
+            Expr::Add(Expr::Boolean(true).into(), Expr::Number(1).into()),
+        );
+        assert_eq!(
+            Err(EvalError::TypeMismatch(Expr::Add(
+                Expr::Boolean(true).into(),
+                Expr::Number(1).into()
+            ))),
+            stmt.evalute_with_limit(Environment::new(), 10)
+        );
+    }
+
+    #[test]
+    fn eval_str_formulas() {
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(3));
+        env.set("ok", Expr::Boolean(true));
+        assert_eq!(Ok(Expr::Number(7)), eval_str("1 + 2 * x", &env));
+        assert_eq!(Ok(Expr::Number(9)), eval_str("(1 + 2) * x", &env));
+        assert_eq!(Ok(Expr::Number(3)), eval_str("1\u{3000}+ 2", &env));
+        assert_eq!(Ok(Expr::Boolean(true)), eval_str(" x < 2*x ", &env));
+        assert_eq!(Ok(Expr::Boolean(true)), eval_str("ok", &env));
+        assert_eq!(Ok(Expr::Number(-4)), eval_str("x - 2 - 5", &env));
+        assert_eq!(Ok(Expr::Number(-3)), eval_str("x - 2 * x", &env));
+        assert_eq!("x - 2 - 5", Expr::parse("x - 2 - 5").unwrap().to_string());
+
+        assert_eq!(
+            Err("undefined variable `y`".into()),
+            eval_str("y + 1", &env)
+        );
+        assert_eq!(
+            Err("`ok` is not a number in `ok + 1`".into()),
+            eval_str("ok + 1", &env)
+        );
+        assert_eq!(Err("unexpected `)` at 4".into()), eval_str("1 + ) 2", &env));
+        assert_eq!(Err("expected `)` at 6".into()), eval_str("(1 + 2", &env));
+        assert_eq!(Err("unexpected end of input".into()), eval_str("1 *", &env));
+        assert_eq!(
+            Err("arithmetic overflow".into()),
+            eval_str("9223372036854775807 + 1", &env)
+        );
+        assert_eq!(
+            Err("arithmetic overflow".into()),
+            eval_str("0 - 9223372036854775807 - 2", &env)
+        );
+        assert_eq!(Ok(Expr::Number(2)), eval_str("7 / x", &env));
+        assert_eq!(Ok(Expr::Number(-3)), eval_str("(0 - 7) / 2 * 1", &env));
+        assert_eq!(
+            Err("division by zero in `x / 0`".into()),
+            eval_str("x / 0", &env)
+        );
+        assert_eq!(
+            Err("arithmetic overflow".into()),
+            eval_str("(0 - 9223372036854775807 - 1) / (0 - 1)", &env)
+        );
+
+        assert_eq!(Ok(Expr::Number(1)), eval_str("7 % x", &env));
+        assert_eq!(Ok(Expr::Number(-1)), eval_str("(0 - 7) % x", &env));
+        assert_eq!(Ok(Expr::Number(1)), eval_str("2 * 5 % x", &env));
+        assert_eq!(
+            Err("division by zero in `x % 0`".into()),
+            eval_str("x % 0", &env)
+        );
+
+        assert_eq!(Ok(Expr::Boolean(true)), eval_str("x > 2", &env));
+        assert_eq!(Ok(Expr::Boolean(false)), eval_str("x > 3", &env));
+        assert_eq!(Ok(Expr::Boolean(true)), eval_str("x <= 3", &env));
+        assert_eq!(Ok(Expr::Boolean(false)), eval_str("x<=2", &env));
+        assert_eq!(Ok(Expr::Boolean(true)), eval_str("x >= 1 + 2", &env));
+        assert_eq!(Ok(Expr::Boolean(false)), eval_str("x >= 4", &env));
+        assert_eq!("x >= 1 + 2", Expr::parse("x>=1+2").unwrap().to_string());
+        assert_eq!(Err("unexpected `=` at 4".into()), eval_str("x < = 3", &env));
+
+        assert_eq!(Ok(Expr::Boolean(true)), eval_str("x == 1 + 2", &env));
+        assert_eq!(Ok(Expr::Boolean(false)), eval_str("x != 3", &env));
+        assert_eq!(Ok(Expr::Boolean(true)), eval_str("ok == x < 4", &env));
+        assert_eq!(Ok(Expr::Boolean(true)), eval_str("ok!=false", &env));
+        assert_eq!("x < 4 == ok", Expr::parse("x<4==ok").unwrap().to_string());
+        assert_eq!(Err("unexpected `=` at 2".into()), eval_str("x = 3", &env));
+        assert_eq!(
+            Err("`x` and `ok` have different types in `x == ok`".into()),
+            eval_str("x == ok", &env)
+        );
+        let mismatch = Expr::parse("x != ok").unwrap();
+        assert_eq!(
+            Err(EvalError::TypeMismatch(mismatch.clone())),
+            mismatch.evalute_in(&mut env.clone(), &Checked)
+        );
+        // Folding leaves the mismatch in place for the run to report.
+        assert_eq!(Expr::parse("3 != true").unwrap(), mismatch.specialize(&env));
+
+        assert_eq!(Ok(Expr::Boolean(true)), eval_str("x < 4 && ok", &env));
+        assert_eq!(
+            Ok(Expr::Boolean(false)),
+            eval_str("x > 4 || ok == false", &env)
+        );
+        // The right operand is skipped, so its division never runs.
+        assert_eq!(Ok(Expr::Boolean(true)), eval_str("ok || x / 0 == 1", &env));
+        assert_eq!(
+            Ok(Expr::Boolean(false)),
+            eval_str("x == 4 && x / 0 == 1", &env)
+        );
+        assert_eq!(
+            Expr::parse("a || (b && c)").unwrap(),
+            Expr::parse("a||b&&c").unwrap()
+        );
+        assert_eq!(
+            Err("`x` is not a boolean in `x && ok`".into()),
+            eval_str("x && ok", &env)
+        );
+
+        assert_eq!(Ok(Expr::Boolean(true)), eval_str("!(x < 1)", &env));
+        assert_eq!(Ok(Expr::Boolean(false)), eval_str("!ok", &env));
+        assert_eq!(Ok(Expr::Boolean(true)), eval_str("!!ok && x != 4", &env));
+        assert_eq!(Ok(Expr::Boolean(false)), eval_str("!ok || !(x == 3)", &env));
+        assert_eq!("!(x < 5)", Expr::parse("!(x<5)").unwrap().to_string());
+        assert_eq!(
+            Expr::parse("(!ok) && ok").unwrap(),
+            Expr::parse("!ok && ok").unwrap()
+        );
+        assert_eq!(
+            Err("`x` is not a boolean in `!x`".into()),
+            eval_str("!x", &env)
+        );
+        let not = Expr::Not(Expr::Number(1).into());
+        assert_eq!(
+            Err(EvalError::TypeMismatch(not.clone())),
+            not.evalute_in(&mut Environment::new(), &Checked)
+        );
+        assert_eq!(
+            Expr::Boolean(false),
+            Expr::parse("!ok").unwrap().specialize(&env)
+        );
+
+        // Folding leaves the failing division for the run to report.
+        let divide = Expr::Add(
+            Expr::Number(1).into(),
+            Expr::Divide(Expr::Number(1).into(), Expr::Number(0).into()).into(),
+        );
+        assert_eq!(divide, divide.specialize(&Environment::new()));
+        assert_eq!(
+            Err(EvalError::DivisionByZero(Expr::Divide(
+                Expr::Number(1).into(),
+                Expr::Number(0).into()
+            ))),
+            Stmt::Assign("y".into(), divide).evalute_with_limit(env.clone(), 10)
+        );
+    }
+
+    #[test]
+    fn synthesize_well_typed_exprs() {
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(2));
+        env.set("b", Expr::Boolean(false));
+        let show = |exprs: Vec<Expr>| {
+            exprs
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        assert_eq!("0, 1, x", show(synthesize(Type::Number, &env, 1)));
+        assert_eq!("true, false, b", show(synthesize(Type::Boolean, &env, 1)));
+        assert!(synthesize(Type::Number, &env, 0).is_empty());
+
+        for ty in [Type::Number, Type::Boolean] {
+            let exprs = synthesize(ty, &env, 3);
+            assert_eq!(
+                exprs.len(),
+                exprs.iter().collect::<std::collections::HashSet<_>>().len()
+            );
+            for expr in exprs {
+                assert!(expr.depth() <= 3, "{}", expr);
+                assert_eq!(Ok(ty), expr.type_of(&env), "{}", expr);
+                assert_eq!(Ok(ty), expr.evalute(&mut env.clone()).type_of(&env));
+            }
+        }
+        assert_eq!(3 + 3 * 3, synthesize(Type::Boolean, &env, 2).len());
+        assert!(synthesize(Type::Number, &env, 2).contains(&Expr::Add(
+            Expr::Variable("x".into()).into(),
+            Expr::Number(1).into()
+        )));
+    }
+
+    #[test]
+    fn mutation_testing() {
+        // if (x < 3) { y = x + 1 } else { y = 0 }
+        let stmt = Stmt::If {
+            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(3).into()),
+            consequence: Stmt::Assign(
+                "y".into(),
+                Expr::Add(Expr::Variable("x".into()).into(), Expr::Number(1).into()),
+            )
+            .into(),
+            alternative: Stmt::Assign("y".into(), Expr::Number(0)).into(),
+        };
+        assert_eq!(
+            "if (3 < x + 1) { y = x + 1 } else { y = 0 }, \
+             if (x < 4) { y = x + 1 } else { y = 0 }, \
+             if (x < 2) { y = x + 1 } else { y = 0 }, \
+             if (x < 3) { y = x * 1 } else { y = 0 }, \
+             if (x < 3) { y = x + 2 } else { y = 0 }, \
+             if (x < 3) { y = x + 0 } else { y = 0 }, \
+             if (x < 3) { y = x + 1 } else { y = 1 }, \
+             if (x < 3) { y = x + 1 } else { y = -1 }",
+            stmt.mutants()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        let suite = |cases: &[(i64, i64)]| {
+            let cases = cases.to_vec();
+            move |stmt: &Stmt| {
+                cases.iter().all(|&(x, y)| {
+                    let mut env = Environment::new();
+                    env.set("x", Expr::Number(x));
+                    stmt.evalute_with_limit(env, 100)
+                        .is_ok_and(|env| env.get("y") == Ok(&Expr::Number(y)))
+                })
+            }
+        };
+        assert!(suite(&[(0, 1)])(&stmt));
+        let weak = mutation_test(&stmt, suite(&[(0, 1)]));
+        assert_eq!(8, weak.mutants);
+        assert_eq!(4, weak.killed());
+
+        let boundaries = mutation_test(&stmt, suite(&[(0, 1), (1, 2), (2, 3), (3, 0)]));
+        assert_eq!(8, boundaries.killed());
+        assert!(boundaries.survivors.is_empty());
+    }
+
+    #[test]
+    fn laws_rewrite_at_every_position() {
+        // x * (y + 0)
+        let expr = Expr::Multiply(
+            Expr::Variable("x".into()).into(),
+            Expr::Add(Expr::Variable("y".into()).into(), Expr::Number(0).into()).into(),
+        );
+        assert_eq!(
+            "multiply-commutative: y + 0 * x, distributive: x * y + x * 0, \
+             add-commutative: x * 0 + y, add-identity: x * y",
+            rewrites(&expr)
+                .iter()
+                .map(|(name, expr)| format!("{}: {}", name, expr))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let increment = Expr::Add(
+            Expr::PreIncrement("x".into()).into(),
+            Expr::Number(0).into(),
+        );
+        assert!(rewrites(&increment).is_empty());
+    }
+
+    #[test]
+    fn laws_preserve_evaluation() {
+        let envs: Vec<Environment> = [(0, 0), (2, -3), (i64::MAX, 7)]
+            .iter()
+            .map(|&(x, y)| {
+                let mut env = Environment::new();
+                env.set("x", Expr::Number(x));
+                env.set("y", Expr::Number(y));
+                env
+            })
+            .collect();
+        let mut used = std::collections::HashSet::new();
+        for ty in [Type::Number, Type::Boolean] {
+            for expr in synthesize(ty, &envs[0], 3) {
+                for (name, rewritten) in rewrites(&expr) {
+                    used.insert(name);
+                    for env in &envs {
+                        assert_eq!(
+                            expr.evalute_in(&mut env.clone(), &Wrapping),
+                            rewritten.evalute_in(&mut env.clone(), &Wrapping),
+                            "{}: {} => {}",
+                            name,
+                            expr,
+                            rewritten
+                        );
+                    }
+                }
+            }
+        }
+        assert_eq!(LAWS.len(), used.len());
+    }
+
+    #[test]
+    fn saturate_simplify_beats_constant_folding() {
+        let x = || Box::new(Expr::Variable("x".into()));
+        let y = || Box::new(Expr::Variable("y".into()));
+        let n = |n| Box::new(Expr::Number(n));
+        let naive = |expr: &Expr| expr.specialize(&Environment::new());
+
+        // x * 0 + (y + 0) * 1
+        let expr = Expr::Add(
+            Expr::Multiply(x(), n(0)).into(),
+            Expr::Multiply(Expr::Add(y(), n(0)).into(), n(1)).into(),
+        );
+        assert_eq!(expr, naive(&expr));
+        // x may be unbound, so x * 0 stays to report it: x * 0 + y.
+        assert_eq!(5, saturate_simplify(&expr, 1000).size());
+        let expr = Expr::Add(
+            Expr::Multiply(Expr::Add(n(1), n(2)).into(), n(0)).into(),
+            Expr::Multiply(Expr::Add(y(), n(0)).into(), n(1)).into(),
+        );
+        assert_eq!(*y(), saturate_simplify(&expr, 1000));
+
+        // (1 / x) * 0 fails when x is 0, so it must not become 0.
+        let expr = Expr::Multiply(Expr::Divide(n(1), x()).into(), n(0));
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(0));
+        assert_eq!(
+            expr.evalute_in(&mut env.clone(), &Wrapping),
+            saturate_simplify(&expr, 1000).evalute_in(&mut env, &Wrapping)
+        );
+
+        // x * 2 + x * 3  =>  x * 5
+        let expr = Expr::Add(
+            Expr::Multiply(x(), n(2)).into(),
+            Expr::Multiply(x(), n(3)).into(),
+        );
+        assert_eq!(7, naive(&expr).size());
+        assert!([Expr::Multiply(x(), n(5)), Expr::Multiply(n(5), x())]
+            .contains(&saturate_simplify(&expr, 1000)));
+
+        // 1 + (x + 2)  =>  x + 3, which folding alone cannot reach
+        let expr = Expr::Add(n(1), Expr::Add(x(), n(2)).into());
+        assert_eq!(expr, naive(&expr));
+        assert_eq!(3, saturate_simplify(&expr, 1000).size());
+
+        let increment = Expr::Add(Expr::PreIncrement("x".into()).into(), n(0));
+        assert_eq!(increment, saturate_simplify(&increment, 1000));
+
+        // Variables that look like class placeholders are still variables.
+        let placeholder = || Box::new(Expr::Variable("#0".into()));
+        let expr = Expr::Add(placeholder(), n(0));
+        assert_eq!(*placeholder(), saturate_simplify(&expr, 1000));
+        let expr = Expr::Add(Expr::Multiply(placeholder(), n(1)).into(), x());
+        assert_eq!(3, saturate_simplify(&expr, 1000).size());
+
+        // Ill-typed constants are left alone rather than folded or dropped.
+        let ill_typed = Expr::Add(Expr::Boolean(true).into(), n(1));
+        assert_eq!(ill_typed, saturate_simplify(&ill_typed, 1000));
+        let ill_typed = Expr::Multiply(Expr::Boolean(true).into(), n(0));
+        assert!(rewrites(&ill_typed)
+            .iter()
+            .all(|(name, _)| *name != "multiply-zero"));
+        assert_eq!(ill_typed, saturate_simplify(&ill_typed, 1000));
+    }
+
+    /// Sizes and running times of `saturate_simplify` against the folding
+    /// `specialize` does, over every small expression. Run with
+    /// `--nocapture` to see the figures.
+    #[test]
+    fn saturate_simplify_benchmark() {
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(-4));
+        env.set("y", Expr::Number(9));
+        for depth in 1..=2 {
+            let exprs: Vec<Expr> = [Type::Number, Type::Boolean]
+                .iter()
+                .flat_map(|&ty| synthesize(ty, &env, depth))
+                .collect();
+            let started = std::time::Instant::now();
+            let folded: Vec<Expr> = exprs
+                .iter()
+                .map(|expr| expr.specialize(&Environment::new()))
+                .collect();
+            let folding = started.elapsed();
+            let started = std::time::Instant::now();
+            let saturated: Vec<Expr> = exprs
+                .iter()
+                .map(|expr| saturate_simplify(expr, 200))
+                .collect();
+            let saturating = started.elapsed();
+            let size = |exprs: &[Expr]| exprs.iter().map(Expr::size).sum::<usize>();
+            eprintln!(
+                "depth {}: {} expressions of total size {}; folded to {} in {:?}, \
+                 saturated to {} in {:?}",
+                depth,
+                exprs.len(),
+                size(&exprs),
+                size(&folded),
+                folding,
+                size(&saturated),
+                saturating
+            );
+            for ((expr, folded), saturated) in exprs.iter().zip(&folded).zip(&saturated) {
+                assert!(
+                    saturated.size() <= folded.size(),
+                    "{}: folded to {}, saturated to {}",
+                    expr,
+                    folded,
+                    saturated
+                );
+            }
+            // Single literals and variables leave nothing to simplify.
+            if depth > 1 {
+                assert!(size(&saturated) < size(&folded));
+            }
+        }
+    }
+
+    #[test]
+    fn saturate_simplify_preserves_evaluation() {
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(-4));
+        env.set("y", Expr::Number(9));
+        for ty in [Type::Number, Type::Boolean] {
+            for expr in synthesize(ty, &env, 2) {
+                let simplified = saturate_simplify(&expr, 200);
+                assert!(
+                    simplified.size() <= expr.size(),
+                    "{} => {}",
+                    expr,
+                    simplified
+                );
+                assert_eq!(
+                    expr.evalute_in(&mut env.clone(), &Wrapping),
+                    simplified.evalute_in(&mut env.clone(), &Wrapping),
+                    "{} => {}",
+                    expr,
+                    simplified
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn generate_inputs_covers_branches() {
+        let x = || Box::new(Expr::Variable("x".into()));
+        let y = || Box::new(Expr::Variable("y".into()));
+        let n = |n| Box::new(Expr::Number(n));
+        // if (x < 5) { if (10 < y) { z = 1 } else { z = 2 } } else { while (y < x) { y = y + 1 } }
+        let stmt = Stmt::If {
+            condition: Expr::LessThan(x(), n(5)),
+            consequence: Stmt::If {
+                condition: Expr::LessThan(n(10), y()),
+                consequence: Stmt::Assign("z".into(), Expr::Number(1)).into(),
+                alternative: Stmt::Assign("z".into(), Expr::Number(2)).into(),
+            }
+            .into(),
+            alternative: Stmt::While {
+                condition: Expr::LessThan(y(), x()),
+                body: Stmt::Assign("y".into(), Expr::Add(y(), n(1))).into(),
+            }
+            .into(),
+        };
+        let inputs = generate_inputs(&stmt, &["x", "y"], 1000);
+        let covered: HashSet<Branch> = inputs
+            .iter()
+            .flat_map(|env| branches_taken(&stmt, env))
+            .collect();
+        for (path, taken) in [
+            (vec![], true),
+            (vec![], false),
+            (vec![0], true),
+            (vec![0], false),
+            (vec![1], true),
+            (vec![1], false),
+        ] {
+            assert!(
+                covered.contains(&(path.clone(), taken)),
+                "{:?} {}",
+                path,
+                taken
+            );
+        }
+        assert!(inputs.len() <= 6);
+        assert!(inputs.iter().any(|env| env["y"] == Expr::Number(11)));
+
+        assert_eq!(1, generate_inputs(&stmt, &["x", "y"], 1).len());
+        assert!(generate_inputs(&stmt, &["x", "y"], 0).is_empty());
+    }
+
+    #[test]
+    fn track_taint_follows_data_flow() {
+        let var = |name: &str| Box::new(Expr::Variable(name.into()));
+        // y = input + 1; z = 5; if (y < z) { w = (z = y) * 2 } else { w = 0 }; y = 0
+        let stmt = sequence(vec![
+            Stmt::Assign("y".into(), Expr::Add(var("input"), Expr::Number(1).into())),
+            Stmt::Assign("z".into(), Expr::Number(5)),
+            Stmt::If {
+                condition: Expr::LessThan(var("y"), var("z")),
+                consequence: Stmt::Assign(
+                    "w".into(),
+                    Expr::Multiply(
+                        Expr::AssignExpr("z".into(), var("y")).into(),
+                        Expr::Number(2).into(),
+                    ),
+                )
+                .into(),
+                alternative: Stmt::Assign("w".into(), Expr::Number(0)).into(),
+            },
+            Stmt::Assign("y".into(), Expr::Number(0)),
+        ]);
+        let mut env = Environment::new();
+        env.set("input", Expr::Number(1));
+        let report = track_taint(&stmt, env.clone(), &["input"], 100).unwrap();
+        assert_eq!(vec![vec![0], vec![1, 1, 0, 0]], report.assignments);
+        assert_eq!(vec![vec![1, 1, 0]], report.conditions);
+        assert_eq!(vec!["input", "w", "z"], report.tainted);
+        assert!(matches!(stmt.at(&[1, 1, 0, 0]), Some(Stmt::Assign(name, _)) if name == "w"));
+
+        // Taking the other branch stores only constants.
+        env.set("input", Expr::Number(10));
+        let report = track_taint(&stmt, env, &["input"], 100).unwrap();
+        assert_eq!(vec![vec![0]], report.assignments);
+        assert_eq!(vec!["input"], report.tainted);
+
+        // while (true) { x = input }
+        let forever = Stmt::While {
+            condition: Expr::Boolean(true),
+            body: Stmt::Assign("x".into(), *var("input")).into(),
+        };
+        let env: Environment = vec![("input".to_string(), Expr::Number(1))]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            Err(EvalError::StepLimitExceeded),
+            track_taint(&forever, env, &["input"], 1_000_000)
+        );
+    }
+
+    #[test]
+    fn information_flow() {
+        let var = |name: &str| Box::new(Expr::Variable(name.into()));
+        let mut labels = HashMap::new();
+        labels.insert("secret".to_string(), Label::High);
+        labels.insert("h".to_string(), Label::High);
+
+        // h = secret * 2; if (h < 10) { h = 0 } else { low = 1 }; low = (h = low) + 1
+        let stmt = sequence(vec![
+            Stmt::Assign(
+                "h".into(),
+                Expr::Multiply(var("secret"), Expr::Number(2).into()),
+            ),
+            Stmt::If {
+                condition: Expr::LessThan(var("h"), Expr::Number(10).into()),
+                consequence: Stmt::Assign("h".into(), Expr::Number(0)).into(),
+                alternative: Stmt::Assign("low".into(), Expr::Number(1)).into(),
+            },
+            Stmt::Assign(
+                "low".into(),
+                Expr::Add(
+                    Expr::AssignExpr("h".into(), var("low")).into(),
+                    Expr::Number(1).into(),
+                ),
+            ),
+        ]);
+        let violations = check_information_flow(&stmt, &labels).unwrap_err();
+        assert_eq!(
+            vec![
+                "`low = 1` at [1, 0, 1] leaks High data into Low variable `low` \
+                  through an enclosing condition"
+            ],
+            violations
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+
+        let explicit = Stmt::While {
+            condition: Expr::LessThan(var("low"), Expr::Number(3).into()),
+            body: Stmt::Assign(
+                "low".into(),
+                Expr::Add(var("low"), Expr::PostIncrement("h".into()).into()),
+            )
+            .into(),
+        };
+        let violations = check_information_flow(&explicit, &labels).unwrap_err();
+        assert_eq!(1, violations.len());
+        assert_eq!(vec![0], violations[0].path);
+        assert!(!violations[0].implicit);
+
+        // while (low++ < h) {}: low counts up to h + 1 in the condition.
+        let counting = Stmt::While {
+            condition: Expr::LessThan(Expr::PostIncrement("low".into()).into(), var("h")),
+            body: Stmt::DoNothing.into(),
+        };
+        let violations = check_information_flow(&counting, &labels).unwrap_err();
+        assert_eq!(1, violations.len());
+        assert_eq!("low", violations[0].variable);
+        assert!(violations[0].implicit);
+
+        labels.insert("low".to_string(), Label::High);
+        assert_eq!(Ok(()), check_information_flow(&stmt, &labels));
+    }
+
+    #[test]
+    fn rule_definitions() {
+        assert_eq!(
+            "<s1, env> => env1    <s2, env1> => env2\n\
+             --------------------------------------- (Sequence)\n\
+             <s1; s2, env> => env2",
+            Rule::Sequence.definition().to_string()
+        );
+        for rule in Rule::ALL.iter() {
+            assert_eq!(format!("{:?}", rule), rule.definition().name);
+        }
+    }
+
+    #[test]
+    fn independent_statements_run_in_parallel() {
+        let var = |name: &str| Box::new(Expr::Variable(name.into()));
+        let count = |name: &str, to| Stmt::While {
+            condition: Expr::LessThan(var(name), Expr::Number(to).into()),
+            body: Stmt::Assign(
+                name.into(),
+                Expr::Add(Expr::PostIncrement(name.into()).into(), var("step")),
+            )
+            .into(),
+        };
+        // a = 0; b = 0; while (a < 50) { ... }; while (b < 70) { ... }; c = a + b; d = step
+        let stmt = sequence(vec![
+            Stmt::Assign("a".into(), Expr::Number(0)),
+            Stmt::Assign("b".into(), Expr::Number(0)),
+            count("a", 50),
+            count("b", 70),
+            Stmt::Assign("c".into(), Expr::Add(var("a"), var("b"))),
+            Stmt::Assign("d".into(), Expr::Variable("step".into())),
+        ]);
+        assert_eq!(
+            vec![2, 2, 2],
+            independent_groups(&stmt)
+                .iter()
+                .map(Vec::len)
+                .collect::<Vec<_>>()
+        );
+        let mut names: Vec<_> = count("a", 50).write_set().into_iter().collect();
+        names.sort();
+        assert_eq!(vec!["a"], names);
+        assert!(count("a", 1).read_set().contains("step"));
+
+        let mut env = Environment::new();
+        env.set("step", Expr::Number(2));
+        assert_env_eq!(
+            stmt.evalute(env.clone()),
+            evaluate_independent_parallel(&stmt, env).unwrap()
+        );
+        assert_eq!(
+            Some(EvalError::UndefinedVariable("step".into())),
+            evaluate_independent_parallel(&stmt, Environment::new()).err()
+        );
+    }
+
+    #[test]
+    fn obfuscate_preserves_behavior() {
+        let stmt = sum_loop();
+        let (obfuscated, names) = obfuscate(&stmt, true);
+        assert!(names.values().all(|name| name.starts_with('v')));
+        assert!(!names.values().any(|name| stmt.assigns(name)));
+        assert_ne!(stmt, obfuscated);
+        assert_eq!(stmt.size(), obfuscated.size());
+        assert_eq!(
+            Ok(()),
+            preserves_behavior(&stmt, &obfuscated, &names, &sum_loop_inputs(), 1000)
+        );
+
+        // Dropping a statement is caught by the harness.
+        let (broken, _) = obfuscate(&Stmt::DoNothing, false);
+        assert!(preserves_behavior(&stmt, &broken, &names, &sum_loop_inputs(), 1000).is_err());
+    }
+
+    #[test]
+    fn select_statements() {
+        // x = 0; while (x < 3) { if (x < 1) { y = x } else { x = x + 1 }; x = x + 1 }
+        let increment = Stmt::Assign(
+            "x".into(),
+            Expr::Add(Expr::Variable("x".into()).into(), Expr::Number(1).into()),
+        );
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign("x".into(), Expr::Number(0)).into(),
+            second: Stmt::While {
+                condition: Expr::LessThan(
+                    Expr::Variable("x".into()).into(),
+                    Expr::Number(3).into(),
+                ),
+                body: Stmt::Sequence {
+                    first: Stmt::If {
+                        condition: Expr::LessThan(
+                            Expr::Variable("x".into()).into(),
+                            Expr::Number(1).into(),
+                        ),
+                        consequence: Stmt::Assign("y".into(), Expr::Variable("x".into())).into(),
+                        alternative: increment.clone().into(),
+                    }
+                    .into(),
+                    second: increment.into(),
+                }
+                .into(),
+            }
+            .into(),
+        };
+        let paths = |selector| {
+            stmt.select(selector)
+                .unwrap()
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![vec![1, 0, 1]], paths("stmt.while > assign[var=x]"));
+        assert_eq!(
+            vec![vec![1, 0, 0, 1], vec![1, 0, 1]],
+            paths("while assign[var=x]")
+        );
+        assert_eq!(vec![vec![1, 0, 0, 0]], paths("if > assign[var=y]"));
+        assert_eq!(vec![vec![], vec![1, 0]], paths("sequence"));
+        assert_eq!(Vec::<Vec<usize>>::new(), paths("if while"));
+
+        assert!(stmt.select("loop").is_err());
+        assert!(stmt.select("assign[name=x]").is_err());
+        assert!(stmt.select("while >").is_err());
+    }
+
+    #[test]
+    fn equivalent_programs() {
+        let mod_3 = modular::Modular::new(3).unwrap();
+        let mod_7 = modular::Modular::new(7).unwrap();
+        // y = x * 2  vs  y = x + x
+        let double = Stmt::Assign(
+            "y".into(),
+            Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(2).into()),
+        );
+        let sum = Stmt::Assign(
+            "y".into(),
+            Expr::Add(
+                Expr::Variable("x".into()).into(),
+                Expr::Variable("x".into()).into(),
+            ),
+        );
+        let variables = ["x".into(), "y".into()];
+        assert_eq!(Ok(None), double.equivalent(&sum, &variables, &mod_7));
+
+        // y = x * x * x  vs  y = x * 1: equal modulo 3, where every x has
+        // x * x * x == x, but not modulo 7. A plain `y = x` would differ
+        // on a boolean `x`, which it copies without failing.
+        let x = || Box::new(Expr::Variable("x".into()));
+        let cube = Stmt::Assign(
+            "y".into(),
+            Expr::Multiply(Expr::Multiply(x(), x()).into(), x()),
+        );
+        let identity = Stmt::Assign("y".into(), Expr::Multiply(x(), Expr::Number(1).into()));
+        assert_eq!(Ok(None), cube.equivalent(&identity, &variables, &mod_3));
+        let counterexample = cube
+            .equivalent(&identity, &variables, &mod_7)
+            .unwrap()
+            .unwrap();
+        assert_eq!(Ok(&Expr::Number(2)), counterexample.get("x"));
+
+        // Failing runs are compared by their errors, and ill-typed
+        // programs do not panic.
+        let unbound = Stmt::Assign("y".into(), Expr::Variable("z".into()));
+        assert_eq!(Ok(None), unbound.equivalent(&unbound, &variables, &mod_3));
+        assert!(matches!(
+            identity.equivalent(&unbound, &variables, &mod_3),
+            Ok(Some(_))
+        ));
+        let ill_typed = Stmt::Assign("y".into(), Expr::Add(Expr::Boolean(true).into(), x()));
+        assert!(matches!(
+            ill_typed.equivalent(&identity, &variables, &mod_3),
+            Ok(Some(_))
+        ));
+        assert_eq!(
+            Ok(None),
+            ill_typed.equivalent(&ill_typed, &variables, &mod_3)
+        );
+
+        let looping = Stmt::While {
+            condition: Expr::Boolean(false),
+            body: Stmt::DoNothing.into(),
+        };
+        assert_eq!(
+            Err(Undecided::HasLoop),
+            looping.equivalent(&identity, &variables, &mod_3)
+        );
+        let many: Vec<String> = (0..10).map(|i| format!("x{}", i)).collect();
+        assert_eq!(
+            Err(Undecided::TooManyInputs),
+            identity.equivalent(&identity, &many, &mod_7)
+        );
+    }
+
+    #[test]
+    fn inequivalent_programs() {
+        // if (x < 5) { y = 1 } else { y = 0 }  vs  if (x < 4) { y = 1 } else { y = 0 }
+        let branch = |n| Stmt::If {
+            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(n).into()),
+            consequence: Stmt::Assign("y".into(), Expr::Number(1)).into(),
+            alternative: Stmt::Assign("y".into(), Expr::Number(0)).into(),
+        };
+        let variables = ["x".into(), "y".into()];
+        let counterexample = branch(5)
+            .equivalent(&branch(4), &variables, &modular::Modular::new(7).unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(Ok(&Expr::Number(4)), counterexample.get("x"));
     }
 }
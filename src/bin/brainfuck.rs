@@ -0,0 +1,615 @@
+use std::collections::HashMap;
+
+const TAPE_SIZE: usize = 30000;
+
+#[derive(PartialEq, Eq, Debug)]
+pub(crate) enum Error {
+    /// The `[` at this position has no matching `]`.
+    UnmatchedOpen(usize),
+    /// The `]` at this position has no matching `[`.
+    UnmatchedClose(usize),
+    /// The `<` or `>` at this position moved the pointer off the tape.
+    PointerOutOfRange(usize),
+    /// A cell only holds 0..=255.
+    NumberOutOfRange(i64),
+    /// The variable has no cell.
+    UndefinedVariable(String),
+}
+
+struct Interpreter {
+    code: Vec<char>,
+    jumps: HashMap<usize, usize>,
+    tape: Vec<u8>,
+    pointer: usize,
+    pc: usize,
+}
+
+impl Interpreter {
+    fn new(code: &str) -> Result<Self, Error> {
+        let code: Vec<char> = code.chars().filter(|c| "><+-.,[]".contains(*c)).collect();
+        let mut jumps = HashMap::new();
+        let mut stack = Vec::new();
+        for (i, c) in code.iter().enumerate() {
+            match c {
+                '[' => stack.push(i),
+                ']' => {
+                    let open = stack.pop().ok_or(Error::UnmatchedClose(i))?;
+                    jumps.insert(open, i);
+                    jumps.insert(i, open);
+                }
+                _ => {}
+            }
+        }
+        if let Some(&open) = stack.last() {
+            return Err(Error::UnmatchedOpen(open));
+        }
+        Ok(Self {
+            code,
+            jumps,
+            tape: vec![0; TAPE_SIZE],
+            pointer: 0,
+            pc: 0,
+        })
+    }
+
+    fn run(&mut self, input: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut input = input.iter();
+        let mut output = Vec::new();
+        while self.pc < self.code.len() {
+            match self.code[self.pc] {
+                '>' if self.pointer + 1 < TAPE_SIZE => self.pointer += 1,
+                '<' if self.pointer > 0 => self.pointer -= 1,
+                '>' | '<' => return Err(Error::PointerOutOfRange(self.pc)),
+                '+' => self.tape[self.pointer] = self.tape[self.pointer].wrapping_add(1),
+                '-' => self.tape[self.pointer] = self.tape[self.pointer].wrapping_sub(1),
+                '.' => output.push(self.tape[self.pointer]),
+                ',' => self.tape[self.pointer] = input.next().copied().unwrap_or(0),
+                '[' => {
+                    if self.tape[self.pointer] == 0 {
+                        self.pc = self.jumps[&self.pc];
+                    }
+                }
+                ']' => {
+                    if self.tape[self.pointer] != 0 {
+                        self.pc = self.jumps[&self.pc];
+                    }
+                }
+                _ => unreachable!(),
+            }
+            self.pc += 1;
+        }
+        Ok(output)
+    }
+}
+
+// The subset of SIMPLE whose values fit in one cell (0..=255)
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone)]
+pub(crate) enum Expr {
+    Number(i64),
+    Boolean(bool),
+    Variable(String),
+    Add(Box<Expr>, Box<Expr>),
+    Multiply(Box<Expr>, Box<Expr>),
+    LessThan(Box<Expr>, Box<Expr>),
+}
+
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone)]
+pub(crate) enum Stmt {
+    DoNothing,
+    Assign(String, Expr),
+    If {
+        condition: Expr,
+        consequence: Box<Stmt>,
+        alternative: Box<Stmt>,
+    },
+    Sequence {
+        first: Box<Stmt>,
+        second: Box<Stmt>,
+    },
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+    },
+}
+
+type Cell = usize;
+
+/// Reads every variable from the input in `variables` order, runs the
+/// program, then writes every variable to the output in the same order.
+pub(crate) struct CompiledProgram {
+    code: String,
+    variables: Vec<String>,
+}
+
+impl CompiledProgram {
+    pub(crate) fn run(&self, env: &HashMap<String, u8>) -> Result<HashMap<String, u8>, Error> {
+        let input: Vec<u8> = self
+            .variables
+            .iter()
+            .map(|name| env.get(name).copied().unwrap_or(0))
+            .collect();
+        let output = Interpreter::new(&self.code)?.run(&input)?;
+        let mut result = env.clone();
+        for (name, value) in self.variables.iter().zip(output) {
+            result.insert(name.clone(), value);
+        }
+        Ok(result)
+    }
+}
+
+pub(crate) struct Compiler {
+    code: String,
+    variables: Vec<String>,
+    pointer: Cell,
+    cell_count: usize,
+}
+
+impl Compiler {
+    pub(crate) fn compile(stmt: &Stmt) -> Result<CompiledProgram, Error> {
+        let mut variables = Vec::new();
+        collect_variables(stmt, &mut variables);
+        let mut compiler = Self {
+            code: String::new(),
+            cell_count: variables.len(),
+            variables,
+            pointer: 0,
+        };
+        for cell in 0..compiler.variables.len() {
+            compiler.goto(cell);
+            compiler.code.push(',');
+        }
+        compiler.compile_stmt(stmt)?;
+        for cell in 0..compiler.variables.len() {
+            compiler.goto(cell);
+            compiler.code.push('.');
+        }
+        Ok(CompiledProgram {
+            code: compiler.code,
+            variables: compiler.variables,
+        })
+    }
+
+    fn goto(&mut self, cell: Cell) {
+        while self.pointer < cell {
+            self.code.push('>');
+            self.pointer += 1;
+        }
+        while self.pointer > cell {
+            self.code.push('<');
+            self.pointer -= 1;
+        }
+    }
+
+    fn temporary(&mut self) -> Cell {
+        self.cell_count += 1;
+        self.cell_count - 1
+    }
+
+    fn variable(&self, name: &str) -> Result<Cell, Error> {
+        self.variables
+            .iter()
+            .position(|v| v == name)
+            .ok_or_else(|| Error::UndefinedVariable(name.into()))
+    }
+
+    fn clear(&mut self, cell: Cell) {
+        self.goto(cell);
+        self.code.push_str("[-]");
+    }
+
+    fn increment(&mut self, cell: Cell, n: i64) {
+        self.goto(cell);
+        for _ in 0..n {
+            self.code.push('+');
+        }
+    }
+
+    // Adds `src` to each of `dsts`, leaving `src` at zero
+    fn transfer(&mut self, src: Cell, dsts: &[Cell]) {
+        self.goto(src);
+        self.code.push_str("[-");
+        for &dst in dsts {
+            self.goto(dst);
+            self.code.push('+');
+        }
+        self.goto(src);
+        self.code.push(']');
+    }
+
+    // Adds `src` to `dst`, preserving `src`
+    fn add_to(&mut self, src: Cell, dst: Cell) {
+        let saved = self.temporary();
+        self.transfer(src, &[dst, saved]);
+        self.transfer(saved, &[src]);
+    }
+
+    // Writes the value of `expr` into `dst` (booleans as 1 / 0)
+    fn compile_expr(&mut self, expr: &Expr, dst: Cell) -> Result<(), Error> {
+        self.clear(dst);
+        match expr {
+            Expr::Number(n) => {
+                if !(0..=255).contains(n) {
+                    return Err(Error::NumberOutOfRange(*n));
+                }
+                self.increment(dst, *n);
+            }
+            Expr::Boolean(b) => {
+                if *b {
+                    self.increment(dst, 1);
+                }
+            }
+            Expr::Variable(name) => {
+                let cell = self.variable(name)?;
+                self.add_to(cell, dst);
+            }
+            Expr::Add(l, r) => {
+                let a = self.temporary();
+                let b = self.temporary();
+                self.compile_expr(l, a)?;
+                self.compile_expr(r, b)?;
+                self.transfer(a, &[dst]);
+                self.transfer(b, &[dst]);
+            }
+            Expr::Multiply(l, r) => {
+                let a = self.temporary();
+                let b = self.temporary();
+                self.compile_expr(l, a)?;
+                self.compile_expr(r, b)?;
+                self.goto(a);
+                self.code.push_str("[-");
+                self.add_to(b, dst);
+                self.goto(a);
+                self.code.push(']');
+            }
+            Expr::LessThan(l, r) => {
+                let a = self.temporary();
+                let b = self.temporary();
+                let t = self.temporary();
+                let flag = self.temporary();
+                self.compile_expr(l, a)?;
+                self.compile_expr(r, b)?;
+                // Count both down while `b` is non-zero; `a` reaching zero first means true
+                self.goto(b);
+                self.code.push('[');
+                self.increment(flag, 1);
+                self.add_to(a, t);
+                self.goto(t);
+                self.code.push('[');
+                self.goto(a);
+                self.code.push('-');
+                self.goto(b);
+                self.code.push('-');
+                self.clear(flag);
+                self.clear(t);
+                self.code.push(']');
+                self.goto(flag);
+                self.code.push('[');
+                self.increment(dst, 1);
+                self.clear(b);
+                self.clear(flag);
+                self.code.push(']');
+                self.goto(b);
+                self.code.push(']');
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+        match stmt {
+            Stmt::DoNothing => {}
+            Stmt::Assign(name, expr) => {
+                let t = self.temporary();
+                self.compile_expr(expr, t)?;
+                let cell = self.variable(name)?;
+                self.clear(cell);
+                self.transfer(t, &[cell]);
+            }
+            Stmt::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let t = self.temporary();
+                let otherwise = self.temporary();
+                self.compile_expr(condition, t)?;
+                self.clear(otherwise);
+                self.increment(otherwise, 1);
+                self.goto(t);
+                self.code.push('[');
+                self.compile_stmt(consequence)?;
+                self.clear(otherwise);
+                self.clear(t);
+                self.code.push(']');
+                self.goto(otherwise);
+                self.code.push('[');
+                self.compile_stmt(alternative)?;
+                self.clear(otherwise);
+                self.code.push(']');
+            }
+            Stmt::Sequence { first, second } => {
+                self.compile_stmt(first)?;
+                self.compile_stmt(second)?;
+            }
+            Stmt::While { condition, body } => {
+                let t = self.temporary();
+                self.compile_expr(condition, t)?;
+                self.goto(t);
+                self.code.push('[');
+                self.compile_stmt(body)?;
+                self.compile_expr(condition, t)?;
+                self.goto(t);
+                self.code.push(']');
+            }
+        }
+        Ok(())
+    }
+}
+
+fn collect_variables(stmt: &Stmt, variables: &mut Vec<String>) {
+    fn collect_expr(expr: &Expr, variables: &mut Vec<String>) {
+        match expr {
+            Expr::Number(_) | Expr::Boolean(_) => {}
+            Expr::Variable(name) => {
+                if !variables.contains(name) {
+                    variables.push(name.clone());
+                }
+            }
+            Expr::Add(l, r) | Expr::Multiply(l, r) | Expr::LessThan(l, r) => {
+                collect_expr(l, variables);
+                collect_expr(r, variables);
+            }
+        }
+    }
+
+    match stmt {
+        Stmt::DoNothing => {}
+        Stmt::Assign(name, expr) => {
+            if !variables.contains(name) {
+                variables.push(name.clone());
+            }
+            collect_expr(expr, variables);
+        }
+        Stmt::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            collect_expr(condition, variables);
+            collect_variables(consequence, variables);
+            collect_variables(alternative, variables);
+        }
+        Stmt::Sequence { first, second } => {
+            collect_variables(first, variables);
+            collect_variables(second, variables);
+        }
+        Stmt::While { condition, body } => {
+            collect_expr(condition, variables);
+            collect_variables(body, variables);
+        }
+    }
+}
+
+fn main() {
+    let stmt = Stmt::While {
+        condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(5).into()),
+        body: Stmt::Assign(
+            "x".into(),
+            Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(3).into()),
+        )
+        .into(),
+    };
+    let compiled = match Compiler::compile(&stmt) {
+        Ok(compiled) => compiled,
+        Err(error) => {
+            eprintln!("{:?}", error);
+            std::process::exit(1);
+        }
+    };
+    println!("{}", compiled.code);
+    let mut env = HashMap::new();
+    env.insert("x".into(), 1);
+    match compiled.run(&env) {
+        Ok(result) => println!("{:?}", result),
+        Err(error) => {
+            eprintln!("{:?}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_hello() {
+        let code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.";
+        assert_eq!(
+            Ok(b"Hello".to_vec()),
+            Interpreter::new(code).unwrap().run(&[])
+        );
+    }
+
+    #[test]
+    fn run_input() {
+        assert_eq!(
+            Ok(vec![3, 2]),
+            Interpreter::new(",+.,.").unwrap().run(&[2, 2])
+        );
+    }
+
+    #[test]
+    fn run_errors() {
+        assert_eq!(
+            Some(Error::UnmatchedOpen(1)),
+            Interpreter::new("+[[-]").err()
+        );
+        assert_eq!(
+            Some(Error::UnmatchedClose(4)),
+            Interpreter::new("+[-]]").err()
+        );
+        let run = |code: &str| Interpreter::new(code).unwrap().run(&[]);
+        assert_eq!(Err(Error::PointerOutOfRange(1)), run("+<+"));
+        assert_eq!(
+            Err(Error::PointerOutOfRange(TAPE_SIZE - 1)),
+            run(&">".repeat(TAPE_SIZE))
+        );
+        assert_eq!(Ok(vec![]), run(&">".repeat(TAPE_SIZE - 1)));
+    }
+
+    /// The big-step semantics of the subset, the compiled programs'
+    /// reference.
+    impl Expr {
+        fn evalute(&self, env: &HashMap<String, Expr>) -> Expr {
+            match self {
+                Self::Number(_) | Self::Boolean(_) => self.clone(),
+                Self::Variable(name) => env[name].clone(),
+                Self::Add(l, r) | Self::Multiply(l, r) | Self::LessThan(l, r) => {
+                    match (self, l.evalute(env), r.evalute(env)) {
+                        (Self::Add(..), Self::Number(a), Self::Number(b)) => Self::Number(a + b),
+                        (Self::Multiply(..), Self::Number(a), Self::Number(b)) => {
+                            Self::Number(a * b)
+                        }
+                        (Self::LessThan(..), Self::Number(a), Self::Number(b)) => {
+                            Self::Boolean(a < b)
+                        }
+                        _ => panic!("invalid expr"),
+                    }
+                }
+            }
+        }
+    }
+
+    impl Stmt {
+        fn evalute(&self, mut env: HashMap<String, Expr>) -> HashMap<String, Expr> {
+            match self {
+                Self::DoNothing => env,
+                Self::Assign(name, expr) => {
+                    let value = expr.evalute(&env);
+                    env.insert(name.clone(), value);
+                    env
+                }
+                Self::If {
+                    condition,
+                    consequence,
+                    alternative,
+                } => match condition.evalute(&env) {
+                    Expr::Boolean(true) => consequence.evalute(env),
+                    Expr::Boolean(false) => alternative.evalute(env),
+                    _ => panic!("invalid condition"),
+                },
+                Self::Sequence { first, second } => second.evalute(first.evalute(env)),
+                Self::While { condition, body } => loop {
+                    match condition.evalute(&env) {
+                        Expr::Boolean(true) => env = body.evalute(env),
+                        Expr::Boolean(false) => return env,
+                        _ => panic!("invalid condition"),
+                    }
+                },
+            }
+        }
+    }
+
+    /// Runs `stmt` compiled on `env`, and checks it leaves the same cells
+    /// as evaluating it.
+    fn assert_agrees(stmt: &Stmt, env: &[(&str, Expr)]) {
+        let cells = |env: HashMap<String, Expr>| -> HashMap<String, u8> {
+            env.into_iter()
+                .map(|(name, value)| match value {
+                    Expr::Number(n) => (name, n as u8),
+                    Expr::Boolean(b) => (name, b as u8),
+                    _ => panic!("not a value"),
+                })
+                .collect()
+        };
+        let env: HashMap<String, Expr> = env
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        let compiled = Compiler::compile(stmt).unwrap();
+        assert_eq!(
+            Ok(cells(stmt.evalute(env.clone()))),
+            compiled.run(&cells(env)),
+            "{}",
+            compiled.code
+        );
+    }
+
+    #[test]
+    fn compile_assign() {
+        let stmt = Stmt::Assign(
+            "y".into(),
+            Expr::Add(
+                Expr::Variable("x".into()).into(),
+                Expr::Multiply(Expr::Number(2).into(), Expr::Variable("x".into()).into()).into(),
+            ),
+        );
+        for x in [0, 3, 85] {
+            assert_agrees(&stmt, &[("x", Expr::Number(x)), ("y", Expr::Number(100))]);
+        }
+    }
+
+    #[test]
+    fn compile_if() {
+        let stmt = Stmt::If {
+            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(3).into()),
+            consequence: Stmt::Assign("y".into(), Expr::Boolean(true)).into(),
+            alternative: Stmt::Assign("y".into(), Expr::Boolean(false)).into(),
+        };
+        for x in [0, 2, 3, 4, 255] {
+            assert_agrees(
+                &stmt,
+                &[("x", Expr::Number(x)), ("y", Expr::Boolean(false))],
+            );
+        }
+    }
+
+    #[test]
+    fn compile_sequence() {
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign("x".into(), Expr::Number(2)).into(),
+            second: Stmt::Assign(
+                "x".into(),
+                Expr::Multiply(
+                    Expr::Variable("x".into()).into(),
+                    Expr::Variable("x".into()).into(),
+                ),
+            )
+            .into(),
+        };
+        assert_agrees(&stmt, &[]);
+        assert_agrees(&stmt, &[("x", Expr::Number(7))]);
+    }
+
+    #[test]
+    fn compile_while() {
+        let stmt = Stmt::While {
+            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(5).into()),
+            body: Stmt::Assign(
+                "x".into(),
+                Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(3).into()),
+            )
+            .into(),
+        };
+        for x in [1, 2, 5, 80] {
+            assert_agrees(&stmt, &[("x", Expr::Number(x))]);
+        }
+    }
+
+    #[test]
+    fn compile_errors() {
+        let stmt = Stmt::Assign("x".into(), Expr::Number(256));
+        assert_eq!(
+            Some(Error::NumberOutOfRange(256)),
+            Compiler::compile(&stmt).err()
+        );
+        let stmt = Stmt::Assign("x".into(), Expr::Number(-1));
+        assert_eq!(
+            Some(Error::NumberOutOfRange(-1)),
+            Compiler::compile(&stmt).err()
+        );
+    }
+}
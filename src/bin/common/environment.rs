@@ -0,0 +1,244 @@
+//! The variable bindings both semantics evaluate programs in. Each binary
+//! includes this file with `#[path]` and names its own value type.
+
+use std::collections::{btree_map, BTreeMap};
+use std::fmt::{self};
+use std::fs;
+use std::path::Path;
+
+/// What an environment binds variables to.
+pub trait Value: Clone + fmt::Display {
+    /// The value written as `text` in an environment file, such as `1`
+    /// or `true`.
+    fn parse_literal(text: &str) -> Option<Self>;
+}
+
+/// A lookup of a variable that is not bound. Each binary turns it into
+/// its own error type with `From`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct UndefinedVariable(pub String);
+
+impl fmt::Display for UndefinedVariable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "undefined variable `{}`", self.0)
+    }
+}
+
+/// Variable bindings, kept in nested scopes. Lookups search from the
+/// innermost scope outwards; iteration visits each visible binding once,
+/// in name order.
+#[derive(Clone)]
+pub struct Environment<V> {
+    /// Never empty; the last scope is the innermost.
+    scopes: Vec<BTreeMap<String, V>>,
+}
+
+#[allow(dead_code)]
+impl<V: Value> Environment<V> {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![BTreeMap::new()],
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<&V, UndefinedVariable> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .ok_or_else(|| UndefinedVariable(name.into()))
+    }
+
+    /// Updates the innermost binding of `name`, or binds it in the
+    /// innermost scope if there is none.
+    pub fn set(&mut self, name: &str, value: V) {
+        let scope = match self
+            .scopes
+            .iter()
+            .rposition(|scope| scope.contains_key(name))
+        {
+            Some(index) => &mut self.scopes[index],
+            None => self.scopes.last_mut().unwrap(),
+        };
+        scope.insert(name.into(), value);
+    }
+
+    /// Removes the innermost binding of `name`, uncovering any outer one.
+    pub fn remove(&mut self, name: &str) -> Option<V> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.remove(name))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_ok()
+    }
+
+    /// Keeps only the bindings `keep` accepts, in every scope.
+    pub fn retain(&mut self, mut keep: impl FnMut(&String, &mut V) -> bool) {
+        for scope in &mut self.scopes {
+            scope.retain(&mut keep);
+        }
+    }
+
+    /// Parses bindings such as `x = 1, flag = true`, separated by commas
+    /// or newlines. Values are literals as `Value::parse_literal` reads
+    /// them. Blank lines and lines starting with `#` are skipped, and the
+    /// braces `Display` adds are accepted, so a printed environment parses
+    /// back.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let source = source.trim();
+        let source = source
+            .strip_prefix('{')
+            .and_then(|inner| inner.strip_suffix('}'))
+            .unwrap_or(source);
+        let mut env = Self::new();
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            for binding in line.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+                let (name, value) = binding.split_once('=').ok_or_else(|| {
+                    error(format!("expected `name = value`, found `{}`", binding))
+                })?;
+                let (name, value) = (name.trim(), value.trim());
+                let is_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !is_name {
+                    return Err(error(format!("invalid variable name `{}`", name)));
+                }
+                let value = V::parse_literal(value)
+                    .ok_or_else(|| error(format!("invalid value `{}` for `{}`", value, name)))?;
+                env.set(name, value);
+            }
+        }
+        Ok(env)
+    }
+
+    /// Reads a `.env`-style file of `name = value` lines, as `parse`
+    /// accepts them.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        fs::read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|source| Self::parse(&source))
+            .map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    /// A new, empty scope inside this one. Variables first assigned in it
+    /// are dropped by `parent_scope`; assignments to outer ones are kept.
+    pub fn child_scope(&self) -> Self {
+        let mut child = self.clone();
+        child.scopes.push(BTreeMap::new());
+        child
+    }
+
+    /// Leaves the innermost scope. The outermost scope is never left.
+    pub fn parent_scope(mut self) -> Self {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+        self
+    }
+
+    /// The visible bindings in name order; shadowed ones are skipped.
+    pub fn iter(&self) -> btree_map::IntoIter<&String, &V> {
+        let mut visible = BTreeMap::new();
+        for scope in &self.scopes {
+            visible.extend(scope);
+        }
+        visible.into_iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scopes.iter().all(BTreeMap::is_empty)
+    }
+}
+
+impl<V: Value> Default for Environment<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Two environments are equal when the same bindings are visible,
+/// however they are split into scopes.
+impl<V: Value + PartialEq> PartialEq for Environment<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<V: Value + Eq> Eq for Environment<V> {}
+
+impl<V: Value + fmt::Debug> fmt::Debug for Environment<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<V: Value> std::ops::Index<&str> for Environment<V> {
+    type Output = V;
+
+    fn index(&self, name: &str) -> &V {
+        self.get(name).unwrap_or_else(|error| panic!("{}", error))
+    }
+}
+
+impl<V> IntoIterator for Environment<V> {
+    type Item = (String, V);
+    type IntoIter = btree_map::IntoIter<String, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let mut visible = BTreeMap::new();
+        for scope in self.scopes {
+            visible.extend(scope);
+        }
+        visible.into_iter()
+    }
+}
+
+impl<'a, V: Value> IntoIterator for &'a Environment<V> {
+    type Item = (&'a String, &'a V);
+    type IntoIter = btree_map::IntoIter<&'a String, &'a V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<V: Value> std::iter::FromIterator<(String, V)> for Environment<V> {
+    fn from_iter<I: IntoIterator<Item = (String, V)>>(bindings: I) -> Self {
+        let mut env = Self::new();
+        env.extend(bindings);
+        env
+    }
+}
+
+impl<V: Value> Extend<(String, V)> for Environment<V> {
+    fn extend<I: IntoIterator<Item = (String, V)>>(&mut self, bindings: I) {
+        for (name, value) in bindings {
+            self.set(&name, value);
+        }
+    }
+}
+
+impl<V: Value> fmt::Display for Environment<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "{{")?;
+        for (i, (name, value)) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} = {}", name, value)?;
+        }
+        write!(f, "}}")
+    }
+}
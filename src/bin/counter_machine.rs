@@ -0,0 +1,438 @@
+use std::collections::HashMap;
+use std::fmt::{self};
+
+type Register = usize;
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum Instruction {
+    Inc(Register),
+    DecJz(Register, usize),
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            Self::Inc(r) => write!(f, "INC r{}", r),
+            Self::DecJz(r, target) => write!(f, "DECJZ r{}, {}", r, target),
+        }
+    }
+}
+
+impl fmt::Debug for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "<<{}>>", self)
+    }
+}
+
+struct CounterMachine {
+    program: Vec<Instruction>,
+    registers: Vec<u64>,
+    pc: usize,
+}
+
+impl CounterMachine {
+    fn new(program: Vec<Instruction>, registers: Vec<u64>) -> Self {
+        Self {
+            program,
+            registers,
+            pc: 0,
+        }
+    }
+
+    fn is_halted(&self) -> bool {
+        self.pc >= self.program.len()
+    }
+
+    fn step(&mut self) {
+        match self.program[self.pc] {
+            Instruction::Inc(r) => {
+                self.registers[r] += 1;
+                self.pc += 1;
+            }
+            Instruction::DecJz(r, target) => {
+                if self.registers[r] == 0 {
+                    self.pc = target;
+                } else {
+                    self.registers[r] -= 1;
+                    self.pc += 1;
+                }
+            }
+        }
+    }
+
+    fn run(&mut self) {
+        while !self.is_halted() {
+            self.step();
+        }
+    }
+}
+
+// The subset of SIMPLE over natural numbers
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone)]
+pub(crate) enum Expr {
+    Number(i64),
+    Boolean(bool),
+    Variable(String),
+    Add(Box<Expr>, Box<Expr>),
+    Multiply(Box<Expr>, Box<Expr>),
+    LessThan(Box<Expr>, Box<Expr>),
+}
+
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone)]
+pub(crate) enum Stmt {
+    DoNothing,
+    Assign(String, Expr),
+    If {
+        condition: Expr,
+        consequence: Box<Stmt>,
+        alternative: Box<Stmt>,
+    },
+    Sequence {
+        first: Box<Stmt>,
+        second: Box<Stmt>,
+    },
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+    },
+}
+
+/// A program outside the subset the compiler handles.
+#[derive(PartialEq, Eq, Debug)]
+pub(crate) enum Error {
+    /// Registers only hold natural numbers.
+    NegativeNumber(i64),
+}
+
+/// Register 0 is never incremented, so `DECJZ r0, n` is an unconditional jump.
+const ZERO: Register = 0;
+
+pub(crate) struct CompiledProgram {
+    program: Vec<Instruction>,
+    variables: HashMap<String, Register>,
+    register_count: usize,
+}
+
+impl CompiledProgram {
+    pub(crate) fn run(&self, env: &HashMap<String, u64>) -> HashMap<String, u64> {
+        let mut registers = vec![0; self.register_count];
+        for (name, value) in env {
+            if let Some(&r) = self.variables.get(name) {
+                registers[r] = *value;
+            }
+        }
+        let mut machine = CounterMachine::new(self.program.clone(), registers);
+        machine.run();
+        let mut result = env.clone();
+        for (name, &r) in &self.variables {
+            result.insert(name.clone(), machine.registers[r]);
+        }
+        result
+    }
+}
+
+pub(crate) struct Compiler {
+    program: Vec<Instruction>,
+    variables: HashMap<String, Register>,
+    register_count: usize,
+}
+
+impl Compiler {
+    pub(crate) fn compile(stmt: &Stmt) -> Result<CompiledProgram, Error> {
+        let mut compiler = Self {
+            program: Vec::new(),
+            variables: HashMap::new(),
+            register_count: ZERO + 1,
+        };
+        compiler.compile_stmt(stmt)?;
+        Ok(CompiledProgram {
+            program: compiler.program,
+            variables: compiler.variables,
+            register_count: compiler.register_count,
+        })
+    }
+
+    fn here(&self) -> usize {
+        self.program.len()
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.program.push(instruction);
+        self.program.len() - 1
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        if let Instruction::DecJz(r, _) = self.program[at] {
+            self.program[at] = Instruction::DecJz(r, target);
+        }
+    }
+
+    fn jump(&mut self, target: usize) -> usize {
+        self.emit(Instruction::DecJz(ZERO, target))
+    }
+
+    fn temporary(&mut self) -> Register {
+        self.register_count += 1;
+        self.register_count - 1
+    }
+
+    fn variable(&mut self, name: &str) -> Register {
+        if let Some(&r) = self.variables.get(name) {
+            return r;
+        }
+        let r = self.temporary();
+        self.variables.insert(name.into(), r);
+        r
+    }
+
+    fn clear(&mut self, r: Register) {
+        let start = self.emit(Instruction::DecJz(r, 0));
+        self.jump(start);
+        let end = self.here();
+        self.patch(start, end);
+    }
+
+    // Adds `src` to `dst`, leaving `src` at zero
+    fn transfer(&mut self, src: Register, dst: Register) {
+        let start = self.emit(Instruction::DecJz(src, 0));
+        self.emit(Instruction::Inc(dst));
+        self.jump(start);
+        let end = self.here();
+        self.patch(start, end);
+    }
+
+    // Adds `src` to `dst`, preserving `src`
+    fn add_to(&mut self, src: Register, dst: Register) {
+        let saved = self.temporary();
+        let start = self.emit(Instruction::DecJz(src, 0));
+        self.emit(Instruction::Inc(dst));
+        self.emit(Instruction::Inc(saved));
+        self.jump(start);
+        let end = self.here();
+        self.patch(start, end);
+        self.transfer(saved, src);
+    }
+
+    // Writes the value of `expr` into `dst` (booleans as 1 / 0)
+    fn compile_expr(&mut self, expr: &Expr, dst: Register) -> Result<(), Error> {
+        self.clear(dst);
+        match expr {
+            Expr::Number(n) => {
+                if *n < 0 {
+                    return Err(Error::NegativeNumber(*n));
+                }
+                for _ in 0..*n {
+                    self.emit(Instruction::Inc(dst));
+                }
+            }
+            Expr::Boolean(b) => {
+                if *b {
+                    self.emit(Instruction::Inc(dst));
+                }
+            }
+            Expr::Variable(name) => {
+                let r = self.variable(name);
+                self.add_to(r, dst);
+            }
+            Expr::Add(l, r) => {
+                let a = self.temporary();
+                let b = self.temporary();
+                self.compile_expr(l, a)?;
+                self.compile_expr(r, b)?;
+                self.transfer(a, dst);
+                self.transfer(b, dst);
+            }
+            Expr::Multiply(l, r) => {
+                let a = self.temporary();
+                let b = self.temporary();
+                self.compile_expr(l, a)?;
+                self.compile_expr(r, b)?;
+                let start = self.emit(Instruction::DecJz(a, 0));
+                self.add_to(b, dst);
+                self.jump(start);
+                let end = self.here();
+                self.patch(start, end);
+            }
+            Expr::LessThan(l, r) => {
+                let a = self.temporary();
+                let b = self.temporary();
+                self.compile_expr(l, a)?;
+                self.compile_expr(r, b)?;
+                // Count both down; `b` running out first means false, `a` first means true
+                let start = self.emit(Instruction::DecJz(b, 0));
+                let less = self.emit(Instruction::DecJz(a, 0));
+                self.jump(start);
+                let truthy = self.here();
+                self.emit(Instruction::Inc(dst));
+                let end = self.here();
+                self.patch(start, end);
+                self.patch(less, truthy);
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+        match stmt {
+            Stmt::DoNothing => {}
+            Stmt::Assign(name, expr) => {
+                let t = self.temporary();
+                self.compile_expr(expr, t)?;
+                let r = self.variable(name);
+                self.clear(r);
+                self.transfer(t, r);
+            }
+            Stmt::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let t = self.temporary();
+                self.compile_expr(condition, t)?;
+                let branch = self.emit(Instruction::DecJz(t, 0));
+                self.compile_stmt(consequence)?;
+                let skip = self.jump(0);
+                let else_start = self.here();
+                self.compile_stmt(alternative)?;
+                let end = self.here();
+                self.patch(branch, else_start);
+                self.patch(skip, end);
+            }
+            Stmt::Sequence { first, second } => {
+                self.compile_stmt(first)?;
+                self.compile_stmt(second)?;
+            }
+            Stmt::While { condition, body } => {
+                let t = self.temporary();
+                let start = self.here();
+                self.compile_expr(condition, t)?;
+                let exit = self.emit(Instruction::DecJz(t, 0));
+                self.compile_stmt(body)?;
+                self.jump(start);
+                let end = self.here();
+                self.patch(exit, end);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn main() {
+    let stmt = Stmt::While {
+        condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(5).into()),
+        body: Stmt::Assign(
+            "x".into(),
+            Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(3).into()),
+        )
+        .into(),
+    };
+    let compiled = match Compiler::compile(&stmt) {
+        Ok(compiled) => compiled,
+        Err(error) => {
+            eprintln!("{:?}", error);
+            std::process::exit(1);
+        }
+    };
+    for (i, instruction) in compiled.program.iter().enumerate() {
+        println!("{:>4}: {}", i, instruction);
+    }
+    let mut env = HashMap::new();
+    env.insert("x".into(), 1);
+    println!("{:?}", compiled.run(&env));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_counter_machine() {
+        // Moves r1 into r2
+        let program = vec![
+            Instruction::DecJz(1, 3),
+            Instruction::Inc(2),
+            Instruction::DecJz(0, 0),
+        ];
+        let mut machine = CounterMachine::new(program, vec![0, 3, 1]);
+        machine.run();
+        assert_eq!(vec![0, 0, 4], machine.registers);
+    }
+
+    #[test]
+    fn compile_assign() {
+        let stmt = Stmt::Assign(
+            "y".into(),
+            Expr::Add(
+                Expr::Variable("x".into()).into(),
+                Expr::Multiply(Expr::Number(2).into(), Expr::Variable("x".into()).into()).into(),
+            ),
+        );
+        let mut env = HashMap::new();
+        env.insert("x".into(), 3);
+        env.insert("y".into(), 100);
+        let mut expected = env.clone();
+        expected.insert("y".into(), 9);
+        assert_eq!(expected, Compiler::compile(&stmt).unwrap().run(&env));
+    }
+
+    #[test]
+    fn compile_if() {
+        let stmt = Stmt::If {
+            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(3).into()),
+            consequence: Stmt::Assign("y".into(), Expr::Boolean(true)).into(),
+            alternative: Stmt::Assign("y".into(), Expr::Boolean(false)).into(),
+        };
+        let compiled = Compiler::compile(&stmt).unwrap();
+        for (x, y) in [(2, 1), (3, 0), (4, 0)] {
+            let mut env = HashMap::new();
+            env.insert("x".into(), x);
+            assert_eq!(Some(&y), compiled.run(&env).get("y"));
+        }
+    }
+
+    #[test]
+    fn compile_sequence() {
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign("x".into(), Expr::Number(2)).into(),
+            second: Stmt::Assign(
+                "x".into(),
+                Expr::Multiply(
+                    Expr::Variable("x".into()).into(),
+                    Expr::Variable("x".into()).into(),
+                ),
+            )
+            .into(),
+        };
+        let env = HashMap::new();
+        let mut expected = env.clone();
+        expected.insert("x".into(), 4);
+        assert_eq!(expected, Compiler::compile(&stmt).unwrap().run(&env));
+    }
+
+    #[test]
+    fn compile_while() {
+        let stmt = Stmt::While {
+            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(5).into()),
+            body: Stmt::Assign(
+                "x".into(),
+                Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(3).into()),
+            )
+            .into(),
+        };
+        let mut env = HashMap::new();
+        env.insert("x".into(), 1);
+        let mut expected = env.clone();
+        expected.insert("x".into(), 9);
+        assert_eq!(expected, Compiler::compile(&stmt).unwrap().run(&env));
+    }
+    #[test]
+    fn compile_negative_number() {
+        let stmt = Stmt::Assign("x".into(), Expr::Number(-1));
+        assert_eq!(
+            Some(Error::NegativeNumber(-1)),
+            Compiler::compile(&stmt).err()
+        );
+    }
+}
@@ -0,0 +1,384 @@
+//! Differential testing: derives a program from arbitrary bytes, runs it
+//! on every backend and checks that they agree with the big-step
+//! semantics. `check` takes the bytes, so a fuzzer can drive it directly.
+
+// Both semantics include `common/environment.rs`, each for its own values.
+#![allow(clippy::duplicate_mod)]
+
+#[allow(dead_code)]
+#[path = "big_step_semantics.rs"]
+mod big_step;
+
+#[allow(dead_code)]
+#[path = "small_step_semantics.rs"]
+mod small_step;
+
+#[allow(dead_code)]
+#[path = "counter_machine.rs"]
+mod counter_machine;
+
+#[allow(dead_code)]
+#[path = "brainfuck.rs"]
+mod brainfuck;
+
+use big_step::modular::Modular;
+use big_step::{
+    sequence, shrink, synthesize, Checked, Environment, EvalError, Expr, NumericSemantics, Stmt,
+    Type,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::io::{self, Read};
+use std::slice;
+
+const VARIABLES: [&str; 3] = ["x", "y", "z"];
+
+/// At most this many statements are derived, loops counting as one.
+const MAX_STATEMENTS: usize = 8;
+
+/// Every run gives up after this many steps, so a loop that `shrink`
+/// made infinite is just skipped.
+const STEP_LIMIT: usize = 100_000;
+
+/// The counter machine counts in unary, so it is only compared on runs
+/// that never hold a larger value.
+const COUNTER_MACHINE_BOUND: i64 = 1_000;
+
+/// Reads choices from the bytes, and zeros once they run out.
+struct Choices<'a>(slice::Iter<'a, u8>);
+
+impl Choices<'_> {
+    fn below(&mut self, n: usize) -> usize {
+        self.0.next().map_or(0, |&byte| usize::from(byte) % n)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.len() == 0
+    }
+}
+
+/// A program over `VARIABLES` and the environment to run it in. The
+/// expressions come from `synthesize`, so they are well typed and within
+/// the subset every backend compiles; loops count a fresh `i` up to a
+/// small bound, so every program terminates.
+fn derive(data: &[u8]) -> (Stmt, Environment) {
+    let mut choices = Choices(data.iter());
+    let mut env = Environment::new();
+    for name in VARIABLES {
+        env.set(name, Expr::Number(choices.below(4) as i64));
+    }
+    let numbers = synthesize(Type::Number, &env, 2);
+    let conditions = synthesize(Type::Boolean, &env, 2);
+    let assign = |choices: &mut Choices| {
+        Stmt::Assign(
+            VARIABLES[choices.below(VARIABLES.len())].into(),
+            numbers[choices.below(numbers.len())].clone(),
+        )
+    };
+    let mut stmts = vec![];
+    while !choices.is_empty() && stmts.len() < MAX_STATEMENTS {
+        let stmt = match choices.below(3) {
+            0 => assign(&mut choices),
+            1 => Stmt::If {
+                condition: conditions[choices.below(conditions.len())].clone(),
+                consequence: assign(&mut choices).into(),
+                alternative: assign(&mut choices).into(),
+            },
+            _ => {
+                // i = 0; while (i < n) { body; i = i + 1 }
+                let i = || Box::new(Expr::Variable("i".into()));
+                let n = choices.below(4) as i64;
+                let body = assign(&mut choices);
+                sequence(vec![
+                    Stmt::Assign("i".into(), Expr::Number(0)),
+                    Stmt::While {
+                        condition: Expr::LessThan(i(), Expr::Number(n).into()),
+                        body: sequence(vec![
+                            body,
+                            Stmt::Assign("i".into(), Expr::Add(i(), Expr::Number(1).into())),
+                        ])
+                        .into(),
+                    },
+                ])
+            }
+        };
+        stmts.push(stmt);
+    }
+    (sequence(stmts), env)
+}
+
+/// Converts a program into another backend's syntax, or `None` if it uses
+/// something the backend lacks.
+macro_rules! converter {
+    ($name:ident, $backend:ident) => {
+        fn $name(stmt: &Stmt) -> Option<$backend::Stmt> {
+            fn expr(value: &Expr) -> Option<$backend::Expr> {
+                use $backend::Expr as To;
+                let operands = |l: &Expr, r: &Expr| -> Option<(Box<To>, Box<To>)> {
+                    Some((expr(l)?.into(), expr(r)?.into()))
+                };
+                Some(match value {
+                    Expr::Number(n) => To::Number(*n),
+                    Expr::Boolean(b) => To::Boolean(*b),
+                    Expr::Variable(name) => To::Variable(name.clone()),
+                    Expr::Add(l, r) => {
+                        let (l, r) = operands(l, r)?;
+                        To::Add(l, r)
+                    }
+                    Expr::Multiply(l, r) => {
+                        let (l, r) = operands(l, r)?;
+                        To::Multiply(l, r)
+                    }
+                    Expr::LessThan(l, r) => {
+                        let (l, r) = operands(l, r)?;
+                        To::LessThan(l, r)
+                    }
+                    _ => return None,
+                })
+            }
+
+            use $backend::Stmt as To;
+            Some(match stmt {
+                Stmt::DoNothing => To::DoNothing,
+                Stmt::Assign(name, value) => To::Assign(name.clone(), expr(value)?),
+                Stmt::If {
+                    condition,
+                    consequence,
+                    alternative,
+                } => To::If {
+                    condition: expr(condition)?,
+                    consequence: $name(consequence)?.into(),
+                    alternative: $name(alternative)?.into(),
+                },
+                Stmt::Sequence { first, second } => To::Sequence {
+                    first: $name(first)?.into(),
+                    second: $name(second)?.into(),
+                },
+                Stmt::While { condition, body } => To::While {
+                    condition: expr(condition)?,
+                    body: $name(body)?.into(),
+                },
+            })
+        }
+    };
+}
+
+converter!(to_small_step, small_step);
+converter!(to_counter_machine, counter_machine);
+converter!(to_brainfuck, brainfuck);
+
+/// `Checked` arithmetic that also fails on a result above the bound.
+struct Bounded(i64);
+
+impl Bounded {
+    fn bound(&self, n: i64) -> Result<i64, EvalError> {
+        if n.abs() > self.0 {
+            return Err(EvalError::Overflow);
+        }
+        Ok(n)
+    }
+}
+
+impl NumericSemantics for Bounded {
+    fn add(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        self.bound(Checked.add(a, b)?)
+    }
+
+    fn subtract(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        self.bound(Checked.subtract(a, b)?)
+    }
+
+    fn multiply(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        self.bound(Checked.multiply(a, b)?)
+    }
+
+    fn divide(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        self.bound(Checked.divide(a, b)?)
+    }
+
+    fn remainder(&self, a: i64, b: i64) -> Result<i64, EvalError> {
+        self.bound(Checked.remainder(a, b)?)
+    }
+}
+
+/// Runs `stmt` under the big-step semantics, or `None` if it fails or
+/// runs out of steps.
+fn evalute(
+    stmt: &Stmt,
+    env: &Environment,
+    semantics: &impl NumericSemantics,
+) -> Option<Environment> {
+    let mut env = env.clone();
+    let mut fuel = STEP_LIMIT;
+    stmt.run(&mut env, &mut fuel, semantics).ok()?;
+    Some(env)
+}
+
+/// The environment as cells or registers, booleans as 1 / 0, or `None`
+/// if a value does not fit.
+fn cells<T: TryFrom<i64>>(env: &Environment) -> Option<HashMap<String, T>> {
+    env.iter()
+        .map(|(name, value)| {
+            let n = match value {
+                Expr::Number(n) => *n,
+                Expr::Boolean(b) => *b as i64,
+                _ => return None,
+            };
+            Some((name.clone(), T::try_from(n).ok()?))
+        })
+        .collect()
+}
+
+/// A backend's bindings in name order, for reporting them.
+fn sorted<T>(env: &HashMap<String, T>) -> BTreeMap<&String, &T> {
+    env.iter().collect()
+}
+
+/// How the first backend that disagrees with the big-step semantics on
+/// `stmt` differs from it. Each backend is compared against a big-step
+/// run in its own arithmetic: the small-step machine's checked `i64`s,
+/// the counter machine's natural numbers, and Brainfuck's cells, which
+/// wrap modulo 256. A backend is skipped when that run fails, when the
+/// program is outside its subset, or when it does not compile.
+fn disagreement(stmt: &Stmt, env: &Environment) -> Option<String> {
+    if let (Some(expected), Some(program)) = (evalute(stmt, env, &Checked), to_small_step(stmt)) {
+        let env = small_step::Environment::parse(&env.to_string()).ok()?;
+        let mut machine = small_step::MachineBuilder::new(program, env)
+            .step_limit(STEP_LIMIT)
+            .build();
+        let outcome = machine.run();
+        if outcome != small_step::Outcome::Halted || machine.env.to_string() != expected.to_string()
+        {
+            return Some(format!(
+                "small-step gives {:?} with {}, big-step {}",
+                outcome, machine.env, expected
+            ));
+        }
+    }
+
+    let bounded = Bounded(COUNTER_MACHINE_BOUND);
+    if let (Some(expected), Some(program)) =
+        (evalute(stmt, env, &bounded), to_counter_machine(stmt))
+    {
+        if let Ok(compiled) = counter_machine::Compiler::compile(&program) {
+            let actual = compiled.run(&cells(env)?);
+            if Some(&actual) != cells(&expected).as_ref() {
+                return Some(format!(
+                    "counter machine gives {:?}, big-step {}",
+                    sorted(&actual),
+                    expected
+                ));
+            }
+        }
+    }
+
+    let cells_256 = Modular::new(256).unwrap();
+    if let (Some(expected), Some(program)) = (evalute(stmt, env, &cells_256), to_brainfuck(stmt)) {
+        if let Ok(compiled) = brainfuck::Compiler::compile(&program) {
+            let actual = compiled.run(&cells(env)?);
+            if actual.as_ref().ok() != cells(&expected).as_ref() {
+                return Some(format!(
+                    "Brainfuck gives {:?}, big-step modulo 256 {}",
+                    actual.as_ref().map(sorted),
+                    expected
+                ));
+            }
+        }
+    }
+    None
+}
+
+/// The smallest program `shrink` finds on which `disagreement` still
+/// reports something, along with the report, or `None` if it reports
+/// nothing on `stmt`.
+fn minimize(
+    stmt: &Stmt,
+    env: &Environment,
+    disagreement: impl Fn(&Stmt, &Environment) -> Option<String>,
+) -> Option<(Stmt, String)> {
+    disagreement(stmt, env)?;
+    let smallest = shrink(stmt, |stmt| disagreement(stmt, env).is_some());
+    let report = disagreement(&smallest, env)?;
+    Some((smallest, report))
+}
+
+/// Derives a program from `data`, runs it on every backend, and panics
+/// with the smallest program `shrink` finds if any of them disagree.
+fn check(data: &[u8]) {
+    let (stmt, env) = derive(data);
+    if let Some((smallest, report)) = minimize(&stmt, &env, disagreement) {
+        panic!("backends disagree on `{}` in {}: {}", smallest, env, report);
+    }
+}
+
+fn main() {
+    let mut data = vec![];
+    if let Err(error) = io::stdin().read_to_end(&mut data) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+    let (stmt, env) = derive(&data);
+    println!("{}, {}", stmt, env);
+    check(&data);
+    println!("all backends agree");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random bytes, one stream per seed.
+    fn bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn derive_terminating_programs() {
+        assert_eq!("do-nothing", derive(&[]).0.to_string());
+        let (stmt, env) = derive(&[1, 2, 3, 2, 3, 0, 4]);
+        assert_eq!(
+            "i = 0; while (i < 3) { x = z; i = i + 1 }",
+            stmt.to_string()
+        );
+        assert_eq!("{x = 1, y = 2, z = 3}", env.to_string());
+        for seed in 0..100 {
+            let (stmt, env) = derive(&bytes(seed, 32));
+            assert!(evalute(&stmt, &env, &Checked).is_some(), "{}", stmt);
+        }
+    }
+
+    #[test]
+    fn backends_agree() {
+        for seed in 0..300 {
+            check(&bytes(seed, 32));
+        }
+    }
+
+    #[test]
+    fn disagreements_are_minimized() {
+        // A backend that computed modulo 3 would disagree wherever a
+        // value reaches 3.
+        let modulo_3 = |stmt: &Stmt, env: &Environment| {
+            let expected = evalute(stmt, env, &Checked)?;
+            let actual = evalute(stmt, env, &Modular::new(3).unwrap())?;
+            Some(actual.to_string()).filter(|actual| *actual != expected.to_string())
+        };
+        // x = 0; y = y + z; y = y + z; z = 1
+        let (stmt, env) = derive(&[1, 1, 1, 0, 0, 0, 0, 1, 43, 0, 1, 43, 0, 2, 1]);
+        assert_eq!("x = 0; y = y + z; y = y + z; z = 1", stmt.to_string());
+        let (smallest, report) = minimize(&stmt, &env, modulo_3).unwrap();
+        assert_eq!("y = y + z; y = y + z", smallest.to_string());
+        assert_eq!(Some(report), modulo_3(&smallest, &env));
+
+        let (stmt, env) = derive(&[0, 0, 0, 0, 0, 0]);
+        assert_eq!(None, minimize(&stmt, &env, modulo_3));
+    }
+}
@@ -1,83 +1,858 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fmt::{self};
+use std::io::{self, Write};
+use std::mem;
+use std::ops::{ControlFlow, Range};
+use std::process;
+use std::task::Poll;
+use std::time::{Duration, Instant, SystemTime};
 
-type Environment = HashMap<String, Expr>;
+#[path = "common/environment.rs"]
+mod environment;
 
-#[derive(PartialEq, Eq, Clone)]
-enum Expr {
+use environment::{UndefinedVariable, Value};
+
+pub(crate) type Environment = environment::Environment<Expr>;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+struct CloneCounts {
+    environments: usize,
+    bindings: usize,
+}
+
+thread_local! {
+    static CLONE_COUNTS: Cell<CloneCounts> = Cell::new(CloneCounts::default());
+}
+
+/// Every environment copy made while reducing goes through here, so the
+/// machine can report what the copy-per-step strategy costs.
+fn clone_env(env: &Environment) -> Environment {
+    CLONE_COUNTS.with(|counts| {
+        let mut current = counts.get();
+        current.environments += 1;
+        current.bindings += env.len();
+        counts.set(current);
+    });
+    env.clone()
+}
+
+#[derive(PartialEq, Eq, Hash, Clone)]
+#[allow(dead_code, clippy::enum_variant_names)]
+pub(crate) enum Expr {
     Number(i64),
     Add(Box<Expr>, Box<Expr>),
+    Subtract(Box<Expr>, Box<Expr>),
     Multiply(Box<Expr>, Box<Expr>),
+    Divide(Box<Expr>, Box<Expr>),
+    Modulo(Box<Expr>, Box<Expr>),
     Boolean(bool),
     LessThan(Box<Expr>, Box<Expr>),
+    GreaterThan(Box<Expr>, Box<Expr>),
+    LessThanOrEqual(Box<Expr>, Box<Expr>),
+    GreaterThanOrEqual(Box<Expr>, Box<Expr>),
+    Equal(Box<Expr>, Box<Expr>),
+    NotEqual(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
     Variable(String),
+    AssignExpr(String, Box<Expr>),
+    PreIncrement(String),
+    PostIncrement(String),
+    /// A number drawn uniformly from `0..max` by the machine's RNG.
+    Random(i64),
 }
 
+#[allow(dead_code)]
 impl Expr {
     fn is_reducible(&self) -> bool {
         match self {
             Self::Number(_) => false,
             Self::Add(_, _) => true,
+            Self::Subtract(_, _) => true,
             Self::Multiply(_, _) => true,
+            Self::Divide(_, _) => true,
+            Self::Modulo(_, _) => true,
             Self::Boolean(_) => false,
             Self::LessThan(_, _) => true,
+            Self::GreaterThan(_, _) => true,
+            Self::LessThanOrEqual(_, _) => true,
+            Self::GreaterThanOrEqual(_, _) => true,
+            Self::Equal(_, _) => true,
+            Self::NotEqual(_, _) => true,
+            Self::And(_, _) => true,
+            Self::Or(_, _) => true,
+            Self::Not(_) => true,
             Self::Variable(_) => true,
+            Self::AssignExpr(..) => true,
+            Self::PreIncrement(_) => true,
+            Self::PostIncrement(_) => true,
+            Self::Random(_) => true,
+        }
+    }
+
+    /// Whether `self` is a `&&` or `||` whose left operand alone decides
+    /// the result, so the right one is never reduced.
+    fn short_circuits(&self) -> bool {
+        match self {
+            Self::And(l, _) => **l == Self::Boolean(false),
+            Self::Or(l, _) => **l == Self::Boolean(true),
+            _ => false,
+        }
+    }
+
+    /// How tightly a binary operator binds, loosest first. Everything
+    /// else binds tightest.
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Or(..) => 1,
+            Self::And(..) => 2,
+            Self::Equal(..) | Self::NotEqual(..) => 3,
+            Self::LessThan(..)
+            | Self::GreaterThan(..)
+            | Self::LessThanOrEqual(..)
+            | Self::GreaterThanOrEqual(..) => 4,
+            Self::Add(..) | Self::Subtract(..) => 5,
+            Self::Multiply(..) | Self::Divide(..) | Self::Modulo(..) => 6,
+            _ => 7,
+        }
+    }
+
+    /// Whether the left and the right operand of a binary `self` are
+    /// printed in parentheses: those that bind looser, and those that bind
+    /// as tightly on the right, since operators group to the left. Equality
+    /// and comparisons do not chain, so they are bracketed on both sides.
+    fn parenthesized(&self) -> (bool, bool) {
+        let (l, r) = match self {
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r)
+            | Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r)
+            | Self::Equal(l, r)
+            | Self::NotEqual(l, r)
+            | Self::And(l, r)
+            | Self::Or(l, r) => (l, r),
+            _ => return (false, false),
+        };
+        let chains = !matches!(self.precedence(), 3 | 4);
+        (
+            l.precedence() < self.precedence() || !chains && l.precedence() == self.precedence(),
+            r.precedence() <= self.precedence(),
+        )
+    }
+
+    /// Whether `self` has two operands, which `!` puts in parentheses.
+    fn is_binary(&self) -> bool {
+        !matches!(
+            self,
+            Self::Number(_)
+                | Self::Boolean(_)
+                | Self::Variable(_)
+                | Self::AssignExpr(..)
+                | Self::PreIncrement(_)
+                | Self::PostIncrement(_)
+                | Self::Random(_)
+                | Self::Not(_)
+        )
+    }
+
+    /// Whether reducing `self` can neither write a variable nor draw a
+    /// random number.
+    fn is_pure(&self) -> bool {
+        self.is_deterministic() && !self.variables().iter().any(|name| self.assigns(name))
+    }
+
+    fn is_deterministic(&self) -> bool {
+        match self {
+            Self::Number(_)
+            | Self::Boolean(_)
+            | Self::Variable(_)
+            | Self::PreIncrement(_)
+            | Self::PostIncrement(_) => true,
+            Self::Random(_) => false,
+            Self::AssignExpr(_, expr) | Self::Not(expr) => expr.is_deterministic(),
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r)
+            | Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r)
+            | Self::Equal(l, r)
+            | Self::NotEqual(l, r)
+            | Self::And(l, r)
+            | Self::Or(l, r) => l.is_deterministic() && r.is_deterministic(),
+        }
+    }
+
+    /// `a op b` for this arithmetic operator, or `None` if the result is
+    /// outside the range of i64 or `b` is a zero divisor.
+    fn checked(&self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            Self::Add(..) => a.checked_add(b),
+            Self::Subtract(..) => a.checked_sub(b),
+            Self::Multiply(..) => a.checked_mul(b),
+            Self::Divide(..) => a.checked_div(b),
+            Self::Modulo(..) => a.checked_rem(b),
+            _ => panic!("`checked()` not supported"),
         }
     }
 
-    fn reduce(&self, env: &Environment) -> Self {
+    /// Reduces the leftmost reducible subexpression. Assignment and
+    /// increment expressions write to the environment, so the updated
+    /// environment is returned alongside the reduced expression; every
+    /// other rule hands back the borrowed one without copying it.
+    ///
+    /// Panics on `random`, which needs the RNG passed to `reduce_with`.
+    fn reduce<'e>(&self, env: &'e Environment) -> (Self, Cow<'e, Environment>) {
+        self.reduce_with(env, &mut |_| panic!("`random` needs an RNG"))
+    }
+
+    /// Like `reduce`, but `random(max)` reduces to whatever the `random`
+    /// callback returns for `max`.
+    fn reduce_with<'e>(
+        &self,
+        env: &'e Environment,
+        random: &mut dyn FnMut(i64) -> i64,
+    ) -> (Self, Cow<'e, Environment>) {
         match self {
             Self::Add(l, r) => {
                 if l.is_reducible() {
-                    Self::Add(Box::new(l.reduce(env)), r.clone())
+                    let (l, env) = l.reduce_with(env, random);
+                    (Self::Add(l.into(), r.clone()), env)
+                } else if r.is_reducible() {
+                    let (r, env) = r.reduce_with(env, random);
+                    (Self::Add(l.clone(), r.into()), env)
+                } else {
+                    match (l.as_ref(), r.as_ref()) {
+                        (Self::Number(a), Self::Number(b)) => {
+                            let n = self.checked(*a, *b).expect("arithmetic overflow");
+                            (Self::Number(n), Cow::Borrowed(env))
+                        }
+                        _ => panic!("invalid expr"),
+                    }
+                }
+            }
+            Self::Subtract(l, r) => {
+                if l.is_reducible() {
+                    let (l, env) = l.reduce_with(env, random);
+                    (Self::Subtract(l.into(), r.clone()), env)
                 } else if r.is_reducible() {
-                    Self::Add(l.clone(), Box::new(r.reduce(env)))
+                    let (r, env) = r.reduce_with(env, random);
+                    (Self::Subtract(l.clone(), r.into()), env)
                 } else {
                     match (l.as_ref(), r.as_ref()) {
-                        (Self::Number(a), Self::Number(b)) => Self::Number(a + b),
+                        (Self::Number(a), Self::Number(b)) => {
+                            let n = self.checked(*a, *b).expect("arithmetic overflow");
+                            (Self::Number(n), Cow::Borrowed(env))
+                        }
                         _ => panic!("invalid expr"),
                     }
                 }
             }
             Self::Multiply(l, r) => {
                 if l.is_reducible() {
-                    Self::Multiply(Box::new(l.reduce(env)), r.clone())
+                    let (l, env) = l.reduce_with(env, random);
+                    (Self::Multiply(l.into(), r.clone()), env)
+                } else if r.is_reducible() {
+                    let (r, env) = r.reduce_with(env, random);
+                    (Self::Multiply(l.clone(), r.into()), env)
+                } else {
+                    match (l.as_ref(), r.as_ref()) {
+                        (Self::Number(a), Self::Number(b)) => {
+                            let n = self.checked(*a, *b).expect("arithmetic overflow");
+                            (Self::Number(n), Cow::Borrowed(env))
+                        }
+                        _ => panic!("invalid expr"),
+                    }
+                }
+            }
+            Self::Divide(l, r) => {
+                if l.is_reducible() {
+                    let (l, env) = l.reduce_with(env, random);
+                    (Self::Divide(l.into(), r.clone()), env)
+                } else if r.is_reducible() {
+                    let (r, env) = r.reduce_with(env, random);
+                    (Self::Divide(l.clone(), r.into()), env)
+                } else {
+                    match (l.as_ref(), r.as_ref()) {
+                        (Self::Number(_), Self::Number(0)) => panic!("division by zero"),
+                        (Self::Number(a), Self::Number(b)) => (
+                            Self::Number(self.checked(*a, *b).expect("arithmetic overflow")),
+                            Cow::Borrowed(env),
+                        ),
+                        _ => panic!("invalid expr"),
+                    }
+                }
+            }
+            Self::Modulo(l, r) => {
+                if l.is_reducible() {
+                    let (l, env) = l.reduce_with(env, random);
+                    (Self::Modulo(l.into(), r.clone()), env)
                 } else if r.is_reducible() {
-                    Self::Multiply(l.clone(), Box::new(r.reduce(env)))
+                    let (r, env) = r.reduce_with(env, random);
+                    (Self::Modulo(l.clone(), r.into()), env)
                 } else {
                     match (l.as_ref(), r.as_ref()) {
-                        (Self::Number(a), Self::Number(b)) => Self::Number(a * b),
+                        (Self::Number(_), Self::Number(0)) => panic!("division by zero"),
+                        (Self::Number(a), Self::Number(b)) => (
+                            Self::Number(self.checked(*a, *b).expect("arithmetic overflow")),
+                            Cow::Borrowed(env),
+                        ),
                         _ => panic!("invalid expr"),
                     }
                 }
             }
             Self::LessThan(l, r) => {
                 if l.is_reducible() {
-                    Self::LessThan(Box::new(l.reduce(env)), r.clone())
+                    let (l, env) = l.reduce_with(env, random);
+                    (Self::LessThan(l.into(), r.clone()), env)
+                } else if r.is_reducible() {
+                    let (r, env) = r.reduce_with(env, random);
+                    (Self::LessThan(l.clone(), r.into()), env)
+                } else {
+                    match (l.as_ref(), r.as_ref()) {
+                        (Self::Number(a), Self::Number(b)) => {
+                            (Self::Boolean(a < b), Cow::Borrowed(env))
+                        }
+                        _ => panic!("invalid expr"),
+                    }
+                }
+            }
+            Self::GreaterThan(l, r) => {
+                if l.is_reducible() {
+                    let (l, env) = l.reduce_with(env, random);
+                    (Self::GreaterThan(l.into(), r.clone()), env)
+                } else if r.is_reducible() {
+                    let (r, env) = r.reduce_with(env, random);
+                    (Self::GreaterThan(l.clone(), r.into()), env)
+                } else {
+                    match (l.as_ref(), r.as_ref()) {
+                        (Self::Number(a), Self::Number(b)) => {
+                            (Self::Boolean(a > b), Cow::Borrowed(env))
+                        }
+                        _ => panic!("invalid expr"),
+                    }
+                }
+            }
+            Self::LessThanOrEqual(l, r) => {
+                if l.is_reducible() {
+                    let (l, env) = l.reduce_with(env, random);
+                    (Self::LessThanOrEqual(l.into(), r.clone()), env)
+                } else if r.is_reducible() {
+                    let (r, env) = r.reduce_with(env, random);
+                    (Self::LessThanOrEqual(l.clone(), r.into()), env)
+                } else {
+                    match (l.as_ref(), r.as_ref()) {
+                        (Self::Number(a), Self::Number(b)) => {
+                            (Self::Boolean(a <= b), Cow::Borrowed(env))
+                        }
+                        _ => panic!("invalid expr"),
+                    }
+                }
+            }
+            Self::GreaterThanOrEqual(l, r) => {
+                if l.is_reducible() {
+                    let (l, env) = l.reduce_with(env, random);
+                    (Self::GreaterThanOrEqual(l.into(), r.clone()), env)
                 } else if r.is_reducible() {
-                    Self::LessThan(l.clone(), Box::new(r.reduce(env)))
+                    let (r, env) = r.reduce_with(env, random);
+                    (Self::GreaterThanOrEqual(l.clone(), r.into()), env)
                 } else {
                     match (l.as_ref(), r.as_ref()) {
-                        (Self::Number(a), Self::Number(b)) => Self::Boolean(a < b),
+                        (Self::Number(a), Self::Number(b)) => {
+                            (Self::Boolean(a >= b), Cow::Borrowed(env))
+                        }
                         _ => panic!("invalid expr"),
                     }
                 }
             }
-            Self::Variable(name) => env[name].clone(),
+            Self::Equal(l, r) => {
+                if l.is_reducible() {
+                    let (l, env) = l.reduce_with(env, random);
+                    (Self::Equal(l.into(), r.clone()), env)
+                } else if r.is_reducible() {
+                    let (r, env) = r.reduce_with(env, random);
+                    (Self::Equal(l.clone(), r.into()), env)
+                } else {
+                    match (l.as_ref(), r.as_ref()) {
+                        (Self::Number(a), Self::Number(b)) => {
+                            (Self::Boolean(a == b), Cow::Borrowed(env))
+                        }
+                        (Self::Boolean(a), Self::Boolean(b)) => {
+                            (Self::Boolean(a == b), Cow::Borrowed(env))
+                        }
+                        _ => panic!("type mismatch"),
+                    }
+                }
+            }
+            Self::NotEqual(l, r) => {
+                if l.is_reducible() {
+                    let (l, env) = l.reduce_with(env, random);
+                    (Self::NotEqual(l.into(), r.clone()), env)
+                } else if r.is_reducible() {
+                    let (r, env) = r.reduce_with(env, random);
+                    (Self::NotEqual(l.clone(), r.into()), env)
+                } else {
+                    match (l.as_ref(), r.as_ref()) {
+                        (Self::Number(a), Self::Number(b)) => {
+                            (Self::Boolean(a != b), Cow::Borrowed(env))
+                        }
+                        (Self::Boolean(a), Self::Boolean(b)) => {
+                            (Self::Boolean(a != b), Cow::Borrowed(env))
+                        }
+                        _ => panic!("type mismatch"),
+                    }
+                }
+            }
+            // Once the left operand is a value the right one is either
+            // dropped unreduced, as in `false && e` and `true || e`, or
+            // reduced in place until it is a boolean.
+            Self::And(l, r) | Self::Or(l, r) => {
+                let rebuild = |l: Self, r: Self| match self {
+                    Self::And(..) => Self::And(l.into(), r.into()),
+                    _ => Self::Or(l.into(), r.into()),
+                };
+                if l.is_reducible() {
+                    let (l, env) = l.reduce_with(env, random);
+                    (rebuild(l, *r.clone()), env)
+                } else if !matches!(**l, Self::Boolean(_)) {
+                    panic!("type mismatch")
+                } else if self.short_circuits() {
+                    (*l.clone(), Cow::Borrowed(env))
+                } else if r.is_reducible() {
+                    let (r, env) = r.reduce_with(env, random);
+                    (rebuild(*l.clone(), r), env)
+                } else {
+                    match r.as_ref() {
+                        Self::Boolean(_) => (*r.clone(), Cow::Borrowed(env)),
+                        _ => panic!("type mismatch"),
+                    }
+                }
+            }
+            Self::Not(expr) => {
+                if expr.is_reducible() {
+                    let (expr, env) = expr.reduce_with(env, random);
+                    (Self::Not(expr.into()), env)
+                } else {
+                    match expr.as_ref() {
+                        Self::Boolean(b) => (Self::Boolean(!b), Cow::Borrowed(env)),
+                        _ => panic!("type mismatch"),
+                    }
+                }
+            }
+            Self::Variable(name) => (env[name].clone(), Cow::Borrowed(env)),
+            Self::AssignExpr(name, expr) => {
+                if expr.is_reducible() {
+                    let (expr, env) = expr.reduce_with(env, random);
+                    (Self::AssignExpr(name.clone(), expr.into()), env)
+                } else {
+                    let mut new_env = clone_env(env);
+                    new_env.set(name, *expr.clone());
+                    (*expr.clone(), Cow::Owned(new_env))
+                }
+            }
+            Self::PreIncrement(name) | Self::PostIncrement(name) => match &env[name] {
+                Self::Number(n) => {
+                    let mut new_env = clone_env(env);
+                    new_env.set(name, Self::Number(n + 1));
+                    let value = match self {
+                        Self::PreIncrement(_) => n + 1,
+                        _ => *n,
+                    };
+                    (Self::Number(value), Cow::Owned(new_env))
+                }
+                _ => panic!("invalid expr"),
+            },
+            Self::Random(max) if *max > 0 => (Self::Number(random(*max)), Cow::Borrowed(env)),
+            Self::Random(_) => panic!("invalid expr"),
             _ => panic!("`reduce()` not supported"),
         }
     }
+
+    /// The rule the next `reduce` contracts its redex with.
+    fn rule(&self) -> Rule {
+        match self {
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r)
+            | Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r)
+            | Self::Equal(l, r)
+            | Self::NotEqual(l, r)
+            | Self::And(l, r)
+            | Self::Or(l, r)
+                if l.is_reducible() =>
+            {
+                l.rule()
+            }
+            Self::Add(_, r)
+            | Self::Subtract(_, r)
+            | Self::Multiply(_, r)
+            | Self::Divide(_, r)
+            | Self::Modulo(_, r)
+            | Self::LessThan(_, r)
+            | Self::GreaterThan(_, r)
+            | Self::LessThanOrEqual(_, r)
+            | Self::GreaterThanOrEqual(_, r)
+            | Self::Equal(_, r)
+            | Self::NotEqual(_, r)
+            | Self::And(_, r)
+            | Self::Or(_, r)
+                if r.is_reducible() && !self.short_circuits() =>
+            {
+                r.rule()
+            }
+            Self::AssignExpr(_, expr) | Self::Not(expr) if expr.is_reducible() => expr.rule(),
+            Self::Add(..) => Rule::Add,
+            Self::Subtract(..) => Rule::Subtract,
+            Self::Multiply(..) => Rule::Multiply,
+            Self::Divide(..) => Rule::Divide,
+            Self::Modulo(..) => Rule::Modulo,
+            Self::LessThan(..) => Rule::LessThan,
+            Self::GreaterThan(..) => Rule::GreaterThan,
+            Self::LessThanOrEqual(..) => Rule::LessThanOrEqual,
+            Self::GreaterThanOrEqual(..) => Rule::GreaterThanOrEqual,
+            Self::Equal(..) => Rule::Equal,
+            Self::NotEqual(..) => Rule::NotEqual,
+            Self::And(..) => Rule::And,
+            Self::Or(..) => Rule::Or,
+            Self::Not(_) => Rule::Not,
+            Self::Variable(_) => Rule::Variable,
+            Self::AssignExpr(..) => Rule::AssignExpr,
+            Self::PreIncrement(_) | Self::PostIncrement(_) => Rule::Increment,
+            Self::Random(_) => Rule::Random,
+            Self::Number(_) | Self::Boolean(_) => panic!("`rule()` not supported"),
+        }
+    }
+
+    /// Appends the path from `self` down to the redex the next `reduce`
+    /// contracts, as `Term::child` indices.
+    fn redex_path(&self, path: &mut Vec<usize>) {
+        match self {
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r)
+            | Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r)
+            | Self::Equal(l, r)
+            | Self::NotEqual(l, r)
+            | Self::And(l, r)
+            | Self::Or(l, r) => {
+                if l.is_reducible() {
+                    path.push(0);
+                    l.redex_path(path);
+                } else if r.is_reducible() && !self.short_circuits() {
+                    path.push(1);
+                    r.redex_path(path);
+                }
+            }
+            Self::AssignExpr(_, expr) | Self::Not(expr) if expr.is_reducible() => {
+                path.push(0);
+                expr.redex_path(path);
+            }
+            _ => {}
+        }
+    }
+
+    /// The error the next `reduce` in `env` would run into instead of
+    /// taking a step; `reduce` panics on such an expression.
+    fn error(&self, env: &Environment) -> Option<RuntimeError> {
+        let mut path = vec![];
+        self.redex_path(&mut path);
+        let redex = match path
+            .into_iter()
+            .fold(Term::Expr(self), |term, index| term.child(index).1)
+        {
+            Term::Expr(expr) => expr,
+            Term::Stmt(_) => unreachable!("an expression has no statements"),
+        };
+        match redex {
+            expr @ Self::Add(l, r)
+            | expr @ Self::Subtract(l, r)
+            | expr @ Self::Multiply(l, r)
+            | expr @ Self::Divide(l, r)
+            | expr @ Self::Modulo(l, r)
+            | expr @ Self::LessThan(l, r)
+            | expr @ Self::GreaterThan(l, r)
+            | expr @ Self::LessThanOrEqual(l, r)
+            | expr @ Self::GreaterThanOrEqual(l, r)
+                if matches!(**l, Self::Boolean(_)) || matches!(**r, Self::Boolean(_)) =>
+            {
+                Some(RuntimeError::TypeMismatch(expr.clone()))
+            }
+            expr @ Self::Divide(_, r) | expr @ Self::Modulo(_, r) if **r == Self::Number(0) => {
+                Some(RuntimeError::DivisionByZero(expr.clone()))
+            }
+            // Like `i64::MAX + 1` and `i64::MIN / -1`, which have no i64
+            // result.
+            expr @ Self::Add(l, r)
+            | expr @ Self::Subtract(l, r)
+            | expr @ Self::Multiply(l, r)
+            | expr @ Self::Divide(l, r)
+            | expr @ Self::Modulo(l, r)
+                if matches!(
+                    (&**l, &**r),
+                    (Self::Number(a), Self::Number(b)) if expr.checked(*a, *b).is_none()
+                ) =>
+            {
+                Some(RuntimeError::Overflow(expr.clone()))
+            }
+            expr @ Self::Equal(l, r) | expr @ Self::NotEqual(l, r)
+                if mem::discriminant(&**l) != mem::discriminant(&**r) =>
+            {
+                Some(RuntimeError::TypeMismatch(expr.clone()))
+            }
+            expr @ Self::And(l, _) | expr @ Self::Or(l, _) | expr @ Self::Not(l)
+                if !matches!(**l, Self::Boolean(_)) =>
+            {
+                Some(RuntimeError::TypeMismatch(expr.clone()))
+            }
+            expr @ Self::And(_, r) | expr @ Self::Or(_, r)
+                if !expr.short_circuits() && !matches!(**r, Self::Boolean(_)) =>
+            {
+                Some(RuntimeError::TypeMismatch(expr.clone()))
+            }
+            Self::Variable(name) => env.get(name).err().map(RuntimeError::from),
+            expr @ Self::PreIncrement(name) | expr @ Self::PostIncrement(name) => {
+                match env.get(name) {
+                    Ok(Self::Number(i64::MAX)) => Some(RuntimeError::Overflow(expr.clone())),
+                    Ok(Self::Number(_)) => None,
+                    Ok(_) => Some(RuntimeError::TypeMismatch(expr.clone())),
+                    Err(error) => Some(error.into()),
+                }
+            }
+            expr @ Self::Random(max) if *max <= 0 => {
+                Some(RuntimeError::InvalidRandomBound(expr.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Why the next `reduce` does what it does, one clause per level from
+    /// here down to the redex, ending with the rule that fires.
+    fn explain(&self, clauses: &mut Vec<String>) {
+        match self {
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r)
+            | Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r)
+            | Self::Equal(l, r)
+            | Self::NotEqual(l, r)
+            | Self::And(l, r)
+            | Self::Or(l, r) => {
+                let operator = match self {
+                    Self::Add(..) => "+",
+                    Self::Subtract(..) => "-",
+                    Self::Multiply(..) => "*",
+                    Self::Divide(..) => "/",
+                    Self::Modulo(..) => "%",
+                    Self::GreaterThan(..) => ">",
+                    Self::LessThanOrEqual(..) => "<=",
+                    Self::GreaterThanOrEqual(..) => ">=",
+                    Self::Equal(..) => "==",
+                    Self::NotEqual(..) => "!=",
+                    Self::And(..) => "&&",
+                    Self::Or(..) => "||",
+                    _ => "<",
+                };
+                if l.is_reducible() {
+                    clauses.push(format!(
+                        "the left operand of {} is reducible, so we reduce it first",
+                        operator
+                    ));
+                    return l.explain(clauses);
+                }
+                if r.is_reducible() && !self.short_circuits() {
+                    clauses.push(format!(
+                        "the left operand of {} is a value, so we reduce the right one",
+                        operator
+                    ));
+                    return r.explain(clauses);
+                }
+            }
+            Self::AssignExpr(name, expr) if expr.is_reducible() => {
+                clauses.push(format!(
+                    "the value assigned to {} is reducible, so we reduce it first",
+                    name
+                ));
+                return expr.explain(clauses);
+            }
+            Self::Not(expr) if expr.is_reducible() => {
+                clauses.push("the operand of ! is reducible, so we reduce it first".into());
+                return expr.explain(clauses);
+            }
+            _ => {}
+        }
+        let because = match self {
+            Self::Add(..) => "both operands of + are numbers, so we add them".into(),
+            Self::Subtract(..) => "both operands of - are numbers, so we subtract them".into(),
+            Self::Multiply(..) => "both operands of * are numbers, so we multiply them".into(),
+            Self::Divide(..) => "both operands of / are numbers, so we divide them".into(),
+            Self::Modulo(..) => "both operands of % are numbers, so we take the remainder".into(),
+            Self::LessThan(..) => "both operands of < are numbers, so we compare them".into(),
+            Self::GreaterThan(..) => "both operands of > are numbers, so we compare them".into(),
+            Self::LessThanOrEqual(..) => {
+                "both operands of <= are numbers, so we compare them".into()
+            }
+            Self::GreaterThanOrEqual(..) => {
+                "both operands of >= are numbers, so we compare them".into()
+            }
+            Self::Equal(..) => {
+                "both operands of == are values of one type, so we compare them".into()
+            }
+            Self::NotEqual(..) => {
+                "both operands of != are values of one type, so we compare them".into()
+            }
+            Self::And(l, _) | Self::Or(l, _) => {
+                let (operator, decisive) = match self {
+                    Self::And(..) => ("&&", Self::Boolean(false)),
+                    _ => ("||", Self::Boolean(true)),
+                };
+                if **l == decisive {
+                    format!(
+                        "the left operand of {} is {}, so that is the result and the right one is skipped",
+                        operator, l
+                    )
+                } else {
+                    format!(
+                        "the left operand of {} is {} and the right one is a boolean, so that is the result",
+                        operator, l
+                    )
+                }
+            }
+            Self::Not(..) => "the operand of ! is a boolean, so we flip it".into(),
+            Self::Variable(name) => format!("we look up the value of {} in the environment", name),
+            Self::AssignExpr(name, _) => format!(
+                "the assigned value is a value, so we store it in {} and it is also the result",
+                name
+            ),
+            Self::PreIncrement(name) => format!("we add one to {} and use the new value", name),
+            Self::PostIncrement(name) => format!("we add one to {} and use the old value", name),
+            Self::Random(max) => format!("we draw a number below {}", max),
+            Self::Number(_) | Self::Boolean(_) => unreachable!(),
+        };
+        clauses.push(format!("{} ({})", because, self.rule().definition().name));
+    }
+
+    fn variables(&self) -> Vec<String> {
+        match self {
+            Self::Number(_) | Self::Boolean(_) | Self::Random(_) => vec![],
+            Self::Variable(name) | Self::PreIncrement(name) | Self::PostIncrement(name) => {
+                vec![name.clone()]
+            }
+            Self::Not(expr) => expr.variables(),
+            Self::AssignExpr(name, expr) => {
+                let mut variables = vec![name.clone()];
+                variables.extend(expr.variables());
+                variables
+            }
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r)
+            | Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r)
+            | Self::Equal(l, r)
+            | Self::NotEqual(l, r)
+            | Self::And(l, r)
+            | Self::Or(l, r) => {
+                let mut variables = l.variables();
+                variables.extend(r.variables());
+                variables
+            }
+        }
+    }
+
+    fn assigns(&self, name: &str) -> bool {
+        match self {
+            Self::Number(_) | Self::Boolean(_) | Self::Variable(_) | Self::Random(_) => false,
+            Self::AssignExpr(assigned, expr) => assigned == name || expr.assigns(name),
+            Self::Not(expr) => expr.assigns(name),
+            Self::PreIncrement(assigned) | Self::PostIncrement(assigned) => assigned == name,
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r)
+            | Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r)
+            | Self::Equal(l, r)
+            | Self::NotEqual(l, r)
+            | Self::And(l, r)
+            | Self::Or(l, r) => l.assigns(name) || r.assigns(name),
+        }
+    }
 }
 
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
         match self {
             Self::Number(n) => write!(f, "{}", n),
-            Self::Add(l, r) => write!(f, "{} + {}", l, r),
-            Self::Multiply(l, r) => write!(f, "{} * {}", l, r),
             Self::Boolean(b) => write!(f, "{}", b),
-            Self::LessThan(l, r) => write!(f, "{} < {}", l, r),
+            Self::Add(l, r)
+            | Self::Subtract(l, r)
+            | Self::Multiply(l, r)
+            | Self::Divide(l, r)
+            | Self::Modulo(l, r)
+            | Self::LessThan(l, r)
+            | Self::GreaterThan(l, r)
+            | Self::LessThanOrEqual(l, r)
+            | Self::GreaterThanOrEqual(l, r)
+            | Self::Equal(l, r)
+            | Self::NotEqual(l, r)
+            | Self::And(l, r)
+            | Self::Or(l, r) => {
+                let operand = |expr: &Expr, parenthesized| {
+                    if parenthesized {
+                        format!("({})", expr)
+                    } else {
+                        expr.to_string()
+                    }
+                };
+                let (left, right) = self.parenthesized();
+                write!(
+                    f,
+                    "{} {} {}",
+                    operand(l, left),
+                    Term::Expr(self).label(),
+                    operand(r, right)
+                )
+            }
+            Self::Not(expr) if expr.is_binary() => write!(f, "!({})", expr),
+            Self::Not(expr) => write!(f, "!{}", expr),
             Self::Variable(name) => write!(f, "{}", name),
+            Self::AssignExpr(name, expr) => write!(f, "({} = {})", name, expr),
+            Self::PreIncrement(name) => write!(f, "++{}", name),
+            Self::PostIncrement(name) => write!(f, "{}++", name),
+            Self::Random(max) => write!(f, "random({})", max),
         }
     }
 }
@@ -88,8 +863,18 @@ impl fmt::Debug for Expr {
     }
 }
 
-#[derive(PartialEq, Eq, Clone)]
-enum Stmt {
+impl Value for Expr {
+    fn parse_literal(text: &str) -> Option<Self> {
+        match text {
+            "true" => Some(Self::Boolean(true)),
+            "false" => Some(Self::Boolean(false)),
+            _ => text.parse().ok().map(Self::Number),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone)]
+pub(crate) enum Stmt {
     DoNothing,
     Assign(String, Expr),
     If {
@@ -107,6 +892,7 @@ enum Stmt {
     },
 }
 
+#[allow(dead_code)]
 impl Stmt {
     fn is_reducible(&self) -> bool {
         match self {
@@ -118,15 +904,40 @@ impl Stmt {
         }
     }
 
-    fn reduce(&self, env: &Environment) -> (Stmt, Environment) {
+    fn reduce<'e>(&self, env: &'e Environment) -> (Stmt, Cow<'e, Environment>) {
+        self.reduce_with(env, &mut |_| panic!("`random` needs an RNG"))
+    }
+
+    /// Every configuration one step away: one for each number a `random`
+    /// redex can draw, otherwise the single step `reduce` takes.
+    fn successors(&self, env: &Environment) -> Vec<(Stmt, Environment)> {
+        let draws = match self.redex() {
+            Term::Expr(Expr::Random(max)) => (0..*max).collect(),
+            _ => vec![0],
+        };
+        draws
+            .into_iter()
+            .map(|n| {
+                let (stmt, env) = self.reduce_with(env, &mut |_| n);
+                (stmt, env.into_owned())
+            })
+            .collect()
+    }
+
+    fn reduce_with<'e>(
+        &self,
+        env: &'e Environment,
+        random: &mut dyn FnMut(i64) -> i64,
+    ) -> (Stmt, Cow<'e, Environment>) {
         match self {
             Self::Assign(name, expr) => {
                 if expr.is_reducible() {
-                    (Self::Assign(name.into(), expr.reduce(env)), env.clone())
+                    let (expr, env) = expr.reduce_with(env, random);
+                    (Self::Assign(name.into(), expr), env)
                 } else {
-                    let mut new_env = env.clone();
-                    new_env.insert(name.into(), expr.clone());
-                    (Self::DoNothing, new_env)
+                    let mut new_env = clone_env(env);
+                    new_env.set(name, expr.clone());
+                    (Self::DoNothing, Cow::Owned(new_env))
                 }
             }
             Self::If {
@@ -135,26 +946,27 @@ impl Stmt {
                 alternative,
             } => {
                 if condition.is_reducible() {
+                    let (condition, env) = condition.reduce_with(env, random);
                     (
                         Self::If {
-                            condition: condition.reduce(env),
+                            condition,
                             consequence: consequence.clone(),
                             alternative: alternative.clone(),
                         },
-                        env.clone(),
+                        env,
                     )
                 } else {
                     match condition {
-                        Expr::Boolean(true) => (*consequence.clone(), env.clone()),
-                        Expr::Boolean(false) => (*alternative.clone(), env.clone()),
+                        Expr::Boolean(true) => (*consequence.clone(), Cow::Borrowed(env)),
+                        Expr::Boolean(false) => (*alternative.clone(), Cow::Borrowed(env)),
                         _ => panic!("invalid condition"),
                     }
                 }
             }
             Self::Sequence { first, second } => match first.as_ref() {
-                Self::DoNothing => (*second.clone(), env.clone()),
+                Self::DoNothing => (*second.clone(), Cow::Borrowed(env)),
                 _ => {
-                    let (reduced_first, reduced_env) = first.reduce(env);
+                    let (reduced_first, reduced_env) = first.reduce_with(env, random);
                     (
                         Self::Sequence {
                             first: reduced_first.into(),
@@ -174,75 +986,3607 @@ impl Stmt {
                     .into(),
                     alternative: Self::DoNothing.into(),
                 },
-                env.clone(),
+                Cow::Borrowed(env),
             ),
             _ => panic!("`reduce()` not supported"),
         }
     }
-}
 
-impl fmt::Display for Stmt {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+    fn assigns(&self, name: &str) -> bool {
         match self {
-            Self::DoNothing => write!(f, "do-nothing"),
-            Self::Assign(name, val) => write!(f, "{} = {}", name, val),
+            Self::DoNothing => false,
+            Self::Assign(assigned, expr) => assigned == name || expr.assigns(name),
             Self::If {
                 condition,
                 consequence,
                 alternative,
-            } => write!(
-                f,
-                "if ({}) {{ {} }} else {{ {} }}",
-                condition, consequence, alternative
-            ),
-            Self::Sequence { first, second } => write!(f, "{}; {}", first, second),
-            Self::While { condition, body } => write!(f, "while ({}) {{ {} }}", condition, body),
+            } => condition.assigns(name) || consequence.assigns(name) || alternative.assigns(name),
+            Self::Sequence { first, second } => first.assigns(name) || second.assigns(name),
+            Self::While { condition, body } => condition.assigns(name) || body.assigns(name),
         }
     }
-}
 
-impl fmt::Debug for Stmt {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        write!(f, "<<{}>>", self)
+    /// The rule the next `reduce` contracts its redex with.
+    fn rule(&self) -> Rule {
+        match self {
+            Self::Assign(_, expr) if expr.is_reducible() => expr.rule(),
+            Self::Assign(..) => Rule::Assign,
+            Self::If { condition, .. } if condition.is_reducible() => condition.rule(),
+            Self::If { condition, .. } => match condition {
+                Expr::Boolean(true) => Rule::IfTrue,
+                Expr::Boolean(false) => Rule::IfFalse,
+                _ => panic!("invalid condition"),
+            },
+            Self::Sequence { first, .. } if first.as_ref() == &Self::DoNothing => Rule::Sequence,
+            Self::Sequence { first, .. } => first.rule(),
+            Self::While { .. } => Rule::While,
+            Self::DoNothing => panic!("`rule()` not supported"),
+        }
     }
-}
 
-struct Machine {
-    stmt: Stmt,
-    env: Environment,
-}
+    /// The path to the sub-term the next `reduce` contracts, so a front end
+    /// can highlight it. Empty when the whole statement is the redex.
+    fn redex_path(&self) -> Vec<usize> {
+        let mut path = vec![];
+        match self {
+            Self::Assign(_, expr)
+            | Self::If {
+                condition: expr, ..
+            } if expr.is_reducible() => {
+                path.push(0);
+                expr.redex_path(&mut path);
+            }
+            Self::Sequence { first, .. } if first.as_ref() != &Self::DoNothing => {
+                path.push(0);
+                path.extend(first.redex_path());
+            }
+            _ => {}
+        }
+        path
+    }
 
-impl Machine {
-    fn new(stmt: Stmt, env: Environment) -> Self {
-        Self { stmt, env }
+    /// The sub-term the next `reduce` contracts.
+    fn redex(&self) -> Term<'_> {
+        self.redex_path()
+            .into_iter()
+            .fold(Term::Stmt(self), |term, index| term.child(index).1)
     }
 
-    fn step(&mut self) {
-        let (new_stmt, new_env) = self.stmt.reduce(&self.env);
-        self.stmt = new_stmt;
-        self.env = new_env;
+    /// The error the next `reduce` in `env` would run into instead of
+    /// taking a step; `reduce` panics on such a statement.
+    fn error(&self, env: &Environment) -> Option<RuntimeError> {
+        match self {
+            Self::Assign(_, expr)
+            | Self::If {
+                condition: expr, ..
+            } if expr.is_reducible() => expr.error(env),
+            Self::Sequence { first, .. } if first.as_ref() != &Self::DoNothing => first.error(env),
+            Self::If { condition, .. } if !matches!(condition, Expr::Boolean(_)) => {
+                Some(RuntimeError::TypeMismatch(condition.clone()))
+            }
+            _ => None,
+        }
     }
 
-    fn run(&mut self) {
-        while self.stmt.is_reducible() {
-            println!("{}, {:?}", self.stmt, self.env);
-            self.step();
+    /// Where the redex sits in `self.to_string()`.
+    fn redex_span(&self) -> Range<usize> {
+        let mut term = Term::Stmt(self);
+        let mut start = 0;
+        for &index in &self.redex_path() {
+            let (offset, child) = term.child(index);
+            start += offset;
+            term = child;
         }
-        println!("{}, {:?}", self.stmt, self.env);
+        start..start + term.to_string().len()
     }
-}
 
-fn main() {
-    let stmt = Stmt::While {
-        condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(5).into()).into(),
-        body: Stmt::Assign(
-            "x".into(),
-            Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(3).into()).into(),
-        )
-        .into(),
-    };
-    let mut env = HashMap::new();
-    env.insert("x".into(), Expr::Number(1));
-    let mut machine = Machine::new(stmt, env);
-    machine.run();
+    /// Explains the next step in English, e.g. "the right-hand side of
+    /// x = ... is reducible, so we reduce it first; both operands of + are
+    /// numbers, so we add them (Add)". Sequences are passed through
+    /// silently until their first statement has finished.
+    fn explain(&self) -> String {
+        let mut clauses = vec![];
+        let mut stmt = self;
+        while let Self::Sequence { first, .. } = stmt {
+            if **first == Self::DoNothing {
+                break;
+            }
+            stmt = first;
+        }
+        match stmt {
+            Self::Assign(name, expr) if expr.is_reducible() => {
+                clauses.push(format!(
+                    "the right-hand side of {} = ... is reducible, so we reduce it first",
+                    name
+                ));
+                expr.explain(&mut clauses);
+            }
+            Self::If { condition, .. } if condition.is_reducible() => {
+                clauses.push("the condition is reducible, so we reduce it first".into());
+                condition.explain(&mut clauses);
+            }
+            _ => {
+                let because = match stmt {
+                    Self::Assign(name, _) => format!(
+                        "the right-hand side is a value, so we store it in {} and the \
+                         assignment is done",
+                        name
+                    ),
+                    Self::If { condition, .. } if *condition == Expr::Boolean(true) => {
+                        "the condition is true, so we continue with the consequence".into()
+                    }
+                    Self::If { .. } => {
+                        "the condition is false, so we continue with the alternative".into()
+                    }
+                    Self::Sequence { .. } => {
+                        "the first statement is finished, so we move on to the second".into()
+                    }
+                    Self::While { .. } => "we unfold the loop into an if that runs the body \
+                                           once and then tries the loop again"
+                        .into(),
+                    Self::DoNothing => return "the program has finished".into(),
+                };
+                clauses.push(format!("{} ({})", because, stmt.rule().definition().name));
+            }
+        }
+        clauses.join("; ")
+    }
+
+    /// The statement the next reduction step works on: the leftmost
+    /// statement of a chain of sequences.
+    fn current(&self) -> &Stmt {
+        match self {
+            Self::Sequence { first, .. } if first.as_ref() != &Self::DoNothing => first.current(),
+            _ => self,
+        }
+    }
+
+    /// Every loop in the program with a linear ranking function proving it
+    /// terminates, or `None` where none was found.
+    fn termination_proofs(&self) -> Vec<(Stmt, Option<Linear>)> {
+        match self {
+            Self::DoNothing | Self::Assign(..) => vec![],
+            Self::If {
+                consequence,
+                alternative,
+                ..
+            } => {
+                let mut proofs = consequence.termination_proofs();
+                proofs.extend(alternative.termination_proofs());
+                proofs
+            }
+            Self::Sequence { first, second } => {
+                let mut proofs = first.termination_proofs();
+                proofs.extend(second.termination_proofs());
+                proofs
+            }
+            Self::While { condition, body } => {
+                let mut proofs = vec![(self.clone(), ranking_function(condition, body))];
+                proofs.extend(body.termination_proofs());
+                proofs
+            }
+        }
+    }
+
+    /// The updates each path through a loop-free statement makes, as
+    /// linear functions of the variables before it. `If` contributes both
+    /// branches as separate paths. `None` if anything is not linear or a
+    /// coefficient overflows.
+    fn linear_effects(&self) -> Option<Vec<HashMap<String, Linear>>> {
+        match self {
+            Self::DoNothing => Some(vec![HashMap::new()]),
+            Self::Assign(name, expr) => {
+                let mut effect = HashMap::new();
+                effect.insert(name.clone(), Linear::from_expr(expr)?);
+                Some(vec![effect])
+            }
+            Self::If {
+                condition,
+                consequence,
+                alternative,
+            } if condition.is_pure() => {
+                let mut effects = consequence.linear_effects()?;
+                effects.extend(alternative.linear_effects()?);
+                Some(effects)
+            }
+            Self::Sequence { first, second } => {
+                let firsts = first.linear_effects()?;
+                let seconds = second.linear_effects()?;
+                let mut effects = vec![];
+                for first in &firsts {
+                    for second in &seconds {
+                        let mut effect = first.clone();
+                        for (name, value) in second {
+                            effect.insert(name.clone(), value.substitute(first)?);
+                        }
+                        effects.push(effect);
+                    }
+                }
+                Some(effects)
+            }
+            Self::If { .. } | Self::While { .. } => None,
+        }
+    }
+
+    /// Flags loops that obviously never terminate once entered: the condition
+    /// is constantly true, or none of its variables is assigned in the body.
+    fn termination_warnings(&self) -> Vec<TerminationWarning> {
+        match self {
+            Self::DoNothing | Self::Assign(..) => vec![],
+            Self::If {
+                consequence,
+                alternative,
+                ..
+            } => {
+                let mut warnings = consequence.termination_warnings();
+                warnings.extend(alternative.termination_warnings());
+                warnings
+            }
+            Self::Sequence { first, second } => {
+                let mut warnings = first.termination_warnings();
+                warnings.extend(second.termination_warnings());
+                warnings
+            }
+            Self::While { condition, body } => {
+                let variables = condition.variables();
+                let mut warnings = vec![];
+                if variables.is_empty() && condition.is_deterministic() {
+                    // A condition that fails, such as `1 / 0 < 1`, is left
+                    // for `run` to report.
+                    let env = Environment::new();
+                    let mut value = condition.clone();
+                    while value.is_reducible() && value.error(&env).is_none() {
+                        value = value.reduce(&env).0;
+                    }
+                    if value == Expr::Boolean(true) {
+                        warnings.push(TerminationWarning::AlwaysTrue(self.clone()));
+                    }
+                } else if !variables.iter().any(|name| self.assigns(name)) {
+                    warnings.push(TerminationWarning::ConditionNeverModified(self.clone()));
+                }
+                warnings.extend(body.termination_warnings());
+                warnings
+            }
+        }
+    }
+}
+
+/// `constant + sum(coefficient * variable)` over integers.
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+struct Linear {
+    constant: i64,
+    coefficients: BTreeMap<String, i64>,
+}
+
+#[allow(dead_code)]
+impl Linear {
+    fn constant(constant: i64) -> Self {
+        Self {
+            constant,
+            ..Self::default()
+        }
+    }
+
+    fn from_expr(expr: &Expr) -> Option<Self> {
+        match expr {
+            Expr::Number(n) => Some(Self::constant(*n)),
+            Expr::Variable(name) => {
+                let mut coefficients = BTreeMap::new();
+                coefficients.insert(name.clone(), 1);
+                Some(Self {
+                    constant: 0,
+                    coefficients,
+                })
+            }
+            Expr::Add(l, r) => Self::from_expr(l)?.plus(&Self::from_expr(r)?),
+            Expr::Subtract(l, r) => Self::from_expr(l)?.plus(&Self::from_expr(r)?.scale(-1)?),
+            Expr::Multiply(l, r) => {
+                let (l, r) = (Self::from_expr(l)?, Self::from_expr(r)?);
+                if l.coefficients.is_empty() {
+                    r.scale(l.constant)
+                } else if r.coefficients.is_empty() {
+                    l.scale(r.constant)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The sum, or `None` if a coefficient overflows.
+    fn plus(&self, other: &Self) -> Option<Self> {
+        let mut sum = self.clone();
+        sum.constant = sum.constant.checked_add(other.constant)?;
+        for (name, coefficient) in &other.coefficients {
+            let entry = sum.coefficients.entry(name.clone()).or_insert(0);
+            *entry = entry.checked_add(*coefficient)?;
+        }
+        sum.coefficients.retain(|_, coefficient| *coefficient != 0);
+        Some(sum)
+    }
+
+    /// The product with `factor`, or `None` if a coefficient overflows.
+    fn scale(&self, factor: i64) -> Option<Self> {
+        let mut scaled = Self::constant(self.constant.checked_mul(factor)?);
+        if factor != 0 {
+            for (name, coefficient) in &self.coefficients {
+                scaled
+                    .coefficients
+                    .insert(name.clone(), coefficient.checked_mul(factor)?);
+            }
+        }
+        Some(scaled)
+    }
+
+    /// Replaces each variable bound in `values` by its value, or returns
+    /// `None` if a coefficient overflows.
+    fn substitute(&self, values: &HashMap<String, Linear>) -> Option<Self> {
+        let mut result = Self::constant(self.constant);
+        for (name, coefficient) in &self.coefficients {
+            let value = values
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| Self::from_expr(&Expr::Variable(name.clone())).unwrap());
+            result = result.plus(&value.scale(*coefficient)?)?;
+        }
+        Some(result)
+    }
+}
+
+impl fmt::Display for Linear {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        let mut terms: Vec<(i64, String)> = self
+            .coefficients
+            .iter()
+            .map(|(name, coefficient)| (*coefficient, name.clone()))
+            .collect();
+        // The constant goes first, then positive terms, so that `n - x` is
+        // not written `-x + n`.
+        terms.sort_by_key(|(coefficient, _)| *coefficient < 0);
+        if self.constant != 0 || terms.is_empty() {
+            terms.insert(0, (self.constant, String::new()));
+        }
+        for (i, (coefficient, name)) in terms.iter().enumerate() {
+            let magnitude = coefficient.unsigned_abs();
+            match (i, *coefficient < 0) {
+                (0, true) => write!(f, "-")?,
+                (0, false) => {}
+                (_, true) => write!(f, " - ")?,
+                (_, false) => write!(f, " + ")?,
+            }
+            match (magnitude, name.is_empty()) {
+                (_, true) => write!(f, "{}", magnitude)?,
+                (1, false) => write!(f, "{}", name)?,
+                (_, false) => write!(f, "{}{}", magnitude, name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tries the ranking function `b - a` for a loop `while (a < b)` over
+/// linear terms (`a > b`, `a <= b` and `a >= b` are rewritten into that
+/// form). It is positive whenever the loop runs, so if every path
+/// through the body lowers it by at least one, the loop terminates.
+#[allow(dead_code)]
+fn ranking_function(condition: &Expr, body: &Stmt) -> Option<Linear> {
+    if !condition.is_pure() {
+        return None;
+    }
+    let (a, b) = match condition {
+        Expr::LessThan(a, b) => (Linear::from_expr(a)?, Linear::from_expr(b)?),
+        Expr::GreaterThan(a, b) => (Linear::from_expr(b)?, Linear::from_expr(a)?),
+        Expr::LessThanOrEqual(a, b) => (
+            Linear::from_expr(a)?,
+            Linear::from_expr(b)?.plus(&Linear::constant(1))?,
+        ),
+        Expr::GreaterThanOrEqual(a, b) => (
+            Linear::from_expr(b)?,
+            Linear::from_expr(a)?.plus(&Linear::constant(1))?,
+        ),
+        _ => return None,
+    };
+    let rank = b.plus(&a.scale(-1)?)?;
+    let decreases = body.linear_effects()?.iter().all(|effect| {
+        let decrease = rank
+            .substitute(effect)
+            .and_then(|next| rank.plus(&next.scale(-1)?));
+        decrease.is_some_and(|decrease| decrease.coefficients.is_empty() && decrease.constant >= 1)
+    });
+    if decreases {
+        Some(rank)
+    } else {
+        None
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+enum TerminationWarning {
+    AlwaysTrue(Stmt),
+    ConditionNeverModified(Stmt),
+}
+
+impl fmt::Display for TerminationWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            Self::AlwaysTrue(stmt) => {
+                write!(f, "`{}` never terminates: condition is always true", stmt)
+            }
+            Self::ConditionNeverModified(stmt) => write!(
+                f,
+                "`{}` never terminates once entered: condition variables are never modified",
+                stmt
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            Self::DoNothing => write!(f, "do-nothing"),
+            Self::Assign(name, val) => write!(f, "{} = {}", name, val),
+            Self::If {
+                condition,
+                consequence,
+                alternative,
+            } => write!(
+                f,
+                "if ({}) {{ {} }} else {{ {} }}",
+                condition, consequence, alternative
+            ),
+            Self::Sequence { first, second } => write!(f, "{}; {}", first, second),
+            Self::While { condition, body } => write!(f, "while ({}) {{ {} }}", condition, body),
+        }
+    }
+}
+
+impl fmt::Debug for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "<<{}>>", self)
+    }
+}
+
+#[allow(dead_code)]
+impl Stmt {
+    /// Parses a statement as `Display` writes it. A chain of sequences is
+    /// nested to the right, which runs the same as any other nesting.
+    fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = Parser { source, pos: 0 };
+        let stmt = parser.stmt()?;
+        parser.end()?;
+        Ok(stmt)
+    }
+}
+
+#[allow(dead_code)]
+impl Expr {
+    /// Parses an expression as `Display` writes it.
+    fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = Parser { source, pos: 0 };
+        let expr = parser.or()?;
+        parser.end()?;
+        Ok(expr)
+    }
+}
+
+struct Parser<'s> {
+    source: &'s str,
+    pos: usize,
+}
+
+/// Builds a binary expression from its operands.
+type Operator = fn(Box<Expr>, Box<Expr>) -> Expr;
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn end(&mut self) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Ok(()),
+            Some(c) => Err(format!("unexpected `{}` at {}", c, self.pos)),
+        }
+    }
+
+    /// Eats `token` after skipping whitespace, or eats nothing.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.source[self.pos..].starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(format!("expected `{}` at {}", token, self.pos))
+        }
+    }
+
+    /// The name or number starting here, which may be empty.
+    fn word(&mut self) -> &str {
+        self.skip_whitespace();
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            self.pos += 1;
+        }
+        &self.source[start..self.pos]
+    }
+
+    /// Eats `keyword` if it is the whole word starting here.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let start = self.pos;
+        if self.word() == keyword {
+            return true;
+        }
+        self.pos = start;
+        false
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        let word = self.word();
+        if word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            Ok(word.into())
+        } else {
+            Err(format!("expected a variable at {}", start))
+        }
+    }
+
+    fn number(&mut self) -> Result<i64, String> {
+        let start = self.pos;
+        let sign = if self.eat("-") { "-" } else { "" };
+        let digits = self.word();
+        format!("{}{}", sign, digits)
+            .parse()
+            .map_err(|_| format!("expected a number at {}", start))
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, String> {
+        let first = self.simple_stmt()?;
+        if !self.eat(";") {
+            return Ok(first);
+        }
+        Ok(Stmt::Sequence {
+            first: first.into(),
+            second: self.stmt()?.into(),
+        })
+    }
+
+    fn block(&mut self) -> Result<Stmt, String> {
+        self.expect("{")?;
+        let stmt = self.stmt()?;
+        self.expect("}")?;
+        Ok(stmt)
+    }
+
+    fn condition(&mut self) -> Result<Expr, String> {
+        self.expect("(")?;
+        let condition = self.or()?;
+        self.expect(")")?;
+        Ok(condition)
+    }
+
+    fn simple_stmt(&mut self) -> Result<Stmt, String> {
+        if self.keyword("do") {
+            self.expect("-nothing")?;
+            return Ok(Stmt::DoNothing);
+        }
+        if self.keyword("if") {
+            let condition = self.condition()?;
+            let consequence = self.block()?;
+            if !self.keyword("else") {
+                return Err(format!("expected `else` at {}", self.pos));
+            }
+            return Ok(Stmt::If {
+                condition,
+                consequence: consequence.into(),
+                alternative: self.block()?.into(),
+            });
+        }
+        if self.keyword("while") {
+            let condition = self.condition()?;
+            return Ok(Stmt::While {
+                condition,
+                body: self.block()?.into(),
+            });
+        }
+        let name = self.name()?;
+        self.expect("=")?;
+        Ok(Stmt::Assign(name, self.or()?))
+    }
+
+    /// Parses operands with `operand` for as long as one of `operators`
+    /// follows, grouping to the left.
+    fn binary(
+        &mut self,
+        operators: &[(&str, Operator)],
+        operand: fn(&mut Self) -> Result<Expr, String>,
+    ) -> Result<Expr, String> {
+        let mut expr = operand(self)?;
+        while let Some((_, build)) = operators.iter().find(|(operator, _)| self.eat(operator)) {
+            expr = build(expr.into(), operand(self)?.into());
+        }
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        self.binary(&[("||", Expr::Or)], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        self.binary(&[("&&", Expr::And)], Self::equality)
+    }
+
+    fn equality(&mut self) -> Result<Expr, String> {
+        let l = self.comparison()?;
+        let equality: Operator = if self.eat("==") {
+            Expr::Equal
+        } else if self.eat("!=") {
+            Expr::NotEqual
+        } else {
+            return Ok(l);
+        };
+        Ok(equality(l.into(), self.comparison()?.into()))
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let l = self.sum()?;
+        // `<=` and `>=` before `<` and `>`, which start them.
+        let comparison: Operator = if self.eat("<=") {
+            Expr::LessThanOrEqual
+        } else if self.eat(">=") {
+            Expr::GreaterThanOrEqual
+        } else if self.eat("<") {
+            Expr::LessThan
+        } else if self.eat(">") {
+            Expr::GreaterThan
+        } else {
+            return Ok(l);
+        };
+        Ok(comparison(l.into(), self.sum()?.into()))
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        self.binary(&[("+", Expr::Add), ("-", Expr::Subtract)], Self::product)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        self.binary(
+            &[
+                ("*", Expr::Multiply),
+                ("/", Expr::Divide),
+                ("%", Expr::Modulo),
+            ],
+            Self::atom,
+        )
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        if self.eat("!") {
+            return Ok(Expr::Not(self.atom()?.into()));
+        }
+        if self.eat("++") {
+            return Ok(Expr::PreIncrement(self.name()?));
+        }
+        if self.eat("(") {
+            // `(x = e)`, or a parenthesized expression
+            let start = self.pos;
+            let assigned = self.name().ok().filter(|_| {
+                let before = self.pos;
+                let assign = self.eat("=") && !self.eat("=");
+                self.pos = before;
+                assign
+            });
+            let expr = match assigned {
+                Some(name) => {
+                    self.expect("=")?;
+                    Expr::AssignExpr(name, self.or()?.into())
+                }
+                None => {
+                    self.pos = start;
+                    self.or()?
+                }
+            };
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        self.skip_whitespace();
+        if self.peek().is_some_and(|c| c == '-' || c.is_ascii_digit()) {
+            return self.number().map(Expr::Number);
+        }
+        let start = self.pos;
+        match self.word() {
+            "" => Err(match self.peek() {
+                Some(c) => format!("unexpected `{}` at {}", c, start),
+                None => "unexpected end of input".into(),
+            }),
+            "true" => Ok(Expr::Boolean(true)),
+            "false" => Ok(Expr::Boolean(false)),
+            "random" => {
+                self.expect("(")?;
+                let max = self.number()?;
+                self.expect(")")?;
+                Ok(Expr::Random(max))
+            }
+            word => {
+                let name = word.to_string();
+                // `x++` is written without a space, unlike `x + ++y`
+                if self.source[self.pos..].starts_with("++") {
+                    self.pos += 2;
+                    return Ok(Expr::PostIncrement(name));
+                }
+                Ok(Expr::Variable(name))
+            }
+        }
+    }
+}
+
+/// A statement or an expression inside one, for walking redex paths that
+/// cross from statements into their expressions.
+#[derive(Clone, Copy)]
+enum Term<'a> {
+    Stmt(&'a Stmt),
+    Expr(&'a Expr),
+}
+
+impl<'a> Term<'a> {
+    /// The `index`th sub-term, with how far into this term's rendering it
+    /// starts. Children are numbered left to right as they are printed.
+    fn child(self, index: usize) -> (usize, Term<'a>) {
+        let len = |term: Term| term.to_string().len();
+        let parenthesized = || match self {
+            Self::Expr(expr) => expr.parenthesized(),
+            Self::Stmt(_) => (false, false),
+        };
+        match (self, index) {
+            (Self::Stmt(Stmt::Assign(name, expr)), 0) => (name.len() + 3, Term::Expr(expr)),
+            (Self::Stmt(Stmt::If { condition, .. }), 0)
+            | (Self::Stmt(Stmt::While { condition, .. }), 0) => {
+                let keyword = if let Self::Stmt(Stmt::If { .. }) = self {
+                    "if ("
+                } else {
+                    "while ("
+                };
+                (keyword.len(), Term::Expr(condition))
+            }
+            (
+                Self::Stmt(Stmt::If {
+                    condition,
+                    consequence,
+                    ..
+                }),
+                1,
+            ) => (len(Term::Expr(condition)) + 8, Term::Stmt(consequence)),
+            (
+                Self::Stmt(Stmt::If {
+                    condition,
+                    consequence,
+                    alternative,
+                }),
+                2,
+            ) => (
+                len(Term::Expr(condition)) + len(Term::Stmt(consequence)) + 18,
+                Term::Stmt(alternative),
+            ),
+            (Self::Stmt(Stmt::While { condition, body }), 1) => {
+                (len(Term::Expr(condition)) + 11, Term::Stmt(body))
+            }
+            (Self::Stmt(Stmt::Sequence { first, .. }), 0) => (0, Term::Stmt(first)),
+            (Self::Stmt(Stmt::Sequence { first, second }), 1) => {
+                (len(Term::Stmt(first)) + 2, Term::Stmt(second))
+            }
+            (Self::Expr(Expr::Add(l, _)), 0)
+            | (Self::Expr(Expr::Subtract(l, _)), 0)
+            | (Self::Expr(Expr::Multiply(l, _)), 0)
+            | (Self::Expr(Expr::Divide(l, _)), 0)
+            | (Self::Expr(Expr::Modulo(l, _)), 0)
+            | (Self::Expr(Expr::LessThan(l, _)), 0)
+            | (Self::Expr(Expr::GreaterThan(l, _)), 0)
+            | (Self::Expr(Expr::LessThanOrEqual(l, _)), 0)
+            | (Self::Expr(Expr::GreaterThanOrEqual(l, _)), 0)
+            | (Self::Expr(Expr::Equal(l, _)), 0)
+            | (Self::Expr(Expr::NotEqual(l, _)), 0)
+            | (Self::Expr(Expr::And(l, _)), 0)
+            | (Self::Expr(Expr::Or(l, _)), 0) => (parenthesized().0 as usize, Term::Expr(l)),
+            (Self::Expr(Expr::Add(l, r)), 1)
+            | (Self::Expr(Expr::Subtract(l, r)), 1)
+            | (Self::Expr(Expr::Multiply(l, r)), 1)
+            | (Self::Expr(Expr::Divide(l, r)), 1)
+            | (Self::Expr(Expr::Modulo(l, r)), 1)
+            | (Self::Expr(Expr::LessThan(l, r)), 1)
+            | (Self::Expr(Expr::GreaterThan(l, r)), 1)
+            | (Self::Expr(Expr::LessThanOrEqual(l, r)), 1)
+            | (Self::Expr(Expr::GreaterThanOrEqual(l, r)), 1)
+            | (Self::Expr(Expr::Equal(l, r)), 1)
+            | (Self::Expr(Expr::NotEqual(l, r)), 1)
+            | (Self::Expr(Expr::And(l, r)), 1)
+            | (Self::Expr(Expr::Or(l, r)), 1) => {
+                // The operator, which `label` spells, sits between two spaces.
+                let (left, right) = parenthesized();
+                let left = if left { 2 } else { 0 };
+                let offset = len(Term::Expr(l)) + left + self.label().len() + 2 + right as usize;
+                (offset, Term::Expr(r))
+            }
+            (Self::Expr(Expr::AssignExpr(name, expr)), 0) => (name.len() + 4, Term::Expr(expr)),
+            (Self::Expr(Expr::Not(expr)), 0) => {
+                (if expr.is_binary() { 2 } else { 1 }, Term::Expr(expr))
+            }
+            _ => panic!("{} has no child {}", self, index),
+        }
+    }
+}
+
+impl<'a> Term<'a> {
+    /// The sub-terms, in the order `child` numbers them.
+    fn children(self) -> Vec<Term<'a>> {
+        match self {
+            Self::Stmt(Stmt::DoNothing) => vec![],
+            Self::Stmt(Stmt::Assign(_, expr)) => vec![Term::Expr(expr)],
+            Self::Stmt(Stmt::If {
+                condition,
+                consequence,
+                alternative,
+            }) => vec![
+                Term::Expr(condition),
+                Term::Stmt(consequence),
+                Term::Stmt(alternative),
+            ],
+            Self::Stmt(Stmt::Sequence { first, second }) => {
+                vec![Term::Stmt(first), Term::Stmt(second)]
+            }
+            Self::Stmt(Stmt::While { condition, body }) => {
+                vec![Term::Expr(condition), Term::Stmt(body)]
+            }
+            Self::Expr(Expr::Add(l, r))
+            | Self::Expr(Expr::Subtract(l, r))
+            | Self::Expr(Expr::Multiply(l, r))
+            | Self::Expr(Expr::Divide(l, r))
+            | Self::Expr(Expr::Modulo(l, r))
+            | Self::Expr(Expr::LessThan(l, r))
+            | Self::Expr(Expr::GreaterThan(l, r))
+            | Self::Expr(Expr::LessThanOrEqual(l, r))
+            | Self::Expr(Expr::GreaterThanOrEqual(l, r))
+            | Self::Expr(Expr::Equal(l, r))
+            | Self::Expr(Expr::NotEqual(l, r))
+            | Self::Expr(Expr::And(l, r))
+            | Self::Expr(Expr::Or(l, r)) => vec![Term::Expr(l), Term::Expr(r)],
+            Self::Expr(Expr::AssignExpr(_, expr)) | Self::Expr(Expr::Not(expr)) => {
+                vec![Term::Expr(expr)]
+            }
+            Self::Expr(_) => vec![],
+        }
+    }
+
+    /// The term's own node in a tree rendering, without its children.
+    fn label(self) -> String {
+        match self {
+            Self::Stmt(Stmt::DoNothing) => "do-nothing".into(),
+            Self::Stmt(Stmt::Assign(name, _)) => format!("{} =", name),
+            Self::Stmt(Stmt::If { .. }) => "if".into(),
+            Self::Stmt(Stmt::Sequence { .. }) => ";".into(),
+            Self::Stmt(Stmt::While { .. }) => "while".into(),
+            Self::Expr(Expr::Add(..)) => "+".into(),
+            Self::Expr(Expr::Subtract(..)) => "-".into(),
+            Self::Expr(Expr::Multiply(..)) => "*".into(),
+            Self::Expr(Expr::Divide(..)) => "/".into(),
+            Self::Expr(Expr::Modulo(..)) => "%".into(),
+            Self::Expr(Expr::LessThan(..)) => "<".into(),
+            Self::Expr(Expr::GreaterThan(..)) => ">".into(),
+            Self::Expr(Expr::LessThanOrEqual(..)) => "<=".into(),
+            Self::Expr(Expr::GreaterThanOrEqual(..)) => ">=".into(),
+            Self::Expr(Expr::Equal(..)) => "==".into(),
+            Self::Expr(Expr::NotEqual(..)) => "!=".into(),
+            Self::Expr(Expr::And(..)) => "&&".into(),
+            Self::Expr(Expr::Or(..)) => "||".into(),
+            Self::Expr(Expr::Not(..)) => "!".into(),
+            Self::Expr(Expr::AssignExpr(name, _)) => format!("({} =)", name),
+            Self::Expr(expr) => expr.to_string(),
+        }
+    }
+
+    /// Renders the term as an indented tree, one node per line. Nodes
+    /// that are not where they were in `previous` are marked with `*`;
+    /// their children are new too, so they are not marked again.
+    fn tree(self, previous: Option<Term>, depth: usize, lines: &mut Vec<String>) {
+        let changed = previous.is_some_and(|previous| {
+            previous.label() != self.label() || previous.children().len() != self.children().len()
+        });
+        lines.push(format!(
+            "{} {}{}",
+            if changed { '*' } else { ' ' },
+            "  ".repeat(depth),
+            self.label()
+        ));
+        let previous = previous
+            .filter(|_| !changed)
+            .map_or_else(Vec::new, Term::children);
+        for (index, child) in self.children().into_iter().enumerate() {
+            child.tree(previous.get(index).copied(), depth + 1, lines);
+        }
+    }
+}
+
+impl fmt::Display for Term<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            Self::Stmt(stmt) => write!(f, "{}", stmt),
+            Self::Expr(expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
+/// The reduction rules of the small-step semantics, named after the
+/// construct whose redex they contract.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Rule {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    LessThan,
+    GreaterThan,
+    LessThanOrEqual,
+    GreaterThanOrEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    Not,
+    Variable,
+    AssignExpr,
+    Increment,
+    Assign,
+    IfTrue,
+    IfFalse,
+    Sequence,
+    While,
+    Random,
+}
+
+#[allow(dead_code)]
+impl Rule {
+    const ALL: [Rule; 23] = [
+        Rule::Add,
+        Rule::Subtract,
+        Rule::Multiply,
+        Rule::Divide,
+        Rule::Modulo,
+        Rule::LessThan,
+        Rule::GreaterThan,
+        Rule::LessThanOrEqual,
+        Rule::GreaterThanOrEqual,
+        Rule::Equal,
+        Rule::NotEqual,
+        Rule::And,
+        Rule::Or,
+        Rule::Not,
+        Rule::Variable,
+        Rule::AssignExpr,
+        Rule::Increment,
+        Rule::Assign,
+        Rule::IfTrue,
+        Rule::IfFalse,
+        Rule::Sequence,
+        Rule::While,
+        Rule::Random,
+    ];
+
+    /// The axiom this rule contracts a redex with. Reducing the leftmost
+    /// reducible operand first is implicit in every rule rather than a
+    /// rule of its own.
+    fn definition(self) -> RuleDefinition {
+        let (premises, conclusion): (&'static [&'static str], _) = match self {
+            Self::Add => (&["n = n1 + n2"], "<n1 + n2, env> -> <n, env>"),
+            Self::Subtract => (&["n = n1 - n2"], "<n1 - n2, env> -> <n, env>"),
+            Self::Multiply => (&["n = n1 * n2"], "<n1 * n2, env> -> <n, env>"),
+            Self::Divide => (&["n2 != 0", "n = n1 / n2"], "<n1 / n2, env> -> <n, env>"),
+            Self::Modulo => (&["n2 != 0", "n = n1 % n2"], "<n1 % n2, env> -> <n, env>"),
+            Self::LessThan => (&["b = n1 < n2"], "<n1 < n2, env> -> <b, env>"),
+            Self::GreaterThan => (&["b = n1 > n2"], "<n1 > n2, env> -> <b, env>"),
+            Self::LessThanOrEqual => (&["b = n1 <= n2"], "<n1 <= n2, env> -> <b, env>"),
+            Self::GreaterThanOrEqual => (&["b = n1 >= n2"], "<n1 >= n2, env> -> <b, env>"),
+            Self::Equal => (
+                &["v1, v2 : t", "b = v1 == v2"],
+                "<v1 == v2, env> -> <b, env>",
+            ),
+            Self::NotEqual => (
+                &["v1, v2 : t", "b = v1 != v2"],
+                "<v1 != v2, env> -> <b, env>",
+            ),
+            Self::And => (
+                &[],
+                "<true && b, env> -> <b, env>    <false && e, env> -> <false, env>",
+            ),
+            Self::Or => (
+                &[],
+                "<true || e, env> -> <true, env>    <false || b, env> -> <b, env>",
+            ),
+            Self::Not => (&["b' = !b"], "<!b, env> -> <b', env>"),
+            Self::Variable => (&["env(x) = v"], "<x, env> -> <v, env>"),
+            Self::AssignExpr => (&[], "<(x = v), env> -> <v, env[x := v]>"),
+            Self::Increment => (
+                &["env(x) = n"],
+                "<++x, env> -> <n + 1, env[x := n + 1]>    <x++, env> -> <n, env[x := n + 1]>",
+            ),
+            Self::Assign => (&[], "<x = v, env> -> <do-nothing, env[x := v]>"),
+            Self::IfTrue => (&[], "<if (true) { s1 } else { s2 }, env> -> <s1, env>"),
+            Self::IfFalse => (&[], "<if (false) { s1 } else { s2 }, env> -> <s2, env>"),
+            Self::Sequence => (&[], "<do-nothing; s2, env> -> <s2, env>"),
+            Self::While => (
+                &[],
+                "<while (e) { s }, env> -> \
+                 <if (e) { s; while (e) { s } } else { do-nothing }, env>",
+            ),
+            Self::Random => (&["0 <= n < max"], "<random(max), env> -> <n, env>"),
+        };
+        RuleDefinition {
+            name: match self {
+                Self::Add => "Add",
+                Self::Subtract => "Subtract",
+                Self::Multiply => "Multiply",
+                Self::Divide => "Divide",
+                Self::Modulo => "Modulo",
+                Self::LessThan => "LessThan",
+                Self::GreaterThan => "GreaterThan",
+                Self::LessThanOrEqual => "LessThanOrEqual",
+                Self::GreaterThanOrEqual => "GreaterThanOrEqual",
+                Self::Equal => "Equal",
+                Self::NotEqual => "NotEqual",
+                Self::And => "And",
+                Self::Or => "Or",
+                Self::Not => "Not",
+                Self::Variable => "Variable",
+                Self::AssignExpr => "AssignExpr",
+                Self::Increment => "Increment",
+                Self::Assign => "Assign",
+                Self::IfTrue => "IfTrue",
+                Self::IfFalse => "IfFalse",
+                Self::Sequence => "Sequence",
+                Self::While => "While",
+                Self::Random => "Random",
+            },
+            premises,
+            conclusion,
+        }
+    }
+}
+
+/// A rule written out as an inference rule: the premises above the line
+/// and the conclusion below it, in the notation of the book.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+struct RuleDefinition {
+    name: &'static str,
+    premises: &'static [&'static str],
+    conclusion: &'static str,
+}
+
+impl fmt::Display for RuleDefinition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        let premises = self.premises.join("    ");
+        let width = premises.len().max(self.conclusion.len());
+        writeln!(f, "{}", premises)?;
+        writeln!(f, "{} ({})", "-".repeat(width), self.name)?;
+        write!(f, "{}", self.conclusion)
+    }
+}
+
+/// Takes one step with `rule`, or returns `None` if the next step of
+/// `stmt` uses a different rule. `Rule::Random` has no single step to
+/// take, so it is never applied.
+#[allow(dead_code)]
+fn apply_rule(rule: Rule, stmt: &Stmt, env: &Environment) -> Option<(Stmt, Environment)> {
+    if rule == Rule::Random
+        || !stmt.is_reducible()
+        || stmt.error(env).is_some()
+        || stmt.rule() != rule
+    {
+        return None;
+    }
+    let (stmt, env) = stmt.reduce(env);
+    Some((stmt, env.into_owned()))
+}
+
+/// One entry of a recorded run. Each step is logged as its rule, then the
+/// numbers `random` drew during it, then the bindings it wrote.
+#[derive(PartialEq, Eq, Clone, Debug)]
+enum Event {
+    Step(Rule),
+    Random(i64),
+    Write(String, Expr),
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            Self::Step(rule) => write!(f, "step {:?}", rule),
+            Self::Random(n) => write!(f, "random {}", n),
+            Self::Write(name, value) => write!(f, "write {} = {}", name, value),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Event {
+    /// Parses one line written by `Display`.
+    fn parse(line: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid event `{}`", line);
+        let (kind, rest) = line.split_once(' ').ok_or_else(invalid)?;
+        match kind {
+            "step" => Rule::ALL
+                .iter()
+                .find(|rule| format!("{:?}", rule) == rest)
+                .map(|rule| Self::Step(*rule))
+                .ok_or_else(invalid),
+            "random" => rest.parse().map(Self::Random).map_err(|_| invalid()),
+            "write" => {
+                let (name, value) = rest.split_once(" = ").ok_or_else(invalid)?;
+                let value = match value {
+                    "true" => Expr::Boolean(true),
+                    "false" => Expr::Boolean(false),
+                    _ => Expr::Number(value.parse().map_err(|_| invalid())?),
+                };
+                Ok(Self::Write(name.into(), value))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Reconstructs the configuration `steps` steps into the run that `events`
+/// records, by reducing `stmt` from `env` again with the logged random
+/// numbers. Fails if the log does not describe a run of this program.
+#[allow(dead_code)]
+fn replay(
+    stmt: &Stmt,
+    env: &Environment,
+    events: &[Event],
+    steps: usize,
+) -> Result<(Stmt, Environment), String> {
+    let mut stmt = stmt.clone();
+    let mut env = env.clone();
+    let mut events = events.iter().peekable();
+    for step in 0..steps {
+        if let Some(error) = stmt.error(&env) {
+            return Err(format!("step {}: {}", step, error));
+        }
+        match events.next() {
+            Some(Event::Step(rule)) if stmt.is_reducible() && *rule == stmt.rule() => {}
+            Some(event) => return Err(format!("step {}: unexpected `{}`", step, event)),
+            None => return Err(format!("the log ends after {} steps", step)),
+        }
+        let mut drawn = vec![];
+        while let Some(Event::Random(n)) = events.peek() {
+            drawn.push(*n);
+            events.next();
+        }
+        let mut drawn = drawn.into_iter();
+        let mut missing = false;
+        let (next_stmt, next_env) = stmt.reduce_with(&env, &mut |_| {
+            drawn.next().unwrap_or_else(|| {
+                missing = true;
+                0
+            })
+        });
+        if missing || drawn.next().is_some() {
+            return Err(format!("step {}: random draws do not match", step));
+        }
+        let next_env = next_env.into_owned();
+        while let Some(Event::Write(name, value)) = events.peek() {
+            if next_env.get(name) != Ok(value) {
+                return Err(format!(
+                    "step {}: `{}` was not written",
+                    step,
+                    events.next().unwrap()
+                ));
+            }
+            events.next();
+        }
+        stmt = next_stmt;
+        env = next_env;
+    }
+    Ok((stmt, env))
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub(crate) enum Outcome {
+    Halted,
+    Breakpoint,
+    StepLimitReached,
+    Aborted,
+    ResourceExceeded(Resource),
+    Error(RuntimeError),
+    /// Writing the trace to the sink failed.
+    TraceFailed(io::ErrorKind),
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub(crate) enum RuntimeError {
+    /// A `random` expression was reduced in the deterministic profile.
+    Nondeterministic,
+    /// This division or remainder had a zero divisor.
+    DivisionByZero(Expr),
+    /// This arithmetic has a result outside the range of i64.
+    Overflow(Expr),
+    /// This variable was looked up but is not bound.
+    UndefinedVariable(String),
+    /// The operands of this equality are values of different types, an
+    /// operand of this arithmetic or comparison is a boolean, an operand
+    /// of this `&&`, `||` or `!` is a number, the variable of this
+    /// increment holds a boolean, or this condition is a number.
+    TypeMismatch(Expr),
+    /// This `random(max)` has no number to draw because `max` is not
+    /// positive.
+    InvalidRandomBound(Expr),
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            Self::Nondeterministic => write!(f, "`random` in a deterministic run"),
+            Self::DivisionByZero(expr) => write!(f, "division by zero in `{}`", expr),
+            Self::Overflow(expr) => write!(f, "arithmetic overflow in `{}`", expr),
+            Self::UndefinedVariable(name) => write!(f, "undefined variable `{}`", name),
+            Self::TypeMismatch(expr) => write!(f, "type mismatch in `{}`", expr),
+            Self::InvalidRandomBound(expr) => write!(f, "nothing to draw in `{}`", expr),
+        }
+    }
+}
+
+impl From<UndefinedVariable> for RuntimeError {
+    fn from(error: UndefinedVariable) -> Self {
+        Self::UndefinedVariable(error.0)
+    }
+}
+
+/// A splitmix64 generator: small, and the same seed always gives the
+/// same run.
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..max`; `max` must be positive.
+    fn below(&mut self, max: i64) -> i64 {
+        (self.next_u64() % max as u64) as i64
+    }
+}
+
+/// The limit that stopped a run with `Outcome::ResourceExceeded`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub(crate) enum Resource {
+    Timeout,
+    EnvironmentSize,
+    ValueSize,
+}
+
+/// Limits for running untrusted programs; `None` means unlimited.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+struct ResourceLimits {
+    timeout: Option<Duration>,
+    max_environment_size: Option<usize>,
+    max_value_size: Option<u64>,
+}
+
+impl ResourceLimits {
+    fn check(&self, env: &Environment) -> Result<(), Resource> {
+        if self
+            .max_environment_size
+            .is_some_and(|limit| env.len() > limit)
+        {
+            return Err(Resource::EnvironmentSize);
+        }
+        if let Some(limit) = self.max_value_size {
+            if env
+                .iter()
+                .any(|(_, value)| matches!(value, Expr::Number(n) if n.unsigned_abs() > limit))
+            {
+                return Err(Resource::ValueSize);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+struct Profile {
+    steps: usize,
+    environment_clones: usize,
+    bindings_cloned: usize,
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "{} steps, {} environment clones ({} bindings copied)",
+            self.steps, self.environment_clones, self.bindings_cloned
+        )
+    }
+}
+
+type StepHook<'a> = Box<dyn FnMut(&Stmt, &Environment, Rule) -> ControlFlow<()> + 'a>;
+
+/// The program is kept as a zipper over its chain of sequences: `focus`
+/// is the statement being reduced and `context` holds the statements
+/// still to run after it, innermost last. Each step only touches the
+/// focus instead of rebuilding every enclosing sequence.
+pub(crate) struct Machine<'a> {
+    focus: Stmt,
+    context: Vec<Stmt>,
+    pub(crate) env: Environment,
+    steps: usize,
+    step_limit: Option<usize>,
+    limits: ResourceLimits,
+    deterministic: bool,
+    explain: bool,
+    color: bool,
+    /// The environment in the last trace line shown, to pick out the
+    /// bindings the next line changes.
+    traced_env: Option<Environment>,
+    tree: bool,
+    /// The program in the last trace line, to diff the next tree against.
+    traced_program: Option<Stmt>,
+    fold: Option<FoldPolicy>,
+    /// The configurations hidden behind each fold so far, by fold number.
+    folds: Vec<Vec<String>>,
+    /// Hidden configurations not yet closed off by one that is shown.
+    folding: Vec<String>,
+    breakpoints: Vec<Stmt>,
+    /// Where the trace goes; without one, nothing is traced.
+    sink: Option<Box<dyn Write + 'a>>,
+    hooks: Vec<StepHook<'a>>,
+    profile: Profile,
+    rng: Rng,
+    /// Every step so far, when the machine was built with `record`.
+    log: Option<Vec<Event>>,
+    /// Set when the machine stopped at a breakpoint, so the next run
+    /// steps past the current statement's breakpoint.
+    resumed: bool,
+    /// What `Stmt::termination_warnings` found in the program the machine
+    /// was built with, for the caller to report.
+    warnings: Vec<TerminationWarning>,
+}
+
+/// Which configurations a folded trace shows.
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone, Debug)]
+enum FoldPolicy {
+    /// Those where one of these rules fires next; `Rules(&[Rule::While])`
+    /// shows one line per loop iteration.
+    Rules(&'static [Rule]),
+    /// Those whose environment differs from the last one shown.
+    EnvironmentChanges,
+}
+
+/// Everything a machine needs to carry on from where it was, apart from
+/// its sink and hooks, which belong to whoever runs it.
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct Snapshot {
+    focus: Stmt,
+    context: Vec<Stmt>,
+    env: Environment,
+    steps: usize,
+    profile: Profile,
+    rng: Rng,
+    log: Option<Vec<Event>>,
+    resumed: bool,
+}
+
+/// One `key value` line per field, and one `context` line per statement
+/// still to run, innermost last. A recorded log is a `log` line followed
+/// by an `event` line for each event.
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        writeln!(f, "focus {}", self.focus)?;
+        for stmt in &self.context {
+            writeln!(f, "context {}", stmt)?;
+        }
+        writeln!(f, "env {}", self.env)?;
+        writeln!(f, "steps {}", self.steps)?;
+        writeln!(
+            f,
+            "profile {} {} {}",
+            self.profile.steps, self.profile.environment_clones, self.profile.bindings_cloned
+        )?;
+        writeln!(f, "rng {}", self.rng.0)?;
+        writeln!(f, "resumed {}", self.resumed)?;
+        if let Some(log) = &self.log {
+            writeln!(f, "log")?;
+            for event in log {
+                writeln!(f, "event {}", event)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+impl Snapshot {
+    /// Parses a snapshot written by `Display`.
+    fn parse(source: &str) -> Result<Self, String> {
+        let mut focus = None;
+        let mut context = vec![];
+        let mut env = None;
+        let mut steps = None;
+        let mut profile = None;
+        let mut rng = None;
+        let mut resumed = None;
+        let mut log = None;
+        for (number, line) in source.lines().enumerate() {
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let count = |value: &str| {
+                value
+                    .parse()
+                    .map_err(|_| error(format!("invalid number `{}`", value)))
+            };
+            match key {
+                "focus" => focus = Some(Stmt::parse(value).map_err(error)?),
+                "context" => context.push(Stmt::parse(value).map_err(error)?),
+                "env" => env = Some(Environment::parse(value).map_err(error)?),
+                "steps" => steps = Some(count(value)?),
+                "profile" => {
+                    let counts = value
+                        .split(' ')
+                        .map(count)
+                        .collect::<Result<Vec<usize>, _>>()?;
+                    profile = match counts[..] {
+                        [steps, environment_clones, bindings_cloned] => Some(Profile {
+                            steps,
+                            environment_clones,
+                            bindings_cloned,
+                        }),
+                        _ => return Err(error(format!("invalid profile `{}`", value))),
+                    };
+                }
+                "rng" => {
+                    let state = value
+                        .parse()
+                        .map_err(|_| error(format!("invalid number `{}`", value)))?;
+                    rng = Some(Rng(state));
+                }
+                "resumed" => {
+                    let flag = value
+                        .parse()
+                        .map_err(|_| error(format!("invalid flag `{}`", value)))?;
+                    resumed = Some(flag);
+                }
+                "log" => log = Some(vec![]),
+                "event" => log
+                    .as_mut()
+                    .ok_or_else(|| error("event before `log`".into()))?
+                    .push(Event::parse(value).map_err(error)?),
+                "" => {}
+                _ => return Err(error(format!("unknown field `{}`", key))),
+            }
+        }
+        let missing = |field: &str| format!("missing `{}`", field);
+        Ok(Self {
+            focus: focus.ok_or_else(|| missing("focus"))?,
+            context,
+            env: env.ok_or_else(|| missing("env"))?,
+            steps: steps.ok_or_else(|| missing("steps"))?,
+            profile: profile.ok_or_else(|| missing("profile"))?,
+            rng: rng.ok_or_else(|| missing("rng"))?,
+            log,
+            resumed: resumed.ok_or_else(|| missing("resumed"))?,
+        })
+    }
+}
+
+pub(crate) struct MachineBuilder<'a> {
+    stmt: Stmt,
+    env: Environment,
+    step_limit: Option<usize>,
+    limits: ResourceLimits,
+    deterministic: bool,
+    seed: Option<u64>,
+    record: bool,
+    explain: bool,
+    color: bool,
+    tree: bool,
+    fold: Option<FoldPolicy>,
+    breakpoints: Vec<Stmt>,
+    sink: Option<Box<dyn Write + 'a>>,
+    hooks: Vec<StepHook<'a>>,
+}
+
+#[allow(dead_code)]
+impl<'a> MachineBuilder<'a> {
+    pub(crate) fn new(stmt: Stmt, env: Environment) -> Self {
+        Self {
+            stmt,
+            env,
+            step_limit: None,
+            limits: ResourceLimits::default(),
+            deterministic: false,
+            seed: None,
+            record: false,
+            explain: false,
+            color: false,
+            tree: false,
+            fold: None,
+            breakpoints: vec![],
+            sink: None,
+            hooks: vec![],
+        }
+    }
+
+    /// Makes `run` stop after `limit` reduction steps in total.
+    pub(crate) fn step_limit(mut self, limit: usize) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    /// Makes `run` give up once it has been running for `timeout`.
+    fn timeout(mut self, timeout: Duration) -> Self {
+        self.limits.timeout = Some(timeout);
+        self
+    }
+
+    /// Makes `run` stop once the environment holds more than `limit`
+    /// variables.
+    fn max_environment_size(mut self, limit: usize) -> Self {
+        self.limits.max_environment_size = Some(limit);
+        self
+    }
+
+    /// Makes `run` stop once a variable holds a number whose absolute
+    /// value exceeds `limit`.
+    fn max_value_size(mut self, limit: u64) -> Self {
+        self.limits.max_value_size = Some(limit);
+        self
+    }
+
+    /// Seeds the RNG behind `random`, which is otherwise seeded from the
+    /// clock.
+    fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// The profile for grading and fuzzing: runs are capped at
+    /// `step_limit` steps and must not depend on anything outside the
+    /// program and its environment, so the wall-clock timeout is dropped
+    /// and reducing `random` is an error.
+    fn deterministic(mut self, step_limit: usize) -> Self {
+        self.deterministic = true;
+        self.step_limit(step_limit)
+    }
+
+    /// Keeps an event log of every step in `Machine::log`, which `replay`
+    /// turns back into the configuration at any step.
+    fn record(mut self) -> Self {
+        self.record = true;
+        self
+    }
+
+    /// Follows each configuration in the trace with an explanation of the
+    /// step taken from it; see `Stmt::explain`.
+    fn explain(mut self) -> Self {
+        self.explain = true;
+        self
+    }
+
+    /// Colors the trace with ANSI escapes, unless `NO_COLOR` is set; see
+    /// `colored_configuration`.
+    fn color(mut self) -> Self {
+        self.color = true;
+        self
+    }
+
+    /// Traces each program as an indented tree, marking the nodes the
+    /// step before it changed; see `Term::tree`.
+    fn tree(mut self) -> Self {
+        self.tree = true;
+        self
+    }
+
+    /// Only shows the configurations `policy` picks, plus the first and
+    /// the last, and folds the steps in between into one line each; see
+    /// `Machine::expand`.
+    fn fold(mut self, policy: FoldPolicy) -> Self {
+        self.fold = Some(policy);
+        self
+    }
+
+    /// Makes `run` stop before reducing `stmt` whenever it becomes the
+    /// current statement.
+    fn breakpoint(mut self, stmt: Stmt) -> Self {
+        self.breakpoints.push(stmt);
+        self
+    }
+
+    /// Makes `run` write a trace of every configuration to `sink`.
+    fn sink(mut self, sink: impl Write + 'a) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    /// See `Machine::on_step`.
+    fn hook(mut self, hook: impl FnMut(&Stmt, &Environment, Rule) -> ControlFlow<()> + 'a) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    pub(crate) fn build(self) -> Machine<'a> {
+        let mut limits = self.limits;
+        if self.deterministic {
+            limits.timeout = None;
+        }
+        Machine {
+            warnings: self.stmt.termination_warnings(),
+            focus: self.stmt,
+            context: vec![],
+            env: self.env,
+            steps: 0,
+            step_limit: self.step_limit,
+            limits,
+            deterministic: self.deterministic,
+            explain: self.explain,
+            color: self.color && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+            traced_env: None,
+            tree: self.tree,
+            traced_program: None,
+            fold: self.fold,
+            folds: vec![],
+            folding: vec![],
+            breakpoints: self.breakpoints,
+            sink: self.sink,
+            hooks: self.hooks,
+            profile: Profile::default(),
+            rng: Rng(self.seed.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+            })),
+            log: if self.record { Some(vec![]) } else { None },
+            resumed: false,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<'a> Machine<'a> {
+    /// Registers a callback that is given the new configuration and the
+    /// rule that produced it after every reduction step. Returning
+    /// `ControlFlow::Break` makes `run` stop with `Outcome::Aborted`.
+    fn on_step(&mut self, hook: impl FnMut(&Stmt, &Environment, Rule) -> ControlFlow<()> + 'a) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            focus: self.focus.clone(),
+            context: self.context.clone(),
+            env: self.env.clone(),
+            steps: self.steps,
+            profile: self.profile,
+            rng: self.rng.clone(),
+            log: self.log.clone(),
+            resumed: self.resumed,
+        }
+    }
+
+    /// Puts the machine back into the state `snapshot` was taken in,
+    /// keeping its current limits, breakpoints, sink and hooks.
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.focus = snapshot.focus;
+        self.context = snapshot.context;
+        self.env = snapshot.env;
+        self.steps = snapshot.steps;
+        self.profile = snapshot.profile;
+        self.rng = snapshot.rng;
+        self.log = snapshot.log;
+        self.resumed = snapshot.resumed;
+    }
+
+    /// The whole program, rebuilt from the focus and its context.
+    fn program(&self) -> Stmt {
+        self.context
+            .iter()
+            .rev()
+            .fold(self.focus.clone(), |first, second| Stmt::Sequence {
+                first: first.into(),
+                second: second.clone().into(),
+            })
+    }
+
+    /// The path to the sub-term the next step contracts and where it sits
+    /// in the rendered `program`, or `None` once halted.
+    fn redex(&self) -> Option<(Vec<usize>, Range<usize>)> {
+        if self.is_halted() {
+            return None;
+        }
+        let program = self.program();
+        Some((program.redex_path(), program.redex_span()))
+    }
+
+    fn is_halted(&self) -> bool {
+        !self.focus.is_reducible() && self.context.is_empty()
+    }
+
+    /// Moves the focus down to the first statement of a sequence until it
+    /// rests on the statement the next step reduces. Not a step itself.
+    fn refocus(&mut self) {
+        while matches!(&self.focus, Stmt::Sequence { first, .. } if **first != Stmt::DoNothing) {
+            if let Stmt::Sequence { first, second } = mem::replace(&mut self.focus, Stmt::DoNothing)
+            {
+                self.context.push(*second);
+                self.focus = *first;
+            }
+        }
+    }
+
+    /// Reduces once, breaking with the outcome `run` should stop with if a
+    /// hook aborts or the step is an error.
+    fn step(&mut self) -> ControlFlow<Outcome> {
+        self.refocus();
+        let rule = if self.focus == Stmt::DoNothing {
+            // The focus finished, which is the `do-nothing; s` => `s`
+            // rule of the enclosing sequence.
+            self.focus = self.context.pop().expect("halted machine cannot step");
+            if let Some(log) = &mut self.log {
+                log.push(Event::Step(Rule::Sequence));
+            }
+            Rule::Sequence
+        } else {
+            if let Some(error) = self.focus.error(&self.env) {
+                return ControlFlow::Break(Outcome::Error(error));
+            }
+            let rule = self.focus.rule();
+            if self.deterministic && rule == Rule::Random {
+                return ControlFlow::Break(Outcome::Error(RuntimeError::Nondeterministic));
+            }
+            if let Some(log) = &mut self.log {
+                log.push(Event::Step(rule));
+            }
+            let before = CLONE_COUNTS.with(Cell::get);
+            let rng = &mut self.rng;
+            let log = &mut self.log;
+            let (new_stmt, new_env) = self.focus.reduce_with(&self.env, &mut |max| {
+                let n = rng.below(max);
+                if let Some(log) = log {
+                    log.push(Event::Random(n));
+                }
+                n
+            });
+            let new_env = match new_env {
+                Cow::Owned(env) => Some(env),
+                Cow::Borrowed(_) => None,
+            };
+            let after = CLONE_COUNTS.with(Cell::get);
+            self.focus = new_stmt;
+            if let Some(env) = new_env {
+                if let Some(log) = &mut self.log {
+                    let old = &self.env;
+                    let mut writes: Vec<_> = env
+                        .iter()
+                        .filter(|(name, value)| old.get(name) != Ok(value))
+                        .collect();
+                    writes.sort_by(|a, b| a.0.cmp(b.0));
+                    log.extend(
+                        writes
+                            .into_iter()
+                            .map(|(name, value)| Event::Write(name.clone(), value.clone())),
+                    );
+                }
+                self.env = env;
+            }
+            self.profile.environment_clones += after.environments - before.environments;
+            self.profile.bindings_cloned += after.bindings - before.bindings;
+            rule
+        };
+        self.steps += 1;
+        self.profile.steps += 1;
+        let mut flow = ControlFlow::Continue(());
+        if !self.hooks.is_empty() {
+            let program = self.program();
+            for hook in self.hooks.iter_mut() {
+                if hook(&program, &self.env, rule).is_break() {
+                    flow = ControlFlow::Break(Outcome::Aborted);
+                }
+            }
+        }
+        flow
+    }
+
+    fn trace(&mut self) -> io::Result<()> {
+        if self.sink.is_none() {
+            return Ok(());
+        }
+        let program = self.program();
+        let halted = self.is_halted();
+        // A program the next step fails on has no rule to explain or fold.
+        let stuck = halted || program.error(&self.env).is_some();
+        let mut lines = if self.tree {
+            let mut lines = vec![];
+            let previous = self.traced_program.as_ref().map(Term::Stmt);
+            Term::Stmt(&program).tree(previous, 0, &mut lines);
+            lines.push(format!("  {:?}", self.env));
+            lines
+        } else if self.color {
+            vec![colored_configuration(
+                &program,
+                &self.env,
+                self.traced_env.as_ref(),
+            )]
+        } else {
+            vec![format!("{}, {:?}", program, self.env)]
+        };
+        if self.explain && !stuck {
+            lines.push(format!("  because {}", program.explain()));
+        }
+        let shown = stuck
+            || self.steps == 0
+            || match &self.fold {
+                None => true,
+                Some(FoldPolicy::Rules(rules)) => rules.contains(&program.rule()),
+                Some(FoldPolicy::EnvironmentChanges) => self.traced_env.as_ref() != Some(&self.env),
+            };
+        if !shown {
+            self.folding.push(lines.join("\n"));
+            return Ok(());
+        }
+        let sink = self.sink.as_mut().expect("traced without a sink");
+        if !self.folding.is_empty() {
+            let folded = mem::take(&mut self.folding);
+            let steps = folded.len();
+            writeln!(
+                sink,
+                "  ... {} step{} folded (#{})",
+                steps,
+                if steps == 1 { "" } else { "s" },
+                self.folds.len()
+            )?;
+            self.folds.push(folded);
+        }
+        for line in lines {
+            writeln!(sink, "{}", line)?;
+        }
+        if self.color || self.fold.is_some() {
+            self.traced_env = Some(self.env.clone());
+        }
+        if self.tree {
+            self.traced_program = Some(program);
+        }
+        Ok(())
+    }
+
+    /// The configurations hidden behind fold number `fold`, each as the
+    /// trace lines it would have printed.
+    fn expand(&self, fold: usize) -> &[String] {
+        &self.folds[fold]
+    }
+
+    /// Reduces until the program halts, hits a breakpoint or runs out of
+    /// steps or another resource. Calling `run` again resumes from a
+    /// breakpoint, with a fresh timeout.
+    pub(crate) fn run(&mut self) -> Outcome {
+        match self.run_for(None) {
+            Poll::Ready(outcome) => outcome,
+            Poll::Pending => unreachable!(),
+        }
+    }
+
+    /// Like `run`, but returns `Poll::Pending` after `n` steps if the run
+    /// has not finished by then, so a host can spread a long program over
+    /// several ticks. Each call gets a fresh timeout.
+    fn poll_steps(&mut self, n: usize) -> Poll<Outcome> {
+        self.run_for(Some(n))
+    }
+
+    fn run_for(&mut self, budget: Option<usize>) -> Poll<Outcome> {
+        let started = Instant::now();
+        let mut taken = 0;
+        while !self.is_halted() {
+            self.refocus();
+            if !self.resumed && self.breakpoints.contains(self.focus.current()) {
+                self.resumed = true;
+                return Poll::Ready(Outcome::Breakpoint);
+            }
+            if budget.is_some_and(|budget| taken >= budget) {
+                return Poll::Pending;
+            }
+            if self.step_limit.is_some_and(|limit| self.steps >= limit) {
+                return Poll::Ready(Outcome::StepLimitReached);
+            }
+            if self
+                .limits
+                .timeout
+                .is_some_and(|timeout| started.elapsed() >= timeout)
+            {
+                return Poll::Ready(Outcome::ResourceExceeded(Resource::Timeout));
+            }
+            self.resumed = false;
+            taken += 1;
+            if let Err(error) = self.trace() {
+                return Poll::Ready(Outcome::TraceFailed(error.kind()));
+            }
+            if let ControlFlow::Break(outcome) = self.step() {
+                return Poll::Ready(outcome);
+            }
+            if let Err(resource) = self.limits.check(&self.env) {
+                return Poll::Ready(Outcome::ResourceExceeded(resource));
+            }
+        }
+        if let Err(error) = self.trace() {
+            return Poll::Ready(Outcome::TraceFailed(error.kind()));
+        }
+        Poll::Ready(Outcome::Halted)
+    }
+}
+
+const KEYWORD: (&str, &str) = ("\x1b[1;34m", "\x1b[22;39m");
+const LITERAL: (&str, &str) = ("\x1b[33m", "\x1b[39m");
+const REDEX: (&str, &str) = ("\x1b[7m", "\x1b[27m");
+const CHANGED: (&str, &str) = ("\x1b[32m", "\x1b[39m");
+
+fn paint(text: &str, (start, end): (&str, &str)) -> String {
+    format!("{}{}{}", start, text, end)
+}
+
+/// Paints the keywords and literals in a rendered program.
+fn paint_tokens(text: &str) -> String {
+    let mut painted = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let word_len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        let (token, style) = if rest.starts_with("do-nothing") {
+            ("do-nothing", Some(KEYWORD))
+        } else if c.is_ascii_digit()
+            || c == '-' && rest[1..].starts_with(|c: char| c.is_ascii_digit())
+        {
+            let len = 1 + rest[1..]
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len() - 1);
+            (&rest[..len], Some(LITERAL))
+        } else if word_len > 0 {
+            let word = &rest[..word_len];
+            let style = match word {
+                "if" | "else" | "while" | "random" => Some(KEYWORD),
+                "true" | "false" => Some(LITERAL),
+                _ => None,
+            };
+            (word, style)
+        } else {
+            (&rest[..c.len_utf8()], None)
+        };
+        match style {
+            Some(style) => painted.push_str(&paint(token, style)),
+            None => painted.push_str(token),
+        }
+        rest = &rest[token.len()..];
+    }
+    painted
+}
+
+/// A trace line like `"{program}, {env:?}"` with ANSI colors: keywords,
+/// literals, the redex the next step contracts, and the bindings that
+/// differ from `previous` each stand out.
+fn colored_configuration(
+    program: &Stmt,
+    env: &Environment,
+    previous: Option<&Environment>,
+) -> String {
+    let text = program.to_string();
+    let program = if program.is_reducible() {
+        let span = program.redex_span();
+        format!(
+            "{}{}{}",
+            paint_tokens(&text[..span.start]),
+            paint(&paint_tokens(&text[span.clone()]), REDEX),
+            paint_tokens(&text[span.end..])
+        )
+    } else {
+        paint_tokens(&text)
+    };
+    let bindings: Vec<_> = env
+        .iter()
+        .map(|(name, value)| {
+            let binding = format!("{:?}: {:?}", name, value);
+            match previous {
+                Some(previous) if previous.get(name) != Ok(value) => paint(&binding, CHANGED),
+                _ => binding,
+            }
+        })
+        .collect();
+    format!("{}, {{{}}}", program, bindings.join(", "))
+}
+
+/// A bounded stand-in for the impossible `does_it_halt`: it can only ever
+/// answer "halts" for programs that finish within `n_steps` reductions.
+#[allow(dead_code)]
+fn halts_within(stmt: &Stmt, env: &Environment, n_steps: usize) -> bool {
+    let mut machine = MachineBuilder::new(stmt.clone(), env.clone())
+        .step_limit(n_steps)
+        .build();
+    machine.run() == Outcome::Halted
+}
+
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Debug)]
+enum ModelCheckResult {
+    /// Every reachable configuration satisfies the invariant.
+    Verified {
+        states: usize,
+    },
+    /// A run from an initial configuration to one breaking the invariant.
+    Violated {
+        trace: Vec<(Stmt, Environment)>,
+    },
+    StateLimitReached,
+}
+
+#[allow(dead_code)]
+type Configuration = (Stmt, Vec<(String, Expr)>);
+
+#[allow(dead_code)]
+fn configuration(stmt: &Stmt, env: &Environment) -> Configuration {
+    let bindings = env
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    (stmt.clone(), bindings)
+}
+
+/// Every environment binding `variables` to values from `values`.
+#[allow(dead_code)]
+fn all_environments(variables: &[&str], values: &[Expr]) -> Vec<Environment> {
+    let mut envs = vec![Environment::new()];
+    for name in variables {
+        envs = envs
+            .into_iter()
+            .flat_map(|env| {
+                values.iter().map(move |value| {
+                    let mut env = env.clone();
+                    env.set(name, value.clone());
+                    env
+                })
+            })
+            .collect();
+    }
+    envs
+}
+
+/// Explores every configuration reachable from `stmt` started in any of
+/// `initial_envs` and checks `invariant` on each environment. A `random`
+/// step branches into every number it can draw. Revisited configurations
+/// are not explored again, so a program over a finite value domain is
+/// checked completely even if it loops forever.
+#[allow(dead_code)]
+fn check_invariant(
+    stmt: &Stmt,
+    initial_envs: Vec<Environment>,
+    invariant: impl Fn(&Environment) -> bool,
+    max_states: usize,
+) -> ModelCheckResult {
+    let mut states: Vec<(Stmt, Environment, Option<usize>)> = vec![];
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+    for env in initial_envs {
+        if seen.insert(configuration(stmt, &env)) {
+            queue.push_back(states.len());
+            states.push((stmt.clone(), env, None));
+        }
+    }
+    while let Some(index) = queue.pop_front() {
+        let (stmt, env, _) = &states[index];
+        if !invariant(env) {
+            let mut trace = vec![];
+            let mut current = Some(index);
+            while let Some(i) = current {
+                trace.push((states[i].0.clone(), states[i].1.clone()));
+                current = states[i].2;
+            }
+            trace.reverse();
+            return ModelCheckResult::Violated { trace };
+        }
+        if !stmt.is_reducible() || stmt.error(env).is_some() {
+            continue;
+        }
+        for (next_stmt, next_env) in stmt.successors(env) {
+            let key = configuration(&next_stmt, &next_env);
+            if seen.contains(&key) {
+                continue;
+            }
+            if states.len() == max_states {
+                return ModelCheckResult::StateLimitReached;
+            }
+            seen.insert(key);
+            queue.push_back(states.len());
+            states.push((next_stmt, next_env, Some(index)));
+        }
+    }
+    ModelCheckResult::Verified {
+        states: states.len(),
+    }
+}
+
+/// Builds a program that always halts but takes more than `n_steps`
+/// reductions, so `halts_within(_, _, n_steps)` is wrong about it.
+#[allow(dead_code)]
+fn outlast(n_steps: usize) -> Stmt {
+    Stmt::Sequence {
+        first: Stmt::Assign("i".into(), Expr::Number(0)).into(),
+        second: Stmt::While {
+            condition: Expr::LessThan(
+                Expr::Variable("i".into()).into(),
+                Expr::Number(n_steps as i64).into(),
+            ),
+            body: Stmt::Assign(
+                "i".into(),
+                Expr::Add(Expr::Variable("i".into()).into(), Expr::Number(1).into()),
+            )
+            .into(),
+        }
+        .into(),
+    }
+}
+
+fn main() {
+    let stmt = Stmt::While {
+        condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(5).into()),
+        body: Stmt::Assign(
+            "x".into(),
+            Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(3).into()),
+        )
+        .into(),
+    };
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let env = match args.as_slice() {
+        [] => {
+            let mut env = Environment::new();
+            env.set("x", Expr::Number(1));
+            env
+        }
+        [flag, path] if flag == "--env-file" => Environment::load(path).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1)
+        }),
+        _ => {
+            eprintln!("usage: small_step_semantics [--env-file <path>]");
+            process::exit(2)
+        }
+    };
+    let mut machine = MachineBuilder::new(stmt, env).sink(io::stdout()).build();
+    for warning in &machine.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    let message = match machine.run() {
+        Outcome::Halted => return,
+        Outcome::Error(error) => error.to_string(),
+        Outcome::TraceFailed(kind) => format!("failed to write trace: {}", kind),
+        outcome => format!("stopped before halting: {:?}", outcome),
+    };
+    eprintln!("error: {}", message);
+    process::exit(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halts_within_finite_program() {
+        let stmt = Stmt::Assign(
+            "x".into(),
+            Expr::Add(Expr::Number(1).into(), Expr::Number(2).into()),
+        );
+        let env = Environment::new();
+        assert!(halts_within(&stmt, &env, 2));
+        assert!(!halts_within(&stmt, &env, 1));
+    }
+
+    #[test]
+    fn halts_within_infinite_loop() {
+        let stmt = Stmt::While {
+            condition: Expr::Boolean(true),
+            body: Stmt::DoNothing.into(),
+        };
+        let env = Environment::new();
+        assert!(!halts_within(&stmt, &env, 1000));
+    }
+
+    #[test]
+    fn halts_within_is_outlasted() {
+        let env = Environment::new();
+        for n_steps in [0, 10, 100] {
+            let stmt = outlast(n_steps);
+            assert!(!halts_within(&stmt, &env, n_steps));
+            assert!(halts_within(&stmt, &env, 10 * n_steps + 10));
+        }
+    }
+
+    fn reduce_fully(stmt: Stmt, env: Environment) -> Environment {
+        let mut machine = MachineBuilder::new(stmt, env).build();
+        machine.run();
+        machine.env
+    }
+
+    fn count_to(n: i64) -> Stmt {
+        Stmt::While {
+            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(n).into()),
+            body: Stmt::Assign(
+                "x".into(),
+                Expr::Add(Expr::Variable("x".into()).into(), Expr::Number(1).into()),
+            )
+            .into(),
+        }
+    }
+
+    #[test]
+    fn machine_builder_options() {
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(0));
+
+        let mut trace = vec![];
+        let mut steps = 0;
+        let mut machine = MachineBuilder::new(count_to(3), env.clone())
+            .step_limit(4)
+            .sink(&mut trace)
+            .hook(|_, _, _| {
+                steps += 1;
+                ControlFlow::Continue(())
+            })
+            .build();
+        assert_eq!(Outcome::StepLimitReached, machine.run());
+        drop(machine);
+        assert_eq!(4, steps);
+        assert_eq!(4, String::from_utf8(trace).unwrap().lines().count());
+
+        let increment = Stmt::Assign(
+            "x".into(),
+            Expr::Add(Expr::Variable("x".into()).into(), Expr::Number(1).into()),
+        );
+        let mut machine = MachineBuilder::new(count_to(3), env)
+            .breakpoint(increment)
+            .build();
+        for x in 0..3 {
+            assert_eq!(Outcome::Breakpoint, machine.run());
+            assert_eq!(Ok(&Expr::Number(x)), machine.env.get("x"));
+        }
+        assert_eq!(Outcome::Halted, machine.run());
+        assert_eq!(Ok(&Expr::Number(3)), machine.env.get("x"));
+
+        // A breakpoint on the very first statement stops the first run.
+        let first = Stmt::Assign("x".into(), Expr::Number(1));
+        let mut machine = MachineBuilder::new(first.clone(), Environment::new())
+            .breakpoint(first)
+            .build();
+        assert_eq!(Outcome::Breakpoint, machine.run());
+        assert_eq!(0, machine.steps);
+        assert_eq!(Outcome::Halted, machine.run());
+    }
+
+    #[test]
+    fn trace_failure() {
+        struct Broken;
+
+        impl Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(0));
+        let mut machine = MachineBuilder::new(count_to(3), env).sink(Broken).build();
+        assert_eq!(
+            Outcome::TraceFailed(io::ErrorKind::BrokenPipe),
+            machine.run()
+        );
+        assert_eq!(0, machine.steps);
+    }
+
+    #[test]
+    fn resource_limits() {
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(0));
+        let forever = Stmt::While {
+            condition: Expr::Boolean(true),
+            body: Stmt::DoNothing.into(),
+        };
+        let mut machine = MachineBuilder::new(forever, env.clone())
+            .timeout(Duration::from_millis(10))
+            .build();
+        assert_eq!(Outcome::ResourceExceeded(Resource::Timeout), machine.run());
+
+        let mut machine = MachineBuilder::new(count_to(1000), env.clone())
+            .max_value_size(5)
+            .build();
+        assert_eq!(
+            Outcome::ResourceExceeded(Resource::ValueSize),
+            machine.run()
+        );
+        assert_eq!(Ok(&Expr::Number(6)), machine.env.get("x"));
+
+        let assign = |name: &str| Stmt::Assign(name.into(), Expr::Number(1));
+        let stmt = Stmt::Sequence {
+            first: assign("y").into(),
+            second: assign("z").into(),
+        };
+        let mut machine = MachineBuilder::new(stmt, env)
+            .max_environment_size(2)
+            .build();
+        assert_eq!(
+            Outcome::ResourceExceeded(Resource::EnvironmentSize),
+            machine.run()
+        );
+        assert_eq!(3, machine.env.len());
+    }
+
+    #[test]
+    fn deterministic_profile() {
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(0));
+        let run = |stmt: Stmt| {
+            let mut machine = MachineBuilder::new(stmt, env.clone())
+                .timeout(Duration::ZERO)
+                .deterministic(100)
+                .build();
+            (machine.run(), machine.env)
+        };
+        let (outcome, env) = run(count_to(3));
+        assert_eq!(Outcome::Halted, outcome);
+        assert_eq!(Ok(&Expr::Number(3)), env.get("x"));
+        assert_eq!(run(count_to(1000)), run(count_to(1000)));
+        assert_eq!(Outcome::StepLimitReached, run(count_to(1000)).0);
+    }
+
+    #[test]
+    fn random_is_seedable() {
+        // x = random(6); y = random(6)
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign("x".into(), Expr::Random(6)).into(),
+            second: Stmt::Assign("y".into(), Expr::Random(6)).into(),
+        };
+        let run = |seed| {
+            let mut machine = MachineBuilder::new(stmt.clone(), Environment::new())
+                .seed(seed)
+                .build();
+            assert_eq!(Outcome::Halted, machine.run());
+            machine.env
+        };
+        let env = run(42);
+        assert_eq!(env, run(42));
+        for name in ["x", "y"] {
+            assert!(matches!(env[name], Expr::Number(0..=5)));
+        }
+        assert!((0..20).any(|seed| run(seed) != env));
+
+        let mut machine = MachineBuilder::new(stmt, Environment::new())
+            .seed(42)
+            .deterministic(100)
+            .build();
+        assert_eq!(
+            Outcome::Error(RuntimeError::Nondeterministic),
+            machine.run()
+        );
+        assert!(machine.env.is_empty());
+
+        for max in [0, -3] {
+            let stmt = Stmt::Assign("x".into(), Expr::Random(max));
+            let mut machine = MachineBuilder::new(stmt, Environment::new()).build();
+            assert_eq!(
+                Outcome::Error(RuntimeError::InvalidRandomBound(Expr::Random(max))),
+                machine.run()
+            );
+            assert!(machine.env.is_empty());
+        }
+    }
+
+    #[test]
+    fn poll_steps_resumes() {
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(0));
+        let mut machine = MachineBuilder::new(count_to(3), env.clone()).build();
+        let mut polls = 1;
+        while machine.poll_steps(5).is_pending() {
+            assert_eq!(5 * polls, machine.steps);
+            polls += 1;
+        }
+        assert_eq!(Poll::Ready(Outcome::Halted), machine.poll_steps(5));
+        assert_eq!(Ok(&Expr::Number(3)), machine.env.get("x"));
+
+        let mut machine = MachineBuilder::new(count_to(3), env)
+            .step_limit(machine.steps)
+            .build();
+        assert_eq!(Poll::Pending, machine.poll_steps(0));
+        assert_eq!(Outcome::Halted, machine.run());
+    }
+
+    #[test]
+    fn replay_event_log() {
+        // x = random(10); y = (x = x + random(10)) * 2
+        let x = || Box::new(Expr::Variable("x".into()));
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign("x".into(), Expr::Random(10)).into(),
+            second: Stmt::Assign(
+                "y".into(),
+                Expr::Multiply(
+                    Expr::AssignExpr("x".into(), Expr::Add(x(), Expr::Random(10).into()).into())
+                        .into(),
+                    Expr::Number(2).into(),
+                ),
+            )
+            .into(),
+        };
+        let env = Environment::new();
+        let mut machine = MachineBuilder::new(stmt.clone(), env.clone())
+            .seed(7)
+            .record()
+            .build();
+        assert_eq!(Outcome::Halted, machine.run());
+        let log = machine.log.clone().unwrap();
+        assert_eq!(Event::Step(Rule::Random), log[0]);
+        assert!(matches!(log[1], Event::Random(0..=9)));
+        assert_eq!(
+            Event::Write("x".into(), machine.env["x"].clone()),
+            log[log.len() - 4]
+        );
+
+        let text: Vec<String> = log.iter().map(ToString::to_string).collect();
+        let parsed: Vec<Event> = text
+            .iter()
+            .map(|line| Event::parse(line).unwrap())
+            .collect();
+        assert_eq!(log, parsed);
+
+        for steps in 0..=machine.steps {
+            let mut expected = MachineBuilder::new(stmt.clone(), env.clone())
+                .seed(7)
+                .step_limit(steps)
+                .build();
+            expected.run();
+            assert_eq!(
+                Ok((expected.program(), expected.env)),
+                replay(&stmt, &env, &parsed, steps)
+            );
+        }
+        assert!(replay(&stmt, &env, &parsed, machine.steps + 1).is_err());
+        assert!(replay(&count_to(3), &env, &parsed, 1).is_err());
+        assert!(Event::parse("step Jump").is_err());
+    }
+
+    #[test]
+    fn snapshot_and_restore() {
+        // x = random(100); y = random(100), paused after the first assignment
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign("x".into(), Expr::Random(100)).into(),
+            second: Stmt::Assign("y".into(), Expr::Random(100)).into(),
+        };
+        let mut machine = MachineBuilder::new(stmt, Environment::new())
+            .seed(3)
+            .build();
+        assert!(machine.poll_steps(2).is_pending());
+        let snapshot = machine.snapshot();
+        assert_eq!(Outcome::Halted, machine.run());
+        let finished = machine.env.clone();
+
+        let mut trace = vec![];
+        let mut resumed = MachineBuilder::new(Stmt::DoNothing, Environment::new())
+            .sink(&mut trace)
+            .build();
+        assert_eq!(Ok(snapshot.clone()), Snapshot::parse(&snapshot.to_string()));
+        resumed.restore(snapshot.clone());
+        assert_eq!(snapshot, resumed.snapshot());
+        assert_eq!(Outcome::Halted, resumed.run());
+        assert_eq!(finished, resumed.env);
+        assert_eq!(machine.steps, resumed.steps);
+        drop(resumed);
+        assert!(String::from_utf8(trace)
+            .unwrap()
+            .starts_with("do-nothing; y = random(100)"));
+    }
+
+    #[test]
+    fn snapshot_text_round_trips() {
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign("x".into(), Expr::Random(2)).into(),
+            second: count_to(3).into(),
+        };
+        let mut machine = MachineBuilder::new(stmt, Environment::new())
+            .seed(5)
+            .record()
+            .build();
+        assert!(machine.poll_steps(7).is_pending());
+        let text = machine.snapshot().to_string();
+        let parsed = Snapshot::parse(&text).unwrap();
+        assert_eq!(machine.snapshot(), parsed);
+        assert_eq!(text, parsed.to_string());
+
+        let mut resumed = MachineBuilder::new(Stmt::DoNothing, Environment::new()).build();
+        resumed.restore(parsed);
+        assert_eq!(machine.run(), resumed.run());
+        assert_eq!(machine.env, resumed.env);
+        assert_eq!(machine.steps, resumed.steps);
+        assert_eq!(machine.log, resumed.log);
+
+        assert!(Snapshot::parse("steps 1").is_err());
+        assert!(Snapshot::parse(&text.replace("rng ", "rng x")).is_err());
+        assert!(Snapshot::parse(&text.replace("log\n", "")).is_err());
+    }
+
+    #[test]
+    fn display_parses_back() {
+        let n = |n| Box::new(Expr::Number(n));
+        let x = || Box::new(Expr::Variable("x".into()));
+        let exprs = vec![
+            Expr::Multiply(Expr::Add(n(1), n(2)).into(), n(3)),
+            Expr::Subtract(n(1), Expr::Subtract(n(2), n(-3)).into()),
+            Expr::LessThan(
+                Expr::LessThan(n(1), n(2)).into(),
+                Expr::Boolean(true).into(),
+            ),
+            Expr::Or(
+                Expr::And(Expr::Boolean(true).into(), Expr::Boolean(false).into()).into(),
+                Expr::Not(Expr::Or(x(), x()).into()).into(),
+            ),
+            Expr::Equal(
+                Expr::AssignExpr("y".into(), Expr::Modulo(x(), n(2)).into()).into(),
+                Expr::NotEqual(n(0), n(1)).into(),
+            ),
+            Expr::Add(
+                Expr::PostIncrement("x".into()).into(),
+                Expr::PreIncrement("x".into()).into(),
+            ),
+            Expr::Divide(Expr::Random(-3).into(), Expr::Random(10).into()),
+        ];
+        for expr in &exprs {
+            let text = expr.to_string();
+            assert_eq!(Ok(expr.clone()), Expr::parse(&text), "{}", text);
+            for (index, child) in Term::Expr(expr).children().into_iter().enumerate() {
+                let (offset, _) = Term::Expr(expr).child(index);
+                assert!(text[offset..].starts_with(&child.to_string()), "{}", text);
+            }
+        }
+        assert_eq!("(1 + 2) * 3", exprs[0].to_string());
+        assert_eq!("1 - (2 - -3)", exprs[1].to_string());
+
+        let stmt = Stmt::Sequence {
+            first: Stmt::If {
+                condition: exprs[2].clone(),
+                consequence: Stmt::Assign("x".into(), exprs[0].clone()).into(),
+                alternative: Stmt::DoNothing.into(),
+            }
+            .into(),
+            second: count_to(3).into(),
+        };
+        assert_eq!(Ok(stmt.clone()), Stmt::parse(&stmt.to_string()));
+        assert!(Stmt::parse("x = ").is_err());
+        assert!(Stmt::parse("if (true) { do-nothing }").is_err());
+    }
+
+    #[test]
+    fn side_effects_follow_left_to_right_order() {
+        // y = x + x++  vs  y = x++ + x, starting from x = 1
+        let x = || Box::new(Expr::Variable("x".into()));
+        let x_inc = || Box::new(Expr::PostIncrement("x".into()));
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(1));
+
+        let env_left = reduce_fully(
+            Stmt::Assign("y".into(), Expr::Add(x(), x_inc())),
+            env.clone(),
+        );
+        assert_eq!(Ok(&Expr::Number(2)), env_left.get("y"));
+        assert_eq!(Ok(&Expr::Number(2)), env_left.get("x"));
+
+        let env_right = reduce_fully(Stmt::Assign("y".into(), Expr::Add(x_inc(), x())), env);
+        assert_eq!(Ok(&Expr::Number(3)), env_right.get("y"));
+        assert_eq!(Ok(&Expr::Number(2)), env_right.get("x"));
+    }
+
+    #[test]
+    fn assign_expr_and_pre_increment() {
+        // y = (x = 3) * ++x
+        let stmt = Stmt::Assign(
+            "y".into(),
+            Expr::Multiply(
+                Expr::AssignExpr("x".into(), Expr::Number(3).into()).into(),
+                Expr::PreIncrement("x".into()).into(),
+            ),
+        );
+        let env = reduce_fully(stmt, Environment::new());
+        assert_eq!(Ok(&Expr::Number(12)), env.get("y"));
+        assert_eq!(Ok(&Expr::Number(4)), env.get("x"));
+
+        // y = ++b, with b a boolean
+        let env: Environment = vec![("b".to_string(), Expr::Boolean(true))]
+            .into_iter()
+            .collect();
+        for increment in [
+            Expr::PreIncrement("b".into()),
+            Expr::PostIncrement("b".into()),
+        ] {
+            let stmt = Stmt::Assign("y".into(), increment.clone());
+            let mut machine = MachineBuilder::new(stmt, env.clone()).build();
+            assert_eq!(
+                Outcome::Error(RuntimeError::TypeMismatch(increment)),
+                machine.run()
+            );
+            assert_eq!(env, machine.env);
+        }
+    }
+
+    #[test]
+    fn subtract() {
+        // y = x - 5 - x++
+        let x = || Box::new(Expr::Variable("x".into()));
+        let stmt = Stmt::Assign(
+            "y".into(),
+            Expr::Subtract(
+                Expr::Subtract(x(), Expr::Number(5).into()).into(),
+                Expr::PostIncrement("x".into()).into(),
+            ),
+        );
+        assert_eq!("y = x - 5 - x++", stmt.to_string());
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(2));
+        let env = reduce_fully(stmt, env);
+        assert_eq!(Ok(&Expr::Number(-5)), env.get("y"));
+        assert_eq!(Ok(&Expr::Number(3)), env.get("x"));
+    }
+
+    #[test]
+    fn comparisons() {
+        let x = || Box::new(Expr::Variable("x".into()));
+        let n = |n| Box::new(Expr::Number(n));
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(3));
+        let cases = [
+            (Expr::GreaterThan(x(), n(2)), "x > 2", true),
+            (Expr::GreaterThan(x(), n(3)), "x > 3", false),
+            (Expr::LessThanOrEqual(x(), n(3)), "x <= 3", true),
+            (Expr::LessThanOrEqual(x(), n(2)), "x <= 2", false),
+            (Expr::GreaterThanOrEqual(x(), n(3)), "x >= 3", true),
+            (Expr::GreaterThanOrEqual(x(), n(4)), "x >= 4", false),
+        ];
+        for (expr, source, result) in cases {
+            assert_eq!(source, expr.to_string());
+            let stmt = Stmt::Assign("b".into(), expr);
+            let env = reduce_fully(stmt, env.clone());
+            assert_eq!(Ok(&Expr::Boolean(result)), env.get("b"), "{}", source);
+        }
+
+        // Multi-character operators still put the right operand's span in
+        // the right place.
+        let stmt = Stmt::Assign("b".into(), Expr::GreaterThanOrEqual(n(1), x()));
+        assert_eq!(9..10, stmt.redex_span());
+
+        // while (x >= 0) { x = x - 1 } terminates with rank x - 0 + 1.
+        let stmt = Stmt::While {
+            condition: Expr::GreaterThanOrEqual(x(), n(0)),
+            body: Stmt::Assign("x".into(), Expr::Subtract(x(), n(1))).into(),
+        };
+        assert!(stmt.termination_proofs()[0].1.is_some());
+    }
+
+    #[test]
+    fn equality() {
+        let x = || Box::new(Expr::Variable("x".into()));
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(3));
+        let cases = [
+            (Expr::Equal(x(), Expr::Number(3).into()), "x == 3", true),
+            (Expr::NotEqual(x(), Expr::Number(3).into()), "x != 3", false),
+            (
+                Expr::Equal(
+                    Expr::LessThan(x(), Expr::Number(4).into()).into(),
+                    Expr::Boolean(true).into(),
+                ),
+                "x < 4 == true",
+                true,
+            ),
+        ];
+        for (expr, source, result) in cases {
+            assert_eq!(source, expr.to_string());
+            let env = reduce_fully(Stmt::Assign("b".into(), expr), env.clone());
+            assert_eq!(Ok(&Expr::Boolean(result)), env.get("b"), "{}", source);
+        }
+
+        // b = x != true
+        let stmt = Stmt::Assign("b".into(), Expr::NotEqual(x(), Expr::Boolean(true).into()));
+        let mut machine = MachineBuilder::new(stmt, env.clone()).build();
+        let failing = Expr::NotEqual(Expr::Number(3).into(), Expr::Boolean(true).into());
+        assert_eq!(
+            Outcome::Error(RuntimeError::TypeMismatch(failing.clone())),
+            machine.run()
+        );
+        assert_eq!(Stmt::Assign("b".into(), failing), machine.program());
+
+        // b = true < x
+        let stmt = Stmt::Assign("b".into(), Expr::LessThan(Expr::Boolean(true).into(), x()));
+        let mut machine = MachineBuilder::new(stmt, env).build();
+        let failing = Expr::LessThan(Expr::Boolean(true).into(), Expr::Number(3).into());
+        assert_eq!(
+            Outcome::Error(RuntimeError::TypeMismatch(failing)),
+            machine.run()
+        );
+    }
+
+    #[test]
+    fn short_circuit() {
+        let x = || Box::new(Expr::Variable("x".into()));
+        let b = |b| Box::new(Expr::Boolean(b));
+        let assign = || Box::new(Expr::AssignExpr("x".into(), Expr::Number(1).into()));
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(0));
+        let cases = [
+            (Expr::And(b(false), assign()), "false && (x = 1)", false, 0),
+            (
+                Expr::And(b(true), Expr::Equal(assign(), x()).into()),
+                "true && (x = 1) == x",
+                true,
+                1,
+            ),
+            (Expr::Or(b(true), assign()), "true || (x = 1)", true, 0),
+            (
+                Expr::Or(b(false), Expr::Equal(assign(), x()).into()),
+                "false || (x = 1) == x",
+                true,
+                1,
+            ),
+        ];
+        for (expr, source, result, x) in cases {
+            assert_eq!(source, expr.to_string());
+            let env = reduce_fully(Stmt::Assign("b".into(), expr), env.clone());
+            assert_eq!(Ok(&Expr::Boolean(result)), env.get("b"), "{}", source);
+            assert_eq!(Ok(&Expr::Number(x)), env.get("x"), "{}", source);
+        }
+
+        // The left operand is reduced first, then the rule fires without
+        // looking at the right one.
+        let stmt = Stmt::Assign(
+            "b".into(),
+            Expr::Or(Expr::LessThan(x(), Expr::Number(1).into()).into(), assign()),
+        );
+        assert_eq!(4..5, stmt.redex_span());
+        let (stmt, env) = stmt.reduce(&env);
+        assert_eq!(4..9, stmt.redex_span());
+        let (stmt, env) = stmt.reduce(&env);
+        assert_eq!("b = true || (x = 1)", stmt.to_string());
+        assert_eq!(4..19, stmt.redex_span());
+        assert!(stmt.explain().contains("the right one is skipped"));
+        let (stmt, _) = stmt.reduce(&env);
+        assert_eq!("b = true", stmt.to_string());
+
+        // b = 1 && true
+        let stmt = Stmt::Assign("b".into(), Expr::And(Expr::Number(1).into(), b(true)));
+        assert_eq!(
+            Some(RuntimeError::TypeMismatch(Expr::And(
+                Expr::Number(1).into(),
+                b(true)
+            ))),
+            stmt.error(&env)
+        );
+
+        // b = true && x + 2: the right operand is reduced in place and
+        // must turn out to be a boolean too.
+        let stmt = Stmt::Assign(
+            "b".into(),
+            Expr::And(b(true), Expr::Add(x(), Expr::Number(2).into()).into()),
+        );
+        let mut machine = MachineBuilder::new(stmt, env.into_owned()).build();
+        let failing = Expr::And(b(true), Expr::Number(2).into());
+        assert_eq!(
+            Outcome::Error(RuntimeError::TypeMismatch(failing.clone())),
+            machine.run()
+        );
+        assert_eq!(Stmt::Assign("b".into(), failing), machine.program());
+    }
+
+    #[test]
+    fn not() {
+        let x = || Box::new(Expr::Variable("x".into()));
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(3));
+
+        // b = !(x < 5)
+        let stmt = Stmt::Assign(
+            "b".into(),
+            Expr::Not(Expr::LessThan(x(), Expr::Number(5).into()).into()),
+        );
+        assert_eq!("b = !(x < 5)", stmt.to_string());
+        assert_eq!(6..7, stmt.redex_span());
+        let (stmt, env) = stmt.reduce(&env);
+        let (stmt, env) = stmt.reduce(&env);
+        assert_eq!("b = !true", stmt.to_string());
+        assert_eq!(4..9, stmt.redex_span());
+        assert!(stmt.explain().contains("so we flip it"));
+        let env = reduce_fully(stmt, env.into_owned());
+        assert_eq!(Ok(&Expr::Boolean(false)), env.get("b"));
+
+        // b = !x
+        let stmt = Stmt::Assign("b".into(), Expr::Not(x()));
+        let mut machine = MachineBuilder::new(stmt, env).build();
+        assert_eq!(
+            Outcome::Error(RuntimeError::TypeMismatch(Expr::Not(
+                Expr::Number(3).into()
+            ))),
+            machine.run()
+        );
+    }
+
+    #[test]
+    fn environment_load() {
+        let path = std::env::temp_dir().join(format!("uc-env-{}.env", process::id()));
+        std::fs::write(&path, "# loop bound\nn = 3\nx = 0\n").unwrap();
+        let env = Environment::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let env = env.unwrap();
+        assert_eq!("{n = 3, x = 0}", env.to_string());
+
+        // while (x < n) { x = x + 1 }
+        let x = || Box::new(Expr::Variable("x".into()));
+        let stmt = Stmt::While {
+            condition: Expr::LessThan(x(), Expr::Variable("n".into()).into()),
+            body: Stmt::Assign("x".into(), Expr::Add(x(), Expr::Number(1).into())).into(),
+        };
+        assert_eq!(Ok(&Expr::Number(3)), reduce_fully(stmt, env).get("x"));
+
+        let error = Environment::load(&path).unwrap_err();
+        assert!(error.starts_with(&path.display().to_string()), "{}", error);
+    }
+
+    #[test]
+    fn non_boolean_condition_is_a_runtime_error() {
+        // if (1) { x = 1 } else { do-nothing }
+        let branch = Stmt::If {
+            condition: Expr::Number(1),
+            consequence: Stmt::Assign("x".into(), Expr::Number(1)).into(),
+            alternative: Stmt::DoNothing.into(),
+        };
+        // while (5) { do-nothing }
+        let forever = Stmt::While {
+            condition: Expr::Number(5),
+            body: Stmt::DoNothing.into(),
+        };
+        for (stmt, condition) in [(branch, 1), (forever, 5)] {
+            let mut trace = vec![];
+            let mut machine = MachineBuilder::new(stmt, Environment::new())
+                .explain()
+                .fold(FoldPolicy::Rules(&[Rule::Assign]))
+                .sink(&mut trace)
+                .build();
+            assert_eq!(
+                Outcome::Error(RuntimeError::TypeMismatch(Expr::Number(condition))),
+                machine.run()
+            );
+        }
+    }
+
+    #[test]
+    fn undefined_variable_is_a_runtime_error() {
+        // x = y + 1
+        let stmt = Stmt::Assign(
+            "x".into(),
+            Expr::Add(Expr::Variable("y".into()).into(), Expr::Number(1).into()),
+        );
+        let mut machine = MachineBuilder::new(stmt.clone(), Environment::new()).build();
+        assert_eq!(
+            Outcome::Error(RuntimeError::UndefinedVariable("y".into())),
+            machine.run()
+        );
+        assert_eq!(stmt, machine.program());
+
+        let env: Environment = vec![("y".to_string(), Expr::Number(2))]
+            .into_iter()
+            .collect();
+        let inner = reduce_fully(stmt, env.child_scope());
+        assert_eq!(Ok(&Expr::Number(3)), inner.get("x"));
+        // `x` was first bound in the inner scope, so it goes with it.
+        assert_eq!(env, inner.parent_scope());
+    }
+
+    #[test]
+    fn division_by_zero_is_a_runtime_error() {
+        // x = 10 / (7 - y)
+        let stmt = Stmt::Assign(
+            "x".into(),
+            Expr::Divide(
+                Expr::Number(10).into(),
+                Expr::Subtract(Expr::Number(7).into(), Expr::Variable("y".into()).into()).into(),
+            ),
+        );
+        let run = |y| {
+            let mut env = Environment::new();
+            env.set("y", Expr::Number(y));
+            let mut machine = MachineBuilder::new(stmt.clone(), env).build();
+            let outcome = machine.run();
+            (
+                outcome,
+                machine.program(),
+                machine.env.get("x").ok().cloned(),
+            )
+        };
+        assert_eq!(
+            (Outcome::Halted, Stmt::DoNothing, Some(Expr::Number(-3))),
+            run(10)
+        );
+
+        let (outcome, program, x) = run(7);
+        let failing = Expr::Divide(Expr::Number(10).into(), Expr::Number(0).into());
+        assert_eq!(
+            Outcome::Error(RuntimeError::DivisionByZero(failing.clone())),
+            outcome
+        );
+        assert_eq!(Stmt::Assign("x".into(), failing), program);
+        assert_eq!(None, x);
+        assert_eq!(
+            None,
+            apply_rule(Rule::Divide, &program, &Environment::new())
+        );
+    }
+
+    #[test]
+    fn fizz_buzz_with_modulo() {
+        let var = |name: &str| Box::new(Expr::Variable(name.into()));
+        let divides = |n| {
+            Expr::LessThan(
+                Expr::Modulo(var("i"), Expr::Number(n).into()).into(),
+                Expr::Number(1).into(),
+            )
+        };
+        let add =
+            |name: &str| Stmt::Assign(name.into(), Expr::Add(var(name), Expr::Number(1).into()));
+        // while (i < 16) { if (i % 15 < 1) { fizz_buzz = fizz_buzz + 1 } else { ... }; i = i + 1 }
+        let stmt = Stmt::While {
+            condition: Expr::LessThan(var("i"), Expr::Number(16).into()),
+            body: Stmt::Sequence {
+                first: Stmt::If {
+                    condition: divides(15),
+                    consequence: add("fizz_buzz").into(),
+                    alternative: Stmt::If {
+                        condition: divides(3),
+                        consequence: add("fizz").into(),
+                        alternative: Stmt::If {
+                            condition: divides(5),
+                            consequence: add("buzz").into(),
+                            alternative: Stmt::DoNothing.into(),
+                        }
+                        .into(),
+                    }
+                    .into(),
+                }
+                .into(),
+                second: add("i").into(),
+            }
+            .into(),
+        };
+        assert!(stmt
+            .to_string()
+            .starts_with("while (i < 16) { if (i % 15 < 1)"));
+        let mut env = Environment::new();
+        for name in ["i", "fizz", "buzz", "fizz_buzz"] {
+            env.set(name, Expr::Number(0));
+        }
+        env.set("i", Expr::Number(1));
+        let env = reduce_fully(stmt, env);
+        assert_eq!(Ok(&Expr::Number(4)), env.get("fizz"));
+        assert_eq!(Ok(&Expr::Number(2)), env.get("buzz"));
+        assert_eq!(Ok(&Expr::Number(1)), env.get("fizz_buzz"));
+
+        let failing = Stmt::Assign(
+            "x".into(),
+            Expr::Modulo(Expr::Number(3).into(), Expr::Number(0).into()),
+        );
+        let mut machine = MachineBuilder::new(failing, Environment::new()).build();
+        assert_eq!(
+            Outcome::Error(RuntimeError::DivisionByZero(Expr::Modulo(
+                Expr::Number(3).into(),
+                Expr::Number(0).into()
+            ))),
+            machine.run()
+        );
+    }
+
+    #[test]
+    fn overflow_is_a_runtime_error() {
+        for overflowing in [
+            Expr::Add(Expr::Number(i64::MAX).into(), Expr::Number(1).into()),
+            Expr::Subtract(Expr::Number(i64::MIN).into(), Expr::Number(1).into()),
+            Expr::Multiply(Expr::Number(i64::MAX).into(), Expr::Number(2).into()),
+            Expr::Divide(Expr::Number(i64::MIN).into(), Expr::Number(-1).into()),
+            Expr::Modulo(Expr::Number(i64::MIN).into(), Expr::Number(-1).into()),
+            Expr::PreIncrement("n".into()),
+        ] {
+            let stmt = Stmt::Assign("x".into(), overflowing.clone());
+            let env: Environment = vec![("n".to_string(), Expr::Number(i64::MAX))]
+                .into_iter()
+                .collect();
+            let mut machine = MachineBuilder::new(stmt, env).build();
+            assert_eq!(
+                Outcome::Error(RuntimeError::Overflow(overflowing)),
+                machine.run()
+            );
+        }
+    }
+
+    #[test]
+    fn profile_counts_environment_clones() {
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(0));
+        env.set("y", Expr::Number(0));
+        let mut machine = MachineBuilder::new(count_to(3), env).build();
+        machine.run();
+        let profile = machine.profile;
+        assert_eq!(machine.steps, profile.steps);
+        // Only the three `x = x + 1` steps copy the environment.
+        assert_eq!(3, profile.environment_clones);
+        assert_eq!(6, profile.bindings_cloned);
+        assert_eq!(
+            format!(
+                "{} steps, 3 environment clones (6 bindings copied)",
+                profile.steps
+            ),
+            profile.to_string()
+        );
+    }
+
+    #[test]
+    fn zipper_matches_reduction_from_the_root() {
+        // x = 0; (while (x < 3) { x = x + 1 }; (y = x; z = y + 1))
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign("x".into(), Expr::Number(0)).into(),
+            second: Stmt::Sequence {
+                first: count_to(3).into(),
+                second: Stmt::Sequence {
+                    first: Stmt::Assign("y".into(), Expr::Variable("x".into())).into(),
+                    second: Stmt::Assign(
+                        "z".into(),
+                        Expr::Add(Expr::Variable("y".into()).into(), Expr::Number(1).into()),
+                    )
+                    .into(),
+                }
+                .into(),
+            }
+            .into(),
+        };
+
+        let mut expected = vec![];
+        let (mut current, mut env) = (stmt.clone(), Environment::new());
+        while current.is_reducible() {
+            let rule = current.rule();
+            let (next, next_env) = current.reduce(&env);
+            let next_env = next_env.into_owned();
+            expected.push((next.clone(), next_env.clone(), rule));
+            current = next;
+            env = next_env;
+        }
+
+        let mut actual = vec![];
+        let mut machine = MachineBuilder::new(stmt, Environment::new())
+            .hook(|stmt, env, rule| {
+                actual.push((stmt.clone(), env.clone(), rule));
+                ControlFlow::Continue(())
+            })
+            .build();
+        assert_eq!(Outcome::Halted, machine.run());
+        assert_eq!(Stmt::DoNothing, machine.program());
+        drop(machine);
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn on_step_reports_rules_and_aborts() {
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(0));
+        let mut rules = vec![];
+        let mut machine = MachineBuilder::new(count_to(1), env).build();
+        machine.on_step(|_, env, rule| {
+            rules.push(rule);
+            if env.get("x") == Ok(&Expr::Number(1)) {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(Outcome::Aborted, machine.run());
+        drop(machine);
+        assert_eq!(
+            vec![
+                Rule::While,
+                Rule::Variable,
+                Rule::LessThan,
+                Rule::IfTrue,
+                Rule::Variable,
+                Rule::Add,
+                Rule::Assign,
+            ],
+            rules
+        );
+    }
+
+    #[test]
+    fn check_invariant_verifies_looping_program() {
+        // while (x < 5) { x = x * 1 } never terminates for x < 5, but only
+        // visits finitely many configurations.
+        let stmt = Stmt::While {
+            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(5).into()),
+            body: Stmt::Assign(
+                "x".into(),
+                Expr::Multiply(Expr::Variable("x".into()).into(), Expr::Number(1).into()),
+            )
+            .into(),
+        };
+        let values: Vec<_> = (0..8).map(Expr::Number).collect();
+        let envs = all_environments(&["x"], &values);
+        let result = check_invariant(&stmt, envs, |env| env["x"] != Expr::Number(8), 1000);
+        assert!(matches!(result, ModelCheckResult::Verified { .. }));
+    }
+
+    #[test]
+    fn check_invariant_reports_counterexample() {
+        // y = x + x, checked against y < 5 for x in 0..=3
+        let stmt = Stmt::Assign(
+            "y".into(),
+            Expr::Add(
+                Expr::Variable("x".into()).into(),
+                Expr::Variable("x".into()).into(),
+            ),
+        );
+        let values: Vec<_> = (0..4).map(Expr::Number).collect();
+        let envs = all_environments(&["x", "y"], &values);
+        let invariant = |env: &Environment| match env["y"] {
+            Expr::Number(y) => y < 5,
+            _ => false,
+        };
+        match check_invariant(&stmt, envs, invariant, 1000) {
+            ModelCheckResult::Violated { trace } => {
+                assert_eq!(stmt, trace[0].0);
+                assert_eq!(Expr::Number(3), trace[0].1["x"]);
+                assert_eq!(Stmt::DoNothing, trace.last().unwrap().0);
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+
+        let stmt = count_to(100);
+        let envs = all_environments(&["x"], &[Expr::Number(0)]);
+        assert_eq!(
+            ModelCheckResult::StateLimitReached,
+            check_invariant(&stmt, envs, |_| true, 10)
+        );
+
+        // x = random(3) reaches x = 2 on one of its branches.
+        let stmt = Stmt::Assign("x".into(), Expr::Random(3));
+        let envs = all_environments(&["x"], &[Expr::Number(0)]);
+        match check_invariant(&stmt, envs, |env| env["x"] != Expr::Number(2), 1000) {
+            ModelCheckResult::Violated { trace } => {
+                assert_eq!("x = 2", trace[1].0.to_string());
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+        let envs = all_environments(&["x"], &[Expr::Number(0)]);
+        assert_eq!(None, apply_rule(Rule::Random, &stmt, &envs[0]));
+    }
+
+    #[test]
+    fn rule_definitions() {
+        assert_eq!(
+            "n = n1 + n2\n-------------------------- (Add)\n<n1 + n2, env> -> <n, env>",
+            Rule::Add.definition().to_string()
+        );
+        for rule in Rule::ALL.iter() {
+            assert_eq!(format!("{:?}", rule), rule.definition().name);
+        }
+
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(0));
+        let stmt = count_to(1);
+        assert_eq!(None, apply_rule(Rule::IfTrue, &stmt, &env));
+        let (stmt, _) = apply_rule(Rule::While, &stmt, &env).unwrap();
+        let (stmt, _) = apply_rule(Rule::Variable, &stmt, &env).unwrap();
+        let (stmt, _) = apply_rule(Rule::LessThan, &stmt, &env).unwrap();
+        assert_eq!(Rule::IfTrue, stmt.rule());
+        assert_eq!(None, apply_rule(Rule::Sequence, &Stmt::DoNothing, &env));
+    }
+
+    #[test]
+    fn redex_spans() {
+        let var = |name: &str| Box::new(Expr::Variable(name.into()));
+        // if (x < 1 + y) { z = (w = 2 * x) } else { do-nothing }; while (x < 3) { x = x + 1 }
+        let stmt = Stmt::Sequence {
+            first: Stmt::If {
+                condition: Expr::LessThan(
+                    Expr::Number(0).into(),
+                    Expr::Add(Expr::Number(1).into(), var("y")).into(),
+                ),
+                consequence: Stmt::Assign(
+                    "z".into(),
+                    Expr::AssignExpr(
+                        "w".into(),
+                        Expr::Multiply(Expr::Number(2).into(), var("x")).into(),
+                    ),
+                )
+                .into(),
+                alternative: Stmt::DoNothing.into(),
+            }
+            .into(),
+            second: Stmt::While {
+                condition: Expr::LessThan(var("x"), Expr::Number(3).into()),
+                body: Stmt::Assign("x".into(), Expr::Add(var("x"), Expr::Number(1).into())).into(),
+            }
+            .into(),
+        };
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(1));
+        env.set("y", Expr::Number(5));
+
+        let mut machine = MachineBuilder::new(stmt, env).build();
+        let mut redexes = vec![];
+        while let Some((path, span)) = machine.redex() {
+            redexes.push((path, machine.program().to_string()[span].to_string()));
+            let _ = machine.step();
+        }
+        let expected: Vec<(Vec<usize>, &str)> = vec![
+            (vec![0, 0, 1, 1], "y"),
+            (vec![0, 0, 1], "1 + 5"),
+            (vec![0, 0], "0 < 6"),
+            (vec![0], "if (true) { z = (w = 2 * x) } else { do-nothing }"),
+            (vec![0, 0, 0, 1], "x"),
+            (vec![0, 0, 0], "2 * 1"),
+            (vec![0, 0], "(w = 2)"),
+            (vec![0], "z = 2"),
+            (vec![], "do-nothing; while (x < 3) { x = x + 1 }"),
+            (vec![], "while (x < 3) { x = x + 1 }"),
+        ];
+        assert_eq!(
+            expected,
+            redexes
+                .iter()
+                .take(expected.len())
+                .map(|(path, text)| (path.clone(), text.as_str()))
+                .collect::<Vec<_>>()
+        );
+        // The unrolled loop puts the redex in the condition and the body.
+        assert!(redexes.contains(&(vec![0, 0], "x".to_string())));
+        assert!(redexes.contains(&(vec![0, 0, 0], "x".to_string())));
+        assert_eq!(None, machine.redex());
+    }
+
+    #[test]
+    fn colored_trace() {
+        let stmt = Stmt::Sequence {
+            first: Stmt::Assign(
+                "x".into(),
+                Expr::Add(Expr::Number(-1).into(), Expr::Variable("y".into()).into()),
+            )
+            .into(),
+            second: Stmt::If {
+                condition: Expr::Boolean(true),
+                consequence: Stmt::DoNothing.into(),
+                alternative: Stmt::DoNothing.into(),
+            }
+            .into(),
+        };
+        let mut env = Environment::new();
+        env.set("y", Expr::Number(2));
+        assert_eq!(
+            "x = \x1b[33m-1\x1b[39m + \x1b[7my\x1b[27m; \x1b[1;34mif\x1b[22;39m \
+             (\x1b[33mtrue\x1b[39m) { \x1b[1;34mdo-nothing\x1b[22;39m } \
+             \x1b[1;34melse\x1b[22;39m { \x1b[1;34mdo-nothing\x1b[22;39m }, {\"y\": <<2>>}",
+            colored_configuration(&stmt, &env, None)
+        );
+
+        let previous = env.clone();
+        env.set("y", Expr::Number(3));
+        assert_eq!(
+            "\x1b[1;34mdo-nothing\x1b[22;39m, {\x1b[32m\"y\": <<3>>\x1b[39m}",
+            colored_configuration(&Stmt::DoNothing, &env, Some(&previous))
+        );
+    }
+
+    #[test]
+    fn folded_trace() {
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(0));
+        let trace = |policy| {
+            let mut out = vec![];
+            let mut machine = MachineBuilder::new(count_to(3), env.clone())
+                .fold(policy)
+                .sink(&mut out)
+                .build();
+            machine.run();
+            let folds: Vec<Vec<String>> = (0..machine.folds.len())
+                .map(|fold| machine.expand(fold).to_vec())
+                .collect();
+            drop(machine);
+            (String::from_utf8(out).unwrap(), folds)
+        };
+
+        let (out, folds) = trace(FoldPolicy::Rules(&[Rule::While]));
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(2 * 4 + 1, lines.len());
+        assert_eq!("while (x < 3) { x = x + 1 }, {\"x\": <<0>>}", lines[0]);
+        assert_eq!("  ... 7 steps folded (#0)", lines[1]);
+        assert_eq!("while (x < 3) { x = x + 1 }, {\"x\": <<1>>}", lines[2]);
+        assert_eq!("do-nothing, {\"x\": <<3>>}", lines[lines.len() - 1]);
+        assert_eq!(4, folds.len());
+
+        let full: String = {
+            let mut out = vec![];
+            MachineBuilder::new(count_to(3), env.clone())
+                .sink(&mut out)
+                .build()
+                .run();
+            String::from_utf8(out).unwrap()
+        };
+        let mut expanded = vec![];
+        for line in out.lines() {
+            match line.strip_prefix("  ... ") {
+                Some(fold) => {
+                    let id: usize = fold[fold.find('#').unwrap() + 1..fold.len() - 1]
+                        .parse()
+                        .unwrap();
+                    expanded.extend(folds[id].iter().cloned());
+                }
+                None => expanded.push(line.to_string()),
+            }
+        }
+        assert_eq!(full.lines().collect::<Vec<_>>(), expanded);
+
+        // The first line, one per increment and the last.
+        let (out, _) = trace(FoldPolicy::EnvironmentChanges);
+        let shown: Vec<_> = out.lines().filter(|line| !line.starts_with("  ")).collect();
+        assert_eq!(1 + 3 + 1, shown.len());
+    }
+
+    #[test]
+    fn tree_trace() {
+        // x = 1 + 2 * 3
+        let stmt = Stmt::Assign(
+            "x".into(),
+            Expr::Add(
+                Expr::Number(1).into(),
+                Expr::Multiply(Expr::Number(2).into(), Expr::Number(3).into()).into(),
+            ),
+        );
+        let mut out = vec![];
+        MachineBuilder::new(stmt, Environment::new())
+            .tree()
+            .sink(&mut out)
+            .build()
+            .run();
+        assert_eq!(
+            "  x =\n\
+             \x20   +\n\
+             \x20     1\n\
+             \x20     *\n\
+             \x20       2\n\
+             \x20       3\n\
+             \x20 {}\n\
+             \x20 x =\n\
+             \x20   +\n\
+             \x20     1\n\
+             *     6\n\
+             \x20 {}\n\
+             \x20 x =\n\
+             *   7\n\
+             \x20 {}\n\
+             * do-nothing\n\
+             \x20 {\"x\": <<7>>}\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn explain_steps() {
+        let mut env = Environment::new();
+        env.set("x", Expr::Number(0));
+        let mut trace = vec![];
+        let mut machine = MachineBuilder::new(count_to(1), env)
+            .explain()
+            .sink(&mut trace)
+            .build();
+        assert_eq!(Outcome::Halted, machine.run());
+        drop(machine);
+        let trace = String::from_utf8(trace).unwrap();
+        let explanations: Vec<&str> = trace
+            .lines()
+            .filter_map(|line| line.strip_prefix("  because "))
+            .collect();
+        assert_eq!(
+            vec![
+                "we unfold the loop into an if that runs the body once and then tries the \
+                 loop again (While)",
+                "the condition is reducible, so we reduce it first; \
+                 the left operand of < is reducible, so we reduce it first; \
+                 we look up the value of x in the environment (Variable)",
+                "the condition is reducible, so we reduce it first; \
+                 both operands of < are numbers, so we compare them (LessThan)",
+                "the condition is true, so we continue with the consequence (IfTrue)",
+                "the right-hand side of x = ... is reducible, so we reduce it first; \
+                 the left operand of + is reducible, so we reduce it first; \
+                 we look up the value of x in the environment (Variable)",
+                "the right-hand side of x = ... is reducible, so we reduce it first; \
+                 both operands of + are numbers, so we add them (Add)",
+                "the right-hand side is a value, so we store it in x and the assignment \
+                 is done (Assign)",
+                "the first statement is finished, so we move on to the second (Sequence)",
+            ],
+            explanations[..8]
+        );
+        assert_eq!(explanations.len(), trace.lines().count() / 2);
+    }
+
+    #[test]
+    fn termination_proofs() {
+        let x = || Box::new(Expr::Variable("x".into()));
+        let n = |n| Box::new(Expr::Number(n));
+        let assign_x = |expr| Stmt::Assign("x".into(), expr);
+        let proof = |stmt: &Stmt| {
+            stmt.termination_proofs()
+                .into_iter()
+                .map(|(_, rank)| rank.map(|rank| rank.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![Some("3 - x".to_string())], proof(&count_to(3)));
+
+        // while (2 * x < n + 1) { if (x < 0) { x = x + 2 } else { x = x + 1; y = y * 2 } }
+        let stmt = Stmt::While {
+            condition: Expr::LessThan(
+                Expr::Multiply(n(2), x()).into(),
+                Expr::Add(Expr::Variable("n".into()).into(), n(1)).into(),
+            ),
+            body: Stmt::If {
+                condition: Expr::LessThan(x(), n(0)),
+                consequence: assign_x(Expr::Add(x(), n(2))).into(),
+                alternative: Stmt::Sequence {
+                    first: assign_x(Expr::Add(x(), n(1))).into(),
+                    second: Stmt::Assign(
+                        "y".into(),
+                        Expr::Multiply(Expr::Variable("y".into()).into(), n(2)),
+                    )
+                    .into(),
+                }
+                .into(),
+            }
+            .into(),
+        };
+        assert_eq!(vec![Some("1 + n - 2x".to_string())], proof(&stmt));
+
+        // One path does not make progress.
+        let stalls = Stmt::While {
+            condition: Expr::LessThan(x(), n(3)),
+            body: Stmt::If {
+                condition: Expr::LessThan(x(), n(0)),
+                consequence: assign_x(Expr::Add(x(), n(1))).into(),
+                alternative: Stmt::DoNothing.into(),
+            }
+            .into(),
+        };
+        assert_eq!(vec![None], proof(&stalls));
+
+        // while (x < i64::MIN) { x = x + 1 }: the rank's constant overflows.
+        let overflowing = Stmt::While {
+            condition: Expr::LessThan(x(), n(i64::MIN)),
+            body: assign_x(Expr::Add(x(), n(1))).into(),
+        };
+        assert_eq!(vec![None], proof(&overflowing));
+
+        // Non-linear updates and nested loops are out of reach; the inner
+        // loop is still proved on its own.
+        let squares = Stmt::While {
+            condition: Expr::LessThan(x(), n(100)),
+            body: assign_x(Expr::Multiply(x(), x())).into(),
+        };
+        assert_eq!(vec![None], proof(&squares));
+        let nested = Stmt::While {
+            condition: Expr::LessThan(x(), n(100)),
+            body: count_to(3).into(),
+        };
+        assert_eq!(vec![None, Some("3 - x".to_string())], proof(&nested));
+    }
+
+    #[test]
+    fn termination_warnings() {
+        let forever = Stmt::While {
+            condition: Expr::LessThan(Expr::Number(1).into(), Expr::Number(2).into()),
+            body: Stmt::DoNothing.into(),
+        };
+        assert_eq!(
+            vec![TerminationWarning::AlwaysTrue(forever.clone())],
+            forever.termination_warnings()
+        );
+        let machine = MachineBuilder::new(forever.clone(), Environment::new()).build();
+        assert_eq!(forever.termination_warnings(), machine.warnings);
+
+        let unmodified = Stmt::While {
+            condition: Expr::LessThan(Expr::Variable("x".into()).into(), Expr::Number(5).into()),
+            body: Stmt::Assign(
+                "y".into(),
+                Expr::Add(Expr::Variable("x".into()).into(), Expr::Number(1).into()),
+            )
+            .into(),
+        };
+        assert_eq!(
+            vec![TerminationWarning::ConditionNeverModified(
+                unmodified.clone()
+            )],
+            unmodified.termination_warnings()
+        );
+
+        let terminating = Stmt::Sequence {
+            first: Stmt::While {
+                condition: Expr::Boolean(false),
+                body: Stmt::DoNothing.into(),
+            }
+            .into(),
+            second: Stmt::While {
+                condition: Expr::LessThan(
+                    Expr::Variable("x".into()).into(),
+                    Expr::Number(5).into(),
+                ),
+                body: Stmt::Assign(
+                    "x".into(),
+                    Expr::Add(Expr::Variable("x".into()).into(), Expr::Number(1).into()),
+                )
+                .into(),
+            }
+            .into(),
+        };
+        assert!(terminating.termination_warnings().is_empty());
+
+        let counting = Stmt::While {
+            condition: Expr::LessThan(
+                Expr::PostIncrement("x".into()).into(),
+                Expr::Number(5).into(),
+            ),
+            body: Stmt::DoNothing.into(),
+        };
+        assert!(counting.termination_warnings().is_empty());
+
+        let failing = Stmt::While {
+            condition: Expr::LessThan(
+                Expr::Divide(Expr::Number(1).into(), Expr::Number(0).into()).into(),
+                Expr::Number(1).into(),
+            ),
+            body: Stmt::DoNothing.into(),
+        };
+        let mut machine = MachineBuilder::new(failing, Environment::new()).build();
+        assert!(machine.warnings.is_empty());
+        assert_eq!(
+            Outcome::Error(RuntimeError::DivisionByZero(Expr::Divide(
+                Expr::Number(1).into(),
+                Expr::Number(0).into()
+            ))),
+            machine.run()
+        );
+    }
 }